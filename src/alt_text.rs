@@ -0,0 +1,120 @@
+//! Text descriptions of generated images for `--a11y`
+//!
+//! Built offline from what is known about the image: any description the
+//! model returned, the prompt, its dimensions and its dominant colors.
+
+use std::collections::HashMap;
+
+/// Longest side of the copy sampled for colors
+const SAMPLE_SIZE: u32 = 64;
+/// Share of pixels a color needs to be mentioned
+const MIN_COLOR_SHARE: f32 = 0.12;
+
+/// Describe an image in a sentence or two suitable for a screen reader
+pub fn describe(image_data: &[u8], prompt: &str, model_text: Option<&str>) -> String {
+    let mut parts = Vec::new();
+
+    match image::load_from_memory(image_data) {
+        Ok(img) => {
+            let (width, height) = (img.width(), img.height());
+            let shape = match width.cmp(&height) {
+                std::cmp::Ordering::Greater => "landscape",
+                std::cmp::Ordering::Less => "portrait",
+                std::cmp::Ordering::Equal => "square",
+            };
+            parts.push(format!(
+                "A {} image, {} by {} pixels.",
+                shape, width, height
+            ));
+
+            let rgb = img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+            let colors = dominant_colors(rgb.pixels().map(|p| p.0));
+            if !colors.is_empty() {
+                parts.push(format!("Mostly {}.", join_words(&colors)));
+            }
+        }
+        Err(_) => parts.push("An image that could not be decoded for description.".to_string()),
+    }
+
+    match model_text.map(str::trim).filter(|text| !text.is_empty()) {
+        Some(text) => parts.push(format!("Model description: {}", text)),
+        None => parts.push(format!("Generated from the prompt: {}", prompt)),
+    }
+
+    parts.join(" ")
+}
+
+/// Named colors covering at least [`MIN_COLOR_SHARE`] of the pixels, most common first
+fn dominant_colors(pixels: impl Iterator<Item = [u8; 3]>) -> Vec<&'static str> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    let mut total = 0;
+    for pixel in pixels {
+        *counts.entry(color_name(pixel)).or_default() += 1;
+        total += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+        .into_iter()
+        .filter(|&(_, count)| count as f32 / total.max(1) as f32 >= MIN_COLOR_SHARE)
+        .take(3)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Coarse everyday color name for an RGB pixel
+fn color_name([r, g, b]: [u8; 3]) -> &'static str {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+
+    if chroma < 0.12 {
+        return match lightness {
+            l if l < 0.2 => "black",
+            l if l > 0.85 => "white",
+            _ => "gray",
+        };
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+
+    match hue {
+        h if !(15.0..345.0).contains(&h) => {
+            if lightness > 0.7 {
+                "pink"
+            } else {
+                "red"
+            }
+        }
+        h if h < 45.0 => {
+            if lightness < 0.35 {
+                "brown"
+            } else {
+                "orange"
+            }
+        }
+        h if h < 70.0 => "yellow",
+        h if h < 165.0 => "green",
+        h if h < 255.0 => "blue",
+        h if h < 290.0 => "purple",
+        _ => "pink",
+    }
+}
+
+/// "a", "a and b", "a, b and c"
+fn join_words(words: &[&str]) -> String {
+    match words {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
@@ -0,0 +1,58 @@
+//! API keys stored in the OS keychain (macOS Keychain, Windows Credential
+//! Manager, Secret Service on Linux), one entry per provider
+
+use crate::error::{ImagoError, Result};
+use crate::provider::Provider;
+use std::io::{self, BufRead, IsTerminal};
+
+const SERVICE: &str = "imago";
+
+fn entry(provider: &Provider) -> Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, provider.name())
+        .map_err(|e| ImagoError::AuthError(format!("Keychain unavailable: {}", e)))
+}
+
+/// Read a key without echoing it (or from a pipe), so it stays out of shell history
+pub fn read_key(provider: &Provider) -> Result<String> {
+    let key = if io::stdin().is_terminal() {
+        // Ctrl+C interrupts the read; rpassword turns echo back on itself
+        rpassword::prompt_password(format!("{} API key: ", provider.name())).map_err(|e| match e
+            .kind()
+        {
+            io::ErrorKind::Interrupted => ImagoError::Interrupted,
+            _ => e.into(),
+        })?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line
+    };
+    let key = key.trim().to_string();
+    if key.is_empty() {
+        return Err(ImagoError::AuthError("No key entered".to_string()));
+    }
+    Ok(key)
+}
+
+pub fn set_key(provider: &Provider, key: &str) -> Result<()> {
+    entry(provider)?
+        .set_password(key)
+        .map_err(|e| ImagoError::AuthError(format!("Could not store key: {}", e)))
+}
+
+/// Key stored for `provider`, if any; an unavailable keychain counts as none
+pub fn get_key(provider: &Provider) -> Option<String> {
+    entry(provider).ok()?.get_password().ok()
+}
+
+/// Remove the stored key, returning whether there was one
+pub fn delete_key(provider: &Provider) -> Result<bool> {
+    match entry(provider)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(ImagoError::AuthError(format!(
+            "Could not remove key: {}",
+            e
+        ))),
+    }
+}
@@ -0,0 +1,310 @@
+use crate::error::{ImagoError, Result};
+use crate::http;
+use crate::provider::{
+    parse_dimensions, request_id, retry_after, GeneratedImage, GenerationOptions, GenerationResult,
+};
+use crate::session;
+use crate::sigv4::{self, AwsCredentials};
+use base64::prelude::*;
+use chrono::Utc;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::env;
+
+const DEFAULT_REGION: &str = "us-east-1";
+const SERVICE: &str = "bedrock";
+
+/// AWS Bedrock runtime client for Titan/Nova Canvas and Stability image models
+pub struct BedrockClient {
+    client: Client,
+    access_key_id: String,
+    model: String,
+}
+
+/// Titan Image Generator / Nova Canvas response
+#[derive(Debug, Deserialize)]
+struct TitanResponse {
+    images: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+/// Stability (SD3 / Ultra / Core) on Bedrock response
+#[derive(Debug, Deserialize)]
+struct StabilityResponse {
+    images: Vec<String>,
+    seeds: Option<Vec<u64>>,
+    finish_reasons: Option<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl BedrockClient {
+    /// Create a new Bedrock client
+    ///
+    /// The access key id is passed like any other API key; the secret key,
+    /// session token and region are read from the standard AWS environment
+    /// variables when generating.
+    pub fn new(access_key_id: String, model: String, client: Client) -> Self {
+        Self {
+            client,
+            access_key_id,
+            model,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Generate images from a text prompt
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        if self.model.starts_with("stability.") {
+            // Stability models on Bedrock return one image per invocation
+            let mut result = self.generate_stability(prompt, options).await?;
+            for i in 1..options.count {
+                result.merge(self.generate_stability(prompt, &options.variant(i)).await?);
+            }
+            Ok(result)
+        } else {
+            self.generate_titan(prompt, options).await
+        }
+    }
+
+    async fn generate_titan(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let mut text_params = Map::new();
+        text_params.insert("text".to_string(), json!(prompt));
+        if let Some(negative) = options.negative_prompt.as_deref() {
+            text_params.insert("negativeText".to_string(), json!(negative));
+        }
+
+        let mut config = Map::new();
+        config.insert("numberOfImages".to_string(), json!(options.count));
+        if let Some(size) = options.size.as_deref() {
+            let (width, height) = parse_dimensions(size).ok_or_else(|| {
+                ImagoError::InvalidArgument(format!(
+                    "Titan expects --size as WIDTHxHEIGHT (e.g. 1024x1024), got '{}'",
+                    size
+                ))
+            })?;
+            config.insert("width".to_string(), json!(width));
+            config.insert("height".to_string(), json!(height));
+        }
+        if let Some(cfg_scale) = options.cfg_scale {
+            config.insert("cfgScale".to_string(), json!(cfg_scale));
+        }
+        if let Some(seed) = options.seed {
+            config.insert("seed".to_string(), json!(seed));
+        }
+        if let Some(quality) = options.quality.as_deref() {
+            config.insert("quality".to_string(), json!(quality));
+        }
+
+        let body = json!({
+            "taskType": "TEXT_IMAGE",
+            "textToImageParams": text_params,
+            "imageGenerationConfig": config,
+        });
+
+        let response_text = self.invoke(&body).await?;
+        tracing::info_span!("decode")
+            .in_scope(|| normalize_titan(&self.model, &response_text, options.seed))
+    }
+
+    async fn generate_stability(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let mut body = Map::new();
+        body.insert("prompt".to_string(), json!(prompt));
+        body.insert("output_format".to_string(), json!("png"));
+        if let Some(size) = options.size.as_deref() {
+            body.insert("aspect_ratio".to_string(), json!(size));
+        }
+        if let Some(seed) = options.seed {
+            body.insert("seed".to_string(), json!(seed));
+        }
+        if let Some(negative) = options.negative_prompt.as_deref() {
+            body.insert("negative_prompt".to_string(), json!(negative));
+        }
+
+        let response_text = self.invoke(&Value::Object(body)).await?;
+        tracing::info_span!("decode")
+            .in_scope(|| normalize_stability(&self.model, &response_text, options.seed))
+    }
+
+    /// Send a SigV4-signed InvokeModel request and return the response body
+    async fn invoke(&self, body: &Value) -> Result<String> {
+        // A replayed request is never sent, so the signature doesn't matter
+        let secret_access_key = match env::var("AWS_SECRET_ACCESS_KEY") {
+            Ok(key) => key,
+            Err(_) if session::replaying() => String::new(),
+            Err(_) => return Err(ImagoError::MissingApiKey("AWS_SECRET_ACCESS_KEY")),
+        };
+        let credentials = AwsCredentials {
+            access_key_id: self.access_key_id.clone(),
+            secret_access_key,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        };
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+
+        let host = format!("bedrock-runtime.{}.amazonaws.com", region);
+        let path = format!("/model/{}/invoke", sigv4::uri_encode(&self.model));
+        let payload = serde_json::to_vec(body)?;
+
+        let signed = sigv4::sign_post(
+            &credentials,
+            &region,
+            SERVICE,
+            &host,
+            &path,
+            &payload,
+            Utc::now(),
+        );
+
+        let mut request = self
+            .client
+            .post(format!("https://{}{}", host, path))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header("x-amz-date", &signed.amz_date)
+            .header(reqwest::header::AUTHORIZATION, &signed.authorization);
+        if let Some(token) = &signed.session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+
+        let reply = http::send(
+            "bedrock",
+            request.body(payload),
+            Some(body),
+            &self.access_key_id,
+        )
+        .await?;
+        let status = reply.status;
+        let retry_after = retry_after(&reply.headers);
+        let request_id = request_id(&reply.headers);
+        let response_text = reply.text();
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<ErrorResponse>(&response_text)
+                .map(|e| e.message)
+                .unwrap_or(response_text);
+            return Err(ImagoError::ApiError {
+                status: status.as_u16(),
+                message,
+                retry_after,
+                request_id,
+            });
+        }
+
+        Ok(response_text)
+    }
+}
+
+/// Map a Titan / Nova Canvas response onto a [`GenerationResult`]
+///
+/// These models don't echo the seed back, so the requested one is recorded.
+fn normalize_titan(
+    model: &str,
+    response_text: &str,
+    seed: Option<u64>,
+) -> Result<GenerationResult> {
+    let parsed: TitanResponse =
+        serde_json::from_str(response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })?;
+
+    if let Some(error) = parsed.error {
+        return Err(ImagoError::ApiResponseError(error));
+    }
+
+    let mut result = GenerationResult::new("bedrock", model);
+    for encoded in parsed.images.unwrap_or_default() {
+        result.images.push(GeneratedImage {
+            data: BASE64_STANDARD.decode(&encoded)?,
+            text: None,
+            seed,
+        });
+    }
+
+    if result.images.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+/// Map a Stability-on-Bedrock response onto a [`GenerationResult`]
+fn normalize_stability(
+    model: &str,
+    response_text: &str,
+    seed: Option<u64>,
+) -> Result<GenerationResult> {
+    let parsed: StabilityResponse =
+        serde_json::from_str(response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })?;
+
+    if let Some(reason) = parsed.finish_reasons.iter().flatten().flatten().next() {
+        return Err(ImagoError::SafetyFilter(reason.clone()));
+    }
+
+    let mut result = GenerationResult::new("bedrock", model);
+    let seeds = parsed.seeds.unwrap_or_default();
+    for (i, encoded) in parsed.images.iter().enumerate() {
+        result.images.push(GeneratedImage {
+            data: BASE64_STANDARD.decode(encoded)?,
+            text: None,
+            seed: seeds.get(i).copied().or(seed),
+        });
+    }
+
+    if result.images.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_titan_response() {
+        let result = normalize_titan(
+            "amazon.titan-image-generator-v2:0",
+            &fixture("bedrock/titan.json"),
+            Some(7),
+        )
+        .unwrap();
+        assert_golden("bedrock/titan.golden.json", &result);
+    }
+
+    #[test]
+    fn normalizes_stability_response() {
+        let result = normalize_stability(
+            "stability.sd3-large-v1:0",
+            &fixture("bedrock/stability.json"),
+            None,
+        )
+        .unwrap();
+        assert_golden("bedrock/stability.golden.json", &result);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::error::{ImagoError, Result};
+use crate::provider::{GenerationOptions, GenerationResult};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const REDIS_KEY_PREFIX: &str = "imago:cache:";
+pub const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Retention under `--low-bandwidth`, where a re-download costs more than disk
+pub const LOW_BANDWIDTH_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Available response cache backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CacheKind {
+    /// Files under the user cache directory
+    Disk,
+    /// A shared Redis server, for teams running imago on several machines
+    Redis,
+}
+
+/// Storage for cached generation results, keyed by request hash
+pub trait CacheBackend {
+    /// Fetch a cached value, returning `None` on a miss or when expired
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store a value under `key`
+    fn put(&self, key: &str, value: &[u8]) -> Result<()>;
+}
+
+/// Open the configured cache backend
+pub fn open(
+    kind: CacheKind,
+    redis_url: Option<&str>,
+    ttl: Duration,
+) -> Result<Box<dyn CacheBackend>> {
+    match kind {
+        CacheKind::Disk => Ok(Box::new(DiskCache::new(default_dir()?, ttl))),
+        CacheKind::Redis => {
+            let url = redis_url.ok_or_else(|| {
+                ImagoError::CacheError("Redis cache requires --redis-url".to_string())
+            })?;
+            Ok(Box::new(RedisCache::new(url, ttl)?))
+        }
+    }
+}
+
+/// Default on-disk cache location
+pub fn default_dir() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("imago").join("responses"))
+        .ok_or_else(|| ImagoError::CacheError("No cache directory available".to_string()))
+}
+
+/// Cache files stored one per key, expired by modification time
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl CacheBackend for DiskCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key);
+        let modified = match fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return Ok(None),
+        };
+
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > self.ttl {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+
+        Ok(Some(fs::read(path)?))
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(key), value)?;
+        Ok(())
+    }
+}
+
+/// Cache entries stored in Redis with a server-side expiry
+pub struct RedisCache {
+    client: redis::Client,
+    ttl: Duration,
+}
+
+impl RedisCache {
+    pub fn new(url: &str, ttl: Duration) -> Result<Self> {
+        let client = redis::Client::open(url)
+            .map_err(|e| ImagoError::CacheError(format!("Invalid Redis URL: {}", e)))?;
+        Ok(Self { client, ttl })
+    }
+
+    fn connection(&self) -> Result<redis::Connection> {
+        self.client
+            .get_connection()
+            .map_err(|e| ImagoError::CacheError(format!("Redis connection failed: {}", e)))
+    }
+}
+
+impl CacheBackend for RedisCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut con = self.connection()?;
+        redis::cmd("GET")
+            .arg(format!("{}{}", REDIS_KEY_PREFIX, key))
+            .query(&mut con)
+            .map_err(|e| ImagoError::CacheError(format!("Redis GET failed: {}", e)))
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<()> {
+        let mut con = self.connection()?;
+        redis::cmd("SET")
+            .arg(format!("{}{}", REDIS_KEY_PREFIX, key))
+            .arg(value)
+            .arg("EX")
+            .arg(self.ttl.as_secs().max(1))
+            .query::<()>(&mut con)
+            .map_err(|e| ImagoError::CacheError(format!("Redis SET failed: {}", e)))
+    }
+}
+
+/// Cache key for a request: a hash of everything that affects the output
+pub fn request_key(
+    provider: &str,
+    model: &str,
+    prompt: &str,
+    options: &GenerationOptions,
+) -> String {
+    let parts = [
+        provider.to_string(),
+        model.to_string(),
+        prompt.to_string(),
+        options.size.clone().unwrap_or_default(),
+        options.count.to_string(),
+        options.quality.clone().unwrap_or_default(),
+        options.seed.map(|s| s.to_string()).unwrap_or_default(),
+        options.cfg_scale.map(|c| c.to_string()).unwrap_or_default(),
+        options.negative_prompt.clone().unwrap_or_default(),
+        options.compressed.to_string(),
+    ];
+
+    let mut hasher = Sha256::new();
+    for part in &parts {
+        hasher.update(part.as_bytes());
+        // Separator so ("ab", "c") and ("a", "bc") hash differently
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// What `imago cache stats` reports for one group of files
+#[derive(Debug, Default)]
+pub struct FileStats {
+    pub files: usize,
+    pub bytes: u64,
+    pub oldest: Option<SystemTime>,
+}
+
+/// Disk cache entries, and preview scratch files left in the temp directory
+/// by runs that were killed mid-preview
+#[derive(Debug)]
+pub struct Stats {
+    pub responses: FileStats,
+    pub previews: FileStats,
+}
+
+/// Count what the disk cache and leftover previews hold
+pub fn stats() -> Result<Stats> {
+    let summarize = |files: Vec<(PathBuf, fs::Metadata)>| {
+        let mut stats = FileStats::default();
+        for (_, metadata) in files {
+            stats.files += 1;
+            stats.bytes += metadata.len();
+            let modified = metadata.modified().ok();
+            stats.oldest = match (stats.oldest, modified) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        stats
+    };
+    Ok(Stats {
+        responses: summarize(response_files()?),
+        previews: summarize(preview_files()),
+    })
+}
+
+/// Delete disk cache entries and leftover previews last written more than
+/// `older_than` ago, or all of them with `None`; returns the files and bytes freed
+///
+/// Only the disk backend is touched: Redis entries expire on the server.
+pub fn prune(older_than: Option<Duration>) -> Result<FileStats> {
+    let now = SystemTime::now();
+    let mut removed = FileStats::default();
+    for (path, metadata) in response_files()?.into_iter().chain(preview_files()) {
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if older_than.is_some_and(|limit| age <= limit) {
+            continue;
+        }
+        if fs::remove_file(&path).is_ok() {
+            removed.files += 1;
+            removed.bytes += metadata.len();
+        }
+    }
+    Ok(removed)
+}
+
+fn response_files() -> Result<Vec<(PathBuf, fs::Metadata)>> {
+    Ok(files_in(&default_dir()?, |name| name.ends_with(".json")))
+}
+
+fn preview_files() -> Vec<(PathBuf, fs::Metadata)> {
+    files_in(&std::env::temp_dir(), |name| {
+        name.starts_with(crate::image_handler::PREVIEW_PREFIX)
+    })
+}
+
+/// Regular files in `dir` whose names pass `matches`; a missing directory is empty
+fn files_in(dir: &Path, matches: impl Fn(&str) -> bool) -> Vec<(PathBuf, fs::Metadata)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_str().is_some_and(&matches))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(fs::Metadata::is_file)?;
+            Some((entry.path(), metadata))
+        })
+        .collect()
+}
+
+/// `1.4 MB`-style size for listings
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Parse a duration such as `45s`, `90m`, `12h`, `30d` or `2w`
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || {
+        ImagoError::ConfigError(format!(
+            "invalid duration '{}': expected a number and a unit (s, m, h, d or w), e.g. 30d",
+            text
+        ))
+    };
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = text.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs(amount.saturating_mul(unit_secs)))
+}
+
+/// Serialize a generation result for storage
+pub fn encode_result(result: &GenerationResult) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(result)?)
+}
+
+/// Restore a cached result, ignoring entries that no longer decode
+pub fn decode_result(value: &[u8]) -> Option<GenerationResult> {
+    serde_json::from_slice(value).ok()
+}
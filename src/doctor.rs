@@ -0,0 +1,332 @@
+//! `imago doctor`: a pass/fail report of everything a generation depends on
+//!
+//! Covers connectivity to the provider, key validity, terminal graphics
+//! support, `viu`, and write access to the output and config directories.
+//! The report is plain text meant to be pasted into bug reports.
+
+use crate::cli::Cli;
+use crate::config;
+use crate::error::{ImagoError, Result};
+use crate::http::HttpSettings;
+use crate::image_handler::ImageHandler;
+use crate::probe;
+use crate::provider::Provider;
+use colored::Colorize;
+use reqwest::Client;
+use std::env;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Terminals known to render sixel graphics
+const SIXEL_TERMINALS: [&str; 5] = ["foot", "mlterm", "yaft", "contour", "sixel"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Detected facts that are neither good nor bad
+    Info,
+    Warn,
+    Fail,
+}
+
+/// One line of the report
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Run every check; `keys` is the outcome of key resolution
+pub async fn run(cli: &Cli, keys: Result<Vec<String>>) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let http = HttpSettings {
+        timeout: TIMEOUT,
+        ..cli.http_settings()
+    };
+    let client = http.client().ok();
+
+    match (client.as_ref(), endpoint(&cli.provider)) {
+        (Some(client), Some(url)) => checks.push(connectivity(client, &url).await),
+        _ => checks.push(Check::new(
+            "connectivity",
+            Status::Warn,
+            format!("not checked for {}", cli.provider),
+        )),
+    }
+    checks.push(key_check(cli, client.as_ref(), keys).await);
+    checks.extend(terminal_checks());
+    if cli.output.is_empty() {
+        checks.push(writable("output directory", &output_dir(None)));
+    }
+    for output in &cli.output {
+        checks.push(writable("output directory", &output_dir(Some(output))));
+    }
+    if let Ok(path) = config::target_path(cli.config.as_deref(), false) {
+        if let Some(dir) = path.parent() {
+            checks.push(writable("config directory", dir));
+        }
+    }
+    checks
+}
+
+/// Print the report, returning how many checks failed (warnings allowed)
+pub fn print(cli: &Cli, checks: &[Check]) -> usize {
+    println!(
+        "imago {} on {}/{}",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH
+    );
+    println!("Provider: {} ({})\n", cli.provider, cli.model());
+
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for check in checks {
+        let label = match check.status {
+            Status::Pass => "PASS".green(),
+            Status::Info => "INFO".cyan(),
+            Status::Warn => "WARN".yellow(),
+            Status::Fail => "FAIL".red(),
+        };
+        println!("{}  {:width$}  {}", label.bold(), check.name, check.detail);
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    println!();
+    if failed == 0 {
+        println!("All checks passed");
+    }
+    failed
+}
+
+/// Base URL of the provider's API, reachable without credentials
+fn endpoint(provider: &Provider) -> Option<String> {
+    match provider {
+        Provider::Gemini => Some("https://generativelanguage.googleapis.com".to_string()),
+        Provider::OpenAi => Some("https://api.openai.com".to_string()),
+        Provider::Stability => Some("https://api.stability.ai".to_string()),
+        Provider::Replicate => Some("https://api.replicate.com".to_string()),
+        Provider::Fal => Some("https://queue.fal.run".to_string()),
+        Provider::Azure => env::var("AZURE_OPENAI_ENDPOINT").ok(),
+        Provider::Bedrock => Some(format!(
+            "https://bedrock-runtime.{}.amazonaws.com",
+            env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string())
+        )),
+        Provider::Mock | Provider::Plugin(_) => None,
+    }
+}
+
+/// Any HTTP response, even an error status, proves the host is reachable
+async fn connectivity(client: &Client, url: &str) -> Check {
+    match client.get(url).send().await {
+        Ok(response) => Check::new(
+            "connectivity",
+            Status::Pass,
+            format!("{} answered {}", url, response.status().as_u16()),
+        ),
+        Err(e) => Check::new("connectivity", Status::Fail, format!("{}: {}", url, e)),
+    }
+}
+
+async fn key_check(cli: &Cli, client: Option<&Client>, keys: Result<Vec<String>>) -> Check {
+    let keys = match keys {
+        Ok(keys) => keys,
+        Err(e) => return Check::new("api key", Status::Fail, e.to_string()),
+    };
+    if cli.provider.api_key_env().is_none() {
+        return Check::new("api key", Status::Pass, "not needed for plugins");
+    }
+    let (Some(client), true) = (client, probe::supported(&cli.provider)) else {
+        return Check::new(
+            "api key",
+            Status::Warn,
+            format!(
+                "{} key(s) found, validity not checked for {}",
+                keys.len(),
+                cli.provider
+            ),
+        );
+    };
+
+    let mut rejected = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        match probe::check(client, &cli.provider, key).await {
+            Ok(_) => {}
+            Err(e) if e.is_rate_limited() => {}
+            Err(ImagoError::ApiError { status, .. }) => {
+                rejected.push(format!("key {} (status {})", i + 1, status))
+            }
+            Err(e) => return Check::new("api key", Status::Fail, e.to_string()),
+        }
+    }
+    if rejected.is_empty() {
+        Check::new(
+            "api key",
+            Status::Pass,
+            format!("{} key(s) valid", keys.len()),
+        )
+    } else {
+        Check::new(
+            "api key",
+            Status::Fail,
+            format!("rejected: {}", rejected.join(", ")),
+        )
+    }
+}
+
+fn terminal_checks() -> Vec<Check> {
+    let term = env::var("TERM").unwrap_or_default();
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+    let mut checks = Vec::new();
+
+    if std::io::stdout().is_terminal() {
+        checks.push(Check::new(
+            "terminal",
+            Status::Pass,
+            format!("TERM={} TERM_PROGRAM={}", term, term_program),
+        ));
+    } else {
+        checks.push(Check::new(
+            "terminal",
+            Status::Warn,
+            "stdout is not a terminal, previews will not render",
+        ));
+    }
+
+    let protocol = ImageHandler::graphics_protocol();
+    let status = if protocol.starts_with("block characters") || protocol.starts_with("none") {
+        Status::Warn
+    } else {
+        Status::Pass
+    };
+    checks.push(Check::new("graphics protocol", status, protocol));
+    if let Some(reason) = ImageHandler::plain_session() {
+        checks.push(Check::new(
+            "session",
+            Status::Info,
+            format!(
+                "{}, automatic previews are skipped (--force-preview draws them)",
+                reason
+            ),
+        ));
+    }
+
+    let sixel = SIXEL_TERMINALS
+        .iter()
+        .any(|name| term.contains(name) || term_program.to_lowercase().contains(name));
+    checks.push(Check::new(
+        "sixel",
+        Status::Info,
+        if ImageHandler::has_sixel() {
+            "supported, the terminal says so"
+        } else if sixel {
+            "supported according to TERM"
+        } else {
+            "not detected"
+        },
+    ));
+
+    if ImageHandler::has_viu() {
+        checks.push(Check::new("viu", Status::Pass, "found on PATH"));
+    } else {
+        checks.push(Check::new(
+            "viu",
+            Status::Warn,
+            if cfg!(feature = "preview") {
+                "not found, using the built-in renderer"
+            } else {
+                "not found, using block characters (built without the preview feature)"
+            },
+        ));
+    }
+    if env::var_os("TMUX").is_some() {
+        // Graphics only reach the terminal through tmux's passthrough
+        let passthrough = Command::new("tmux")
+            .args(["show", "-gv", "allow-passthrough"])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+            .unwrap_or_default();
+        checks.push(if passthrough == "on" || passthrough == "all" {
+            Check::new("tmux", Status::Pass, "allow-passthrough is on")
+        } else {
+            Check::new(
+                "tmux",
+                Status::Warn,
+                "allow-passthrough is off, graphics previews will not show (set -g allow-passthrough on)",
+            )
+        });
+    }
+    checks.push(Check::new(
+        "ueberzugpp",
+        Status::Info,
+        if ImageHandler::has_ueberzug() {
+            "found on PATH, used with --preview-backend ueberzug"
+        } else {
+            "not found"
+        },
+    ));
+    checks
+}
+
+/// Directory images are saved into for `--output`
+fn output_dir(output: Option<&Path>) -> PathBuf {
+    match output {
+        Some(path) if path.is_dir() || path.to_string_lossy().ends_with('/') => path.to_path_buf(),
+        Some(path) => path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(".")),
+        None => PathBuf::from("."),
+    }
+}
+
+/// Try creating a file in `dir`, or in its nearest existing ancestor when it
+/// does not exist yet (imago creates missing directories when saving)
+fn writable(name: &'static str, dir: &Path) -> Check {
+    // A relative path's last ancestor is "", meaning the working directory
+    let existing = dir
+        .ancestors()
+        .find(|d| d.as_os_str().is_empty() || d.is_dir())
+        .map(|d| {
+            if d.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                d
+            }
+        });
+    let Some(existing) = existing else {
+        return Check::new(
+            name,
+            Status::Fail,
+            format!("{} does not exist", dir.display()),
+        );
+    };
+    let test_file = existing.join(format!(".imago-doctor-{}", std::process::id()));
+    match fs::write(&test_file, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&test_file);
+            let detail = if existing == dir {
+                format!("{} is writable", dir.display())
+            } else {
+                format!("{} will be created", dir.display())
+            };
+            Check::new(name, Status::Pass, detail)
+        }
+        Err(e) => Check::new(name, Status::Fail, format!("{}: {}", existing.display(), e)),
+    }
+}
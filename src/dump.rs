@@ -0,0 +1,80 @@
+//! `--dump-http`: provider traffic written to disk for debugging
+//!
+//! Every provider request and the response it got back are saved as a pair
+//! of files, numbered in the order they were sent:
+//! `<run>-<n>-<provider>.request.json` holds the method, URL, status and JSON
+//! body sent; `<run>-<n>-<provider>.response.json` is the response body
+//! exactly as received (`.response.bin` for downloaded images). The API key
+//! is replaced with `REDACTED` wherever it appears. Image data is kept, so
+//! dumps of large images are large.
+
+use crate::error::Result;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// Stands in for the API key in dumps and session files
+pub const REDACTED: &str = "REDACTED";
+
+/// Dump directory and the run prefix for its file names
+static TARGET: OnceLock<(PathBuf, String)> = OnceLock::new();
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// Start dumping into `dir`, creating it if needed
+pub fn enable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let run = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let _ = TARGET.set((dir.to_path_buf(), run));
+    Ok(())
+}
+
+/// Record one request/response pair; does nothing unless dumping is enabled
+///
+/// `request` is the JSON body, or `None` for requests without one (polls).
+/// `secret` is redacted from the URL and both bodies. A dump that can't be
+/// written is reported but never fails the request.
+pub fn exchange(
+    provider: &str,
+    method: &str,
+    url: &str,
+    request: Option<&Value>,
+    status: u16,
+    response: &[u8],
+    secret: &str,
+) {
+    let Some((dir, run)) = TARGET.get() else {
+        return;
+    };
+    let redact = |text: &str| redact(text, secret);
+
+    let n = SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+    let stem = dir.join(format!("{}-{:03}-{}", run, n, provider));
+    let request = json!({
+        "method": method,
+        "url": redact(url),
+        "status": status,
+        "body": request.cloned().unwrap_or(Value::Null),
+    });
+    let request = redact(&serde_json::to_string_pretty(&request).unwrap_or_default());
+    let (extension, response) = match std::str::from_utf8(response) {
+        Ok(text) => ("response.json", redact(text).into_bytes()),
+        Err(_) => ("response.bin", response.to_vec()),
+    };
+
+    let written = fs::write(stem.with_extension("request.json"), request)
+        .and_then(|_| fs::write(stem.with_extension(extension), response));
+    if let Err(e) = written {
+        eprintln!("--dump-http: couldn't write to {}: {}", dir.display(), e);
+    }
+}
+
+/// `text` with every occurrence of `secret` replaced by [`REDACTED`]
+pub fn redact(text: &str, secret: &str) -> String {
+    if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, REDACTED)
+    }
+}
@@ -0,0 +1,79 @@
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+/// Static site generators that imago can emit embed snippets for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SiteGenerator {
+    Hugo,
+    Jekyll,
+    Astro,
+}
+
+impl SiteGenerator {
+    /// Directory, relative to the site root, where generated images are saved
+    pub fn asset_dir(&self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo => "static/images",
+            SiteGenerator::Jekyll => "assets/images",
+            SiteGenerator::Astro => "src/assets/images",
+        }
+    }
+
+    /// Directory, relative to the site root, that is served from `/`
+    fn public_root(&self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo => "static",
+            SiteGenerator::Jekyll | SiteGenerator::Astro => "",
+        }
+    }
+
+    /// Build the figure shortcode/component snippet for an image saved at `path`
+    pub fn snippet(&self, site_root: &Path, path: &Path, alt: &str) -> String {
+        let alt = alt.split_whitespace().collect::<Vec<_>>().join(" ");
+        let url = self.site_url(site_root, path);
+
+        match self {
+            SiteGenerator::Hugo => format!(
+                "{{{{< figure src=\"{}\" alt=\"{}\" >}}}}",
+                url,
+                alt.replace('"', "\\\"")
+            ),
+            SiteGenerator::Jekyll => format!(
+                "![{}]({{{{ '{}' | relative_url }}}})",
+                alt.replace('[', "\\[").replace(']', "\\]"),
+                url
+            ),
+            SiteGenerator::Astro => {
+                let alt = serde_json::to_string(&alt).unwrap_or_else(|_| "\"\"".to_string());
+                format!(
+                    "---\nimport {{ Image }} from 'astro:assets';\nimport generatedImage from '{}';\n---\n<Image src={{generatedImage}} alt={{{}}} />",
+                    url, alt
+                )
+            }
+        }
+    }
+
+    /// URL of `path` as seen by the site, falling back to a site-root-relative path
+    fn site_url(&self, site_root: &Path, path: &Path) -> String {
+        let root = canonical(site_root);
+        let path = canonical(path);
+        let public_root = root.join(self.public_root());
+
+        let relative = path
+            .strip_prefix(&public_root)
+            .or_else(|_| path.strip_prefix(&root))
+            .map(Path::to_path_buf)
+            .unwrap_or(path);
+
+        let url = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        format!("/{}", url.trim_start_matches('/'))
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -1,13 +1,41 @@
+use std::time::Duration;
 use thiserror::Error;
 
+// Process exit codes, one per class of failure (documented in the README)
+
+/// Any failure without a more specific code
+pub const EXIT_FAILURE: i32 = 1;
+/// Invalid arguments or configuration
+pub const EXIT_USAGE: i32 = 2;
+/// Missing, rejected or unusable API key
+pub const EXIT_AUTH: i32 = 3;
+/// The provider's safety filter blocked the prompt or image
+pub const EXIT_SAFETY: i32 = 4;
+/// The provider could not be reached or did not answer in time
+pub const EXIT_NETWORK: i32 = 5;
+/// The provider answered without an image
+pub const EXIT_NO_IMAGE: i32 = 6;
+/// Stopped with Ctrl+C (128 + SIGINT, as shells report it)
+pub const EXIT_INTERRUPTED: i32 = 130;
+
 /// Application-specific error types
 #[derive(Error, Debug)]
 pub enum ImagoError {
-    #[error("API key not found. Please set GEMINI_API_KEY environment variable")]
-    MissingApiKey,
+    #[error("API key not found. Please set {0} environment variable or run `imago auth set-key`")]
+    MissingApiKey(&'static str),
 
-    #[error("API error (status {status}): {message}")]
-    ApiError { status: u16, message: String },
+    #[error(
+        "API error (status {status}): {message}{}",
+        .request_id.as_ref().map(|id| format!(" (request ID {})", id)).unwrap_or_default()
+    )]
+    ApiError {
+        status: u16,
+        message: String,
+        /// How long the provider asked us to wait before trying again
+        retry_after: Option<Duration>,
+        /// Provider's ID for the failed call, to quote to its support
+        request_id: Option<String>,
+    },
 
     #[error("API response error: {0}")]
     ApiResponseError(String),
@@ -36,21 +64,135 @@ pub enum ImagoError {
     #[error("Terminal display error: {0}")]
     DisplayError(String),
 
+    #[error("Config error: {0}")]
+    ConfigError(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Job error: {0}")]
+    JobError(String),
+
+    #[error("Trash error: {0}")]
+    TrashError(String),
+
+    #[error("Manifest error: {0}")]
+    ManifestError(String),
+
+    #[error("Auth error: {0}")]
+    AuthError(String),
+
+    #[error("History error: {0}")]
+    HistoryError(String),
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error("{0} already exists; pass --force to overwrite it or --auto-suffix to keep both")]
+    OutputExists(String),
+
+    #[error("Cache error: {0}")]
+    CacheError(String),
+
+    #[error("--pipe-to: {0}")]
+    PipeError(String),
+
+    #[error("Session file: {0}")]
+    SessionError(String),
+
     #[error("Invalid response format: {message}")]
     ResponseFormatError { message: String },
 
-    #[allow(dead_code)]
-    #[error("Request timeout")]
+    #[error(
+        "Response is larger than {limit_mb} MB; raise --max-response-size if that is expected"
+    )]
+    ResponseTooLarge { limit_mb: u64 },
+
+    #[error(
+        "Stopped after repeated failures ({failed} of {requested} requests failed, \
+         even after a pause): {source}"
+    )]
+    CircuitOpen {
+        failed: u32,
+        requested: u32,
+        source: Box<ImagoError>,
+    },
+
+    #[error("{0} check(s) failed")]
+    ChecksFailed(usize),
+
+    #[error("Cancelled")]
+    Interrupted,
+
+    #[error("Request timed out; allow more time with --timeout")]
     Timeout,
 }
 
 impl ImagoError {
-    /// Check if error is retryable (network/server errors)
-    #[allow(dead_code)]
+    /// Variant name, the `kind` of JSON error output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ImagoError::MissingApiKey(_) => "MissingApiKey",
+            ImagoError::ApiError { .. } => "ApiError",
+            ImagoError::ApiResponseError(_) => "ApiResponseError",
+            ImagoError::NoImageData => "NoImageData",
+            ImagoError::SafetyFilter(_) => "SafetyFilter",
+            ImagoError::NetworkError(_) => "NetworkError",
+            ImagoError::JsonError(_) => "JsonError",
+            ImagoError::ImageError(_) => "ImageError",
+            ImagoError::IoError(_) => "IoError",
+            ImagoError::Base64Error(_) => "Base64Error",
+            ImagoError::DisplayError(_) => "DisplayError",
+            ImagoError::ConfigError(_) => "ConfigError",
+            ImagoError::InvalidArgument(_) => "InvalidArgument",
+            ImagoError::JobError(_) => "JobError",
+            ImagoError::TrashError(_) => "TrashError",
+            ImagoError::ManifestError(_) => "ManifestError",
+            ImagoError::AuthError(_) => "AuthError",
+            ImagoError::HistoryError(_) => "HistoryError",
+            ImagoError::BudgetExceeded(_) => "BudgetExceeded",
+            ImagoError::OutputExists(_) => "OutputExists",
+            ImagoError::CacheError(_) => "CacheError",
+            ImagoError::PipeError(_) => "PipeError",
+            ImagoError::SessionError(_) => "SessionError",
+            ImagoError::ResponseFormatError { .. } => "ResponseFormatError",
+            ImagoError::ResponseTooLarge { .. } => "ResponseTooLarge",
+            ImagoError::CircuitOpen { .. } => "CircuitOpen",
+            ImagoError::ChecksFailed(_) => "ChecksFailed",
+            ImagoError::Interrupted => "Interrupted",
+            ImagoError::Timeout => "Timeout",
+        }
+    }
+
+    /// Exit code for this class of error, so scripts can branch on it
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ImagoError::ConfigError(_) | ImagoError::InvalidArgument(_) => EXIT_USAGE,
+            ImagoError::MissingApiKey(_)
+            | ImagoError::AuthError(_)
+            | ImagoError::ApiError {
+                status: 401 | 403, ..
+            } => EXIT_AUTH,
+            ImagoError::SafetyFilter(_) => EXIT_SAFETY,
+            ImagoError::NetworkError(_) | ImagoError::Timeout => EXIT_NETWORK,
+            ImagoError::NoImageData => EXIT_NO_IMAGE,
+            ImagoError::CircuitOpen { source, .. } => source.exit_code(),
+            ImagoError::Interrupted => EXIT_INTERRUPTED,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    /// Check if the provider rejected the key for rate limits or exhausted quota
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, ImagoError::ApiError { status: 429, .. })
+    }
+
+    /// Check if error is retryable (network/server errors and timeouts)
     pub fn is_retryable(&self) -> bool {
         matches!(
             self,
             ImagoError::NetworkError(_)
+                | ImagoError::Timeout
                 | ImagoError::ApiError {
                     status: 500..=599,
                     ..
@@ -0,0 +1,515 @@
+//! Curated, runnable example catalog shown by `imago examples [topic]`
+//!
+//! The same registry feeds the per-subcommand `--help` examples, and the tests
+//! below parse every entry with the real CLI so the catalog can't drift.
+
+use crate::error::{ImagoError, Result};
+use clap::Command;
+use colored::Colorize;
+
+/// A group of related examples
+pub struct Topic {
+    pub name: &'static str,
+    pub summary: &'static str,
+}
+
+/// One runnable command line
+pub struct Example {
+    pub topic: &'static str,
+    pub description: &'static str,
+    pub command: &'static str,
+}
+
+pub const TOPICS: &[Topic] = &[
+    Topic {
+        name: "basics",
+        summary: "Generating, saving and previewing images",
+    },
+    Topic {
+        name: "providers",
+        summary: "Choosing a backend, model and provider-specific options",
+    },
+    Topic {
+        name: "batching",
+        summary: "Several images per run and background jobs",
+    },
+    Topic {
+        name: "pipelines",
+        summary: "Static sites, notes, launchers and gallery pages",
+    },
+    Topic {
+        name: "config",
+        summary: "Profiles, project settings, styles and caching",
+    },
+    Topic {
+        name: "metadata",
+        summary: "Embedded parameters, placeholder EXIF and prompt auditing",
+    },
+    Topic {
+        name: "files",
+        summary: "Trashing and restoring generated images",
+    },
+];
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        topic: "basics",
+        description: "Generate an image into the current directory and preview it",
+        command: r#"imago "a cinematic blue cyberpunk city at night""#,
+    },
+    Example {
+        topic: "basics",
+        description: "Save into a directory without a terminal preview",
+        command: r#"imago "minimal abstract wallpaper" -o ./output/ --no-preview"#,
+    },
+    Example {
+        topic: "basics",
+        description: "Pick an aspect ratio and model",
+        command: r#"imago "product mockup on white desk" --size 16:9 --model gemini-2.5-flash-image"#,
+    },
+    Example {
+        topic: "basics",
+        description: "Compressed output and a small preview on a metered connection",
+        command: r#"imago "travel sketch" --provider fal --low-bandwidth"#,
+    },
+    Example {
+        topic: "basics",
+        description: "Screen-reader-friendly output with a text description of the image",
+        command: r#"imago "a lighthouse on a cliff at sunrise" --a11y"#,
+    },
+    Example {
+        topic: "providers",
+        description: "OpenAI with an explicit size and quality",
+        command: r#"imago "isometric game asset" --provider openai --size 1024x1536 --quality high"#,
+    },
+    Example {
+        topic: "providers",
+        description: "Stability with a fixed seed, guidance and negative prompt",
+        command: r#"imago "foggy forest" --provider stability --seed 42 --cfg-scale 7 --negative-prompt "people""#,
+    },
+    Example {
+        topic: "providers",
+        description: "Any Replicate model by slug",
+        command: r#"imago "pixel art fox" --provider replicate -m black-forest-labs/flux-schnell"#,
+    },
+    Example {
+        topic: "providers",
+        description: "An Azure OpenAI deployment",
+        command: r#"imago "team offsite banner" --provider azure --deployment my-dalle3"#,
+    },
+    Example {
+        topic: "providers",
+        description: "A plugin backend (runs imago-provider-mycorp from PATH)",
+        command: r#"imago "logo sketch" --provider mycorp"#,
+    },
+    Example {
+        topic: "providers",
+        description: "Offline placeholders for demos and tests (no key, no network)",
+        command: r#"imago "launch banner" --provider mock -s 16:9"#,
+    },
+    Example {
+        topic: "batching",
+        description: "Four variations numbered into one directory",
+        command: r#"imago "sticker set of cute robots" -n 4 -o ./stickers/"#,
+    },
+    Example {
+        topic: "batching",
+        description: "Four Gemini requests in flight, paced under 30 per minute",
+        command: r#"imago "icon set" -n 8 --jobs 4 --rpm 30"#,
+    },
+    Example {
+        topic: "batching",
+        description: "Measure the key's rate limit and save --jobs/--rpm into a profile",
+        command: "imago calibrate --profile work",
+    },
+    Example {
+        topic: "batching",
+        description: "Run a slow batch in the background",
+        command: r#"imago "poster series" -n 8 --provider fal --detach"#,
+    },
+    Example {
+        topic: "batching",
+        description: "List background jobs",
+        command: "imago jobs",
+    },
+    Example {
+        topic: "batching",
+        description: "Follow a background job and preview images as they land",
+        command: "imago attach 1a2b3c4d",
+    },
+    Example {
+        topic: "batching",
+        description: "Show the estimated cost of each image and the running total",
+        command: r#"imago "logo ideas" -n 4 --show-cost"#,
+    },
+    Example {
+        topic: "pipelines",
+        description: "Save into a Hugo site and print the embed shortcode",
+        command: r#"imago "watercolor lighthouse" --emit hugo --site-root ./blog"#,
+    },
+    Example {
+        topic: "pipelines",
+        description: "Generate into an Obsidian vault and embed it in a note",
+        command: r#"imago note "concept art of the villain" --vault ./Notes --note "Worldbuilding/Villains.md""#,
+    },
+    Example {
+        topic: "pipelines",
+        description: "Plain path output with a thumbnail for Raycast/Alfred",
+        command: r#"imago "app icon" --launcher-mode --launcher-thumbnail"#,
+    },
+    Example {
+        topic: "pipelines",
+        description: "Build a shareable gallery page for a directory",
+        command: r#"imago publish-gallery ./output --format html --title "Moodboard""#,
+    },
+    Example {
+        topic: "config",
+        description: "Use the [profile.work] section of the config file",
+        command: r#"imago "quarterly report cover" --profile work"#,
+    },
+    Example {
+        topic: "config",
+        description: "Read defaults from a specific config file",
+        command: r#"imago "banner" --config ./imago.toml"#,
+    },
+    Example {
+        topic: "config",
+        description: "Interactive first-run setup with a test generation",
+        command: "imago init",
+    },
+    Example {
+        topic: "config",
+        description: "Read the key from a mounted secret",
+        command: r#"imago "hero image" --api-key-file /run/secrets/gemini"#,
+    },
+    Example {
+        topic: "config",
+        description: "Keep the OpenAI key in the system keychain instead of an env var",
+        command: "imago auth set-key --provider openai",
+    },
+    Example {
+        topic: "config",
+        description: "Check that the configured keys work and which models they reach",
+        command: "imago auth check",
+    },
+    Example {
+        topic: "config",
+        description: "See which image models your key can use with -m",
+        command: "imago models",
+    },
+    Example {
+        topic: "config",
+        description: "Diagnose keys, connectivity and terminal previews before filing a bug",
+        command: "imago doctor",
+    },
+    Example {
+        topic: "config",
+        description: "Change a default without editing the config file",
+        command: "imago config set model gemini-2.5-flash-image",
+    },
+    Example {
+        topic: "config",
+        description: "Set a key inside a profile",
+        command: "imago config set profile.work.provider azure",
+    },
+    Example {
+        topic: "config",
+        description: "Show every setting in the config file",
+        command: "imago config list",
+    },
+    Example {
+        topic: "config",
+        description: "Set a style for everyone working in this repository",
+        command: r#"imago config --project set style "flat vector, brand palette""#,
+    },
+    Example {
+        topic: "config",
+        description: "Append a style preset to the prompt",
+        command: r#"imago "hero image" --style "flat vector, brand palette""#,
+    },
+    Example {
+        topic: "config",
+        description: "Serve repeated identical requests from the local cache",
+        command: r#"imago "hero image" --cache-backend disk"#,
+    },
+    Example {
+        topic: "metadata",
+        description: "Write placeholder photo EXIF for demo datasets",
+        command: r#"imago "street portrait" --fake-exif "camera=Fujifilm X-T4, iso=200, date=2024-08-01""#,
+    },
+    Example {
+        topic: "metadata",
+        description: "Record a signed manifest of how the image was made",
+        command: r#"imago "annual report cover" --provider stability --seed 42 --manifest --sign-manifest"#,
+    },
+    Example {
+        topic: "metadata",
+        description: "Regenerate from a manifest and compare with the original",
+        command: "imago reproduce ./cover.manifest.json",
+    },
+    Example {
+        topic: "metadata",
+        description: "Audit every prompt before it is sent",
+        command: r#"imago "hero image" --prompt-log ./prompts.jsonl"#,
+    },
+    Example {
+        topic: "files",
+        description: "Save to an archive directory and the project's assets in one run",
+        command: r#"imago "hero banner" -o ~/Pictures/imago/ -o ./assets/img/hero.png"#,
+    },
+    Example {
+        topic: "files",
+        description: "Save into a dated subdirectory such as ./images/2025/06/14/",
+        command: r#"imago "app icon" -o ./images/ --organize date"#,
+    },
+    Example {
+        topic: "files",
+        description: "Name files after the date, model and prompt",
+        command: r#"imago "red dragon" -n 4 --name-template "{date}_{model}_{prompt_slug}_{n}""#,
+    },
+    Example {
+        topic: "files",
+        description: "Keep the existing cover.png and save as cover_2.png",
+        command: r#"imago "book cover" -o cover.png --auto-suffix"#,
+    },
+    Example {
+        topic: "files",
+        description: "Frames walk-001.png, walk-002.png, ... continuing an existing sequence",
+        command: r#"imago "pixel knight, walk cycle" -n 8 -o ./frames/ --name-template walk --numbered"#,
+    },
+    Example {
+        topic: "files",
+        description: "Move an image to the trash",
+        command: "imago trash put ./output/old.png",
+    },
+    Example {
+        topic: "files",
+        description: "List trashed images",
+        command: "imago trash list",
+    },
+    Example {
+        topic: "files",
+        description: "Restore a trashed image to where it was",
+        command: "imago trash restore 20260801120512-3f9a",
+    },
+    Example {
+        topic: "files",
+        description: "Find last week's dragon prompts and where the images went",
+        command: "imago history --search dragon --since 7d",
+    },
+    Example {
+        topic: "files",
+        description: "Tag a run, then list everything with that tag",
+        command: "imago tag 42 logo-drafts && imago history --tag logo-drafts",
+    },
+    Example {
+        topic: "files",
+        description: "Every recorded generation as CSV for a spreadsheet",
+        command: "imago history export --format csv > gens.csv",
+    },
+    Example {
+        topic: "files",
+        description: "Browse past generations with previews; r re-runs, t tags, d deletes",
+        command: "imago gallery --search logo",
+    },
+    Example {
+        topic: "files",
+        description: "Four fresh variants of run 42 with the same settings",
+        command: "imago redo 42 -n 4",
+    },
+    Example {
+        topic: "files",
+        description: "Pipe the image straight into another tool",
+        command: "imago \"a cat\" -o - | convert - cat.webp",
+    },
+    Example {
+        topic: "files",
+        description: "Optimize every saved image with an external tool",
+        command: "imago \"app icon\" -o icon.png --pipe-to 'optipng -o7 {path}'",
+    },
+    Example {
+        topic: "files",
+        description: "Read the saved path from the JSON report in a script",
+        command: "imago \"app icon\" --output-format json | jq -r '.images[0].path'",
+    },
+    Example {
+        topic: "files",
+        description: "Only the saved path, for Makefiles and shell substitution",
+        command: "convert \"$(imago -q \"paper texture\")\" -resize 50% texture.jpg",
+    },
+    Example {
+        topic: "files",
+        description: "Tokens and images used per day and model this month",
+        command: "imago usage --since 2026-08-01",
+    },
+];
+
+/// Subcommand an example runs, if any
+fn subcommand_of(example: &Example) -> Option<&'static str> {
+    example
+        .command
+        .strip_prefix("imago ")?
+        .split_whitespace()
+        .next()
+        .filter(|word| !word.starts_with(['"', '-']))
+}
+
+/// Add each subcommand's catalog entries to its `--help`
+pub fn with_examples(mut command: Command) -> Command {
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+
+    for name in names {
+        let lines: Vec<String> = EXAMPLES
+            .iter()
+            .filter(|example| subcommand_of(example) == Some(name.as_str()))
+            .map(|example| format!("  # {}\n  {}", example.description, example.command))
+            .collect();
+        if !lines.is_empty() {
+            let help = format!("Examples:\n{}", lines.join("\n\n"));
+            command = command.mut_subcommand(&name, |sub| sub.after_help(help));
+        }
+    }
+    command
+}
+
+/// Print the catalog, or one topic of it
+pub fn print(topic: Option<&str>) -> Result<()> {
+    let topics: Vec<&Topic> = match topic {
+        Some(name) => {
+            let topic = TOPICS.iter().find(|t| t.name == name).ok_or_else(|| {
+                let names: Vec<&str> = TOPICS.iter().map(|t| t.name).collect();
+                ImagoError::InvalidArgument(format!(
+                    "Unknown topic '{}'. Topics: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+            vec![topic]
+        }
+        None => TOPICS.iter().collect(),
+    };
+
+    for (i, topic) in topics.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{} {}",
+            topic.name.bold(),
+            format!("- {}", topic.summary).dimmed()
+        );
+        for example in EXAMPLES.iter().filter(|e| e.topic == topic.name) {
+            println!("  {}", format!("# {}", example.description).dimmed());
+            println!("  {}", example.command.cyan());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::{CommandFactory, Parser};
+
+    /// Split a catalog command line, honoring single and double quotes
+    fn split_args(command: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut current = String::new();
+        let mut quote = None;
+        let mut in_arg = false;
+        for c in command.chars() {
+            match c {
+                '"' | '\'' if quote.is_none() => {
+                    quote = Some(c);
+                    in_arg = true;
+                }
+                c if quote == Some(c) => quote = None,
+                c if c.is_whitespace() && quote.is_none() => {
+                    if in_arg {
+                        args.push(std::mem::take(&mut current));
+                        in_arg = false;
+                    }
+                }
+                c => {
+                    current.push(c);
+                    in_arg = true;
+                }
+            }
+        }
+        assert!(quote.is_none(), "unbalanced quotes in {}", command);
+        if in_arg {
+            args.push(current);
+        }
+        args
+    }
+
+    /// The `imago` commands of a catalog command line, which may chain
+    /// others with `&&`, `|` or `>`
+    fn imago_commands(command: &str) -> Vec<Vec<String>> {
+        split_args(command)
+            .split(|arg| matches!(arg.as_str(), "&&" | "||" | "|" | ">" | ";"))
+            .filter(|args| args.first().is_some_and(|arg| arg == "imago"))
+            .map(<[String]>::to_vec)
+            .collect()
+    }
+
+    #[test]
+    fn every_example_parses_and_validates() {
+        for example in EXAMPLES {
+            let commands = imago_commands(example.command);
+            assert!(
+                !commands.is_empty() || example.command.contains("$(imago "),
+                "{}",
+                example.command
+            );
+            for args in commands {
+                let cli = Cli::try_parse_from(&args)
+                    .unwrap_or_else(|e| panic!("{}\n{}", example.command, e));
+                cli.validate()
+                    .unwrap_or_else(|e| panic!("{}\n{}", example.command, e));
+            }
+        }
+    }
+
+    #[test]
+    fn every_example_has_a_known_topic() {
+        for example in EXAMPLES {
+            assert!(
+                TOPICS.iter().any(|t| t.name == example.topic),
+                "unknown topic '{}' for {}",
+                example.topic,
+                example.command
+            );
+        }
+    }
+
+    #[test]
+    fn every_topic_has_examples() {
+        for topic in TOPICS {
+            assert!(
+                EXAMPLES.iter().any(|e| e.topic == topic.name),
+                "topic '{}' has no examples",
+                topic.name
+            );
+        }
+    }
+
+    #[test]
+    fn subcommand_examples_name_real_subcommands() {
+        let command = Cli::command();
+        for example in EXAMPLES {
+            if let Some(name) = subcommand_of(example) {
+                assert!(
+                    command.find_subcommand(name).is_some(),
+                    "{} names unknown subcommand '{}'",
+                    example.command,
+                    name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//! Synthetic EXIF photo metadata for `--fake-exif`
+//!
+//! Builds a minimal little-endian TIFF structure (IFD0, Exif IFD and an
+//! optional GPS IFD) that is embedded as a PNG `eXIf` chunk or a JPEG APP1
+//! segment by [`crate::metadata::embed_exif`].
+
+use chrono::{NaiveDate, NaiveDateTime};
+use std::str::FromStr;
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_ISO: u16 = 0x8827;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_LENS_MODEL: u16 = 0xA434;
+const TAG_GPS_VERSION: u16 = 0x0000;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+
+/// Photo metadata parsed from `key=value, key=value`
+///
+/// Keys: `camera` (make and model, e.g. `Fujifilm X-T4`), `make`, `model`,
+/// `lens`, `iso`, `date` (`YYYY-MM-DD[ HH:MM[:SS]]`), `f` (aperture),
+/// `shutter` (e.g. `1/250`), `focal` (mm), `lat` and `lon`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FakeExif {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    pub iso: Option<u16>,
+    pub date: Option<NaiveDateTime>,
+    pub f_number: Option<f64>,
+    pub exposure: Option<(u32, u32)>,
+    pub focal_length: Option<f64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl FromStr for FakeExif {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, Self::Err> {
+        let mut exif = FakeExif::default();
+
+        for field in spec.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .map(|(k, v)| (k.trim().to_lowercase(), v.trim()))
+                .ok_or_else(|| format!("expected key=value, got '{}'", field))?;
+            let number = |value: &str| {
+                value
+                    .trim_start_matches(['f', 'F', '/'])
+                    .trim_end_matches("mm")
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite() && *n > 0.0)
+                    .ok_or_else(|| format!("invalid {} '{}'", key, value))
+            };
+
+            match key.as_str() {
+                "camera" => match value.split_once(char::is_whitespace) {
+                    Some((make, model)) => {
+                        exif.make.get_or_insert_with(|| make.to_string());
+                        exif.model.get_or_insert_with(|| model.trim().to_string());
+                    }
+                    None => exif.model = Some(value.to_string()),
+                },
+                "make" => exif.make = Some(value.to_string()),
+                "model" => exif.model = Some(value.to_string()),
+                "lens" => exif.lens = Some(value.to_string()),
+                "iso" => {
+                    exif.iso = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid iso '{}'", value))?,
+                    )
+                }
+                "date" => exif.date = Some(parse_date(value)?),
+                "f" | "aperture" => exif.f_number = Some(number(value)?),
+                "shutter" | "exposure" => exif.exposure = Some(parse_exposure(value)?),
+                "focal" => exif.focal_length = Some(number(value)?),
+                "lat" => exif.latitude = Some(parse_coordinate(value, 90.0)?),
+                "lon" => exif.longitude = Some(parse_coordinate(value, 180.0)?),
+                _ => return Err(format!("unknown EXIF field '{}'", key)),
+            }
+        }
+
+        if exif.latitude.is_some() != exif.longitude.is_some() {
+            return Err("lat and lon must be given together".to_string());
+        }
+        Ok(exif)
+    }
+}
+
+fn parse_date(value: &str) -> std::result::Result<NaiveDateTime, String> {
+    let value = value.replace('T', " ");
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y:%m:%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&value, format).ok())
+        .or_else(|| {
+            // A bare date gets a plausible midday time
+            NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(12, 0, 0))
+        })
+        .ok_or_else(|| format!("invalid date '{}' (use YYYY-MM-DD[ HH:MM[:SS]])", value))
+}
+
+fn parse_exposure(value: &str) -> std::result::Result<(u32, u32), String> {
+    let invalid = || format!("invalid shutter '{}' (e.g. 1/250 or 2)", value);
+    let value = value.trim_end_matches('s');
+    match value.split_once('/') {
+        Some((num, den)) => {
+            let num = num.trim().parse::<u32>().map_err(|_| invalid())?;
+            let den = den.trim().parse::<u32>().map_err(|_| invalid())?;
+            (num > 0 && den > 0)
+                .then_some((num, den))
+                .ok_or_else(invalid)
+        }
+        None => value
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite() && *n > 0.0)
+            .map(rational)
+            .ok_or_else(invalid),
+    }
+}
+
+fn parse_coordinate(value: &str, limit: f64) -> std::result::Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.abs() <= limit)
+        .ok_or_else(|| format!("invalid coordinate '{}'", value))
+}
+
+/// Approximate a positive decimal as a rational with two decimal places
+fn rational(value: f64) -> (u32, u32) {
+    ((value * 100.0).round() as u32, 100)
+}
+
+/// Degrees/minutes/seconds as three rationals, the GPS IFD encoding
+fn dms(value: f64) -> Vec<(u32, u32)> {
+    // Round once to hundredths of a second so 59.999s carries into the
+    // minutes instead of becoming 60.00s
+    let hundredths = (value.abs() * 360_000.0).round() as u64;
+    let seconds = hundredths % 6000;
+    let minutes = hundredths / 6000 % 60;
+    let degrees = hundredths / 360_000;
+    vec![
+        (degrees as u32, 1),
+        (minutes as u32, 1),
+        (seconds as u32, 100),
+    ]
+}
+
+enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(u16),
+    Long(u32),
+    Rational(Vec<(u32, u32)>),
+    Undefined(Vec<u8>),
+}
+
+impl Value {
+    fn type_and_count(&self) -> (u16, u32) {
+        match self {
+            Value::Byte(bytes) => (1, bytes.len() as u32),
+            Value::Ascii(text) => (2, text.len() as u32 + 1),
+            Value::Short(_) => (3, 1),
+            Value::Long(_) => (4, 1),
+            Value::Rational(values) => (5, values.len() as u32),
+            Value::Undefined(bytes) => (7, bytes.len() as u32),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Byte(bytes) | Value::Undefined(bytes) => bytes.clone(),
+            Value::Ascii(text) => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                bytes
+            }
+            Value::Short(n) => n.to_le_bytes().to_vec(),
+            Value::Long(n) => n.to_le_bytes().to_vec(),
+            Value::Rational(values) => values
+                .iter()
+                .flat_map(|(num, den)| num.to_le_bytes().into_iter().chain(den.to_le_bytes()))
+                .collect(),
+        }
+    }
+}
+
+/// Bytes an IFD occupies, including values stored out of line
+fn ifd_len(entries: &[(u16, Value)]) -> u32 {
+    let data: usize = entries
+        .iter()
+        .map(|(_, value)| value.bytes().len())
+        .filter(|&len| len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    (2 + 12 * entries.len() + 4 + data) as u32
+}
+
+/// Append an IFD at `out.len()` (its TIFF offset) with its out-of-line values after it
+fn write_ifd(out: &mut Vec<u8>, entries: &mut [(u16, Value)]) {
+    entries.sort_by_key(|(tag, _)| *tag);
+    let start = out.len();
+    let mut data_offset = start + 2 + 12 * entries.len() + 4;
+    let mut data = Vec::new();
+
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, value) in entries.iter() {
+        let (kind, count) = value.type_and_count();
+        let bytes = value.bytes();
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        if bytes.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..bytes.len()].copy_from_slice(&bytes);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(data_offset as u32).to_le_bytes());
+            data.extend_from_slice(&bytes);
+            if bytes.len() % 2 == 1 {
+                data.push(0);
+            }
+            data_offset += bytes.len() + bytes.len() % 2;
+        }
+    }
+    // No next IFD
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&data);
+}
+
+impl FakeExif {
+    /// Encode as a TIFF structure, as carried by PNG `eXIf` and JPEG APP1
+    pub fn to_tiff(&self) -> Vec<u8> {
+        let date = self
+            .date
+            .map(|date| date.format("%Y:%m:%d %H:%M:%S").to_string());
+
+        let mut ifd0 = Vec::new();
+        if let Some(make) = &self.make {
+            ifd0.push((TAG_MAKE, Value::Ascii(make.clone())));
+        }
+        if let Some(model) = &self.model {
+            ifd0.push((TAG_MODEL, Value::Ascii(model.clone())));
+        }
+        if let Some(date) = &date {
+            ifd0.push((TAG_DATE_TIME, Value::Ascii(date.clone())));
+        }
+
+        let mut exif_ifd = vec![(TAG_EXIF_VERSION, Value::Undefined(b"0232".to_vec()))];
+        if let Some(exposure) = self.exposure {
+            exif_ifd.push((TAG_EXPOSURE_TIME, Value::Rational(vec![exposure])));
+        }
+        if let Some(f_number) = self.f_number {
+            exif_ifd.push((TAG_F_NUMBER, Value::Rational(vec![rational(f_number)])));
+        }
+        if let Some(iso) = self.iso {
+            exif_ifd.push((TAG_ISO, Value::Short(iso)));
+        }
+        if let Some(date) = &date {
+            exif_ifd.push((TAG_DATE_TIME_ORIGINAL, Value::Ascii(date.clone())));
+            exif_ifd.push((TAG_DATE_TIME_DIGITIZED, Value::Ascii(date.clone())));
+        }
+        if let Some(focal_length) = self.focal_length {
+            exif_ifd.push((
+                TAG_FOCAL_LENGTH,
+                Value::Rational(vec![rational(focal_length)]),
+            ));
+        }
+        if let Some(lens) = &self.lens {
+            exif_ifd.push((TAG_LENS_MODEL, Value::Ascii(lens.clone())));
+        }
+
+        let mut gps_ifd = Vec::new();
+        if let (Some(lat), Some(lon)) = (self.latitude, self.longitude) {
+            let lat_ref = if lat < 0.0 { "S" } else { "N" };
+            let lon_ref = if lon < 0.0 { "W" } else { "E" };
+            gps_ifd.push((TAG_GPS_VERSION, Value::Byte(vec![2, 3, 0, 0])));
+            gps_ifd.push((TAG_GPS_LATITUDE_REF, Value::Ascii(lat_ref.to_string())));
+            gps_ifd.push((TAG_GPS_LATITUDE, Value::Rational(dms(lat))));
+            gps_ifd.push((TAG_GPS_LONGITUDE_REF, Value::Ascii(lon_ref.to_string())));
+            gps_ifd.push((TAG_GPS_LONGITUDE, Value::Rational(dms(lon))));
+        }
+
+        // Pointer entries are inline LONGs, so IFD0's size is known before
+        // their values are
+        ifd0.push((TAG_EXIF_IFD, Value::Long(0)));
+        if !gps_ifd.is_empty() {
+            ifd0.push((TAG_GPS_IFD, Value::Long(0)));
+        }
+        let exif_offset = 8 + ifd_len(&ifd0);
+        let gps_offset = exif_offset + ifd_len(&exif_ifd);
+        for (tag, value) in ifd0.iter_mut() {
+            match *tag {
+                TAG_EXIF_IFD => *value = Value::Long(exif_offset),
+                TAG_GPS_IFD => *value = Value::Long(gps_offset),
+                _ => {}
+            }
+        }
+
+        // Little-endian header pointing at IFD0 right after it
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&8u32.to_le_bytes());
+        write_ifd(&mut out, &mut ifd0);
+        write_ifd(&mut out, &mut exif_ifd);
+        if !gps_ifd.is_empty() {
+            write_ifd(&mut out, &mut gps_ifd);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type, count and value/offset field of `tag` in the IFD at `ifd`
+    fn entry(tiff: &[u8], ifd: usize, tag: u16) -> Option<(u16, u32, u32)> {
+        let u16_at = |at: usize| u16::from_le_bytes([tiff[at], tiff[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(tiff[at..at + 4].try_into().unwrap());
+        (0..u16_at(ifd) as usize)
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|&at| u16_at(at) == tag)
+            .map(|at| (u16_at(at + 2), u32_at(at + 4), u32_at(at + 8)))
+    }
+
+    fn ascii(tiff: &[u8], (kind, count, offset): (u16, u32, u32)) -> String {
+        assert_eq!(kind, 2);
+        let bytes = &tiff[offset as usize..offset as usize + count as usize];
+        String::from_utf8(bytes[..bytes.len() - 1].to_vec()).unwrap()
+    }
+
+    fn rationals(tiff: &[u8], (kind, count, offset): (u16, u32, u32)) -> Vec<(u32, u32)> {
+        assert_eq!(kind, 5);
+        (0..count as usize)
+            .map(|i| {
+                let at = offset as usize + 8 * i;
+                let word = |at: usize| u32::from_le_bytes(tiff[at..at + 4].try_into().unwrap());
+                (word(at), word(at + 4))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_spec() {
+        let exif: FakeExif = "camera=Fujifilm X-T4, lens=XF 35mm F1.4, iso=400, \
+                              date=2023-05-01 18:30, f=f/2.8, shutter=1/250s, focal=35mm, \
+                              lat=37.5665, lon=-126.978"
+            .parse()
+            .unwrap();
+        assert_eq!(exif.make.as_deref(), Some("Fujifilm"));
+        assert_eq!(exif.model.as_deref(), Some("X-T4"));
+        assert_eq!(exif.lens.as_deref(), Some("XF 35mm F1.4"));
+        assert_eq!(exif.iso, Some(400));
+        assert_eq!(
+            exif.date.unwrap().to_string(),
+            "2023-05-01 18:30:00".to_string()
+        );
+        assert_eq!(exif.f_number, Some(2.8));
+        assert_eq!(exif.exposure, Some((1, 250)));
+        assert_eq!(exif.focal_length, Some(35.0));
+        assert_eq!(exif.latitude, Some(37.5665));
+        assert_eq!(exif.longitude, Some(-126.978));
+    }
+
+    #[test]
+    fn bare_date_gets_midday() {
+        let exif: FakeExif = "date=2023-05-01".parse().unwrap();
+        assert_eq!(exif.date.unwrap().to_string(), "2023-05-01 12:00:00");
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for spec in [
+            "camera",
+            "flash=on",
+            "iso=high",
+            "iso=70000",
+            "date=yesterday",
+            "f=0",
+            "shutter=0/250",
+            "shutter=fast",
+            "lat=91, lon=0",
+            "lat=0, lon=181",
+        ] {
+            assert!(spec.parse::<FakeExif>().is_err(), "{} should fail", spec);
+        }
+    }
+
+    #[test]
+    fn requires_lat_and_lon_together() {
+        let err = "lat=37.5".parse::<FakeExif>().unwrap_err();
+        assert_eq!(err, "lat and lon must be given together");
+        assert!("lon=127".parse::<FakeExif>().is_err());
+    }
+
+    #[test]
+    fn dms_never_yields_sixty_seconds() {
+        assert_eq!(dms(1.9999999), vec![(2, 1), (0, 1), (0, 100)]);
+        assert_eq!(dms(-37.5), vec![(37, 1), (30, 1), (0, 100)]);
+        for i in 0..100_000 {
+            let value = f64::from(i) * 0.000_731;
+            let parts = dms(value);
+            assert!(parts[1].0 < 60, "{} gave {:?}", value, parts);
+            assert!(parts[2].0 < 6000, "{} gave {:?}", value, parts);
+        }
+    }
+
+    #[test]
+    fn writes_tags_at_their_offsets() {
+        let exif: FakeExif = "camera=Fujifilm X-T4, iso=400, date=2023-05-01 18:30, \
+                              shutter=1/250, lat=37.5, lon=-127.25"
+            .parse()
+            .unwrap();
+        let tiff = exif.to_tiff();
+        assert_eq!(&tiff[..8], b"II*\0\x08\0\0\0");
+
+        assert_eq!(ascii(&tiff, entry(&tiff, 8, TAG_MAKE).unwrap()), "Fujifilm");
+        assert_eq!(ascii(&tiff, entry(&tiff, 8, TAG_MODEL).unwrap()), "X-T4");
+        assert_eq!(
+            ascii(&tiff, entry(&tiff, 8, TAG_DATE_TIME).unwrap()),
+            "2023:05:01 18:30:00"
+        );
+
+        let (_, _, exif_ifd) = entry(&tiff, 8, TAG_EXIF_IFD).unwrap();
+        let exif_ifd = exif_ifd as usize;
+        assert_eq!(entry(&tiff, exif_ifd, TAG_ISO), Some((3, 1, 400)));
+        assert_eq!(
+            rationals(&tiff, entry(&tiff, exif_ifd, TAG_EXPOSURE_TIME).unwrap()),
+            vec![(1, 250)]
+        );
+        assert_eq!(
+            entry(&tiff, exif_ifd, TAG_EXIF_VERSION),
+            Some((7, 4, u32::from_le_bytes(*b"0232")))
+        );
+
+        let (_, _, gps_ifd) = entry(&tiff, 8, TAG_GPS_IFD).unwrap();
+        let gps_ifd = gps_ifd as usize;
+        assert_eq!(
+            entry(&tiff, gps_ifd, TAG_GPS_LATITUDE_REF),
+            Some((2, 2, u32::from(b'N')))
+        );
+        assert_eq!(
+            entry(&tiff, gps_ifd, TAG_GPS_LONGITUDE_REF),
+            Some((2, 2, u32::from(b'W')))
+        );
+        assert_eq!(
+            rationals(&tiff, entry(&tiff, gps_ifd, TAG_GPS_LONGITUDE).unwrap()),
+            vec![(127, 1), (15, 1), (0, 100)]
+        );
+        // The GPS IFD is the last structure in the block
+        assert_eq!(tiff.len(), gps_ifd + ifd_len_at(&tiff, gps_ifd));
+    }
+
+    #[test]
+    fn omits_gps_without_coordinates() {
+        let tiff = "model=X100V".parse::<FakeExif>().unwrap().to_tiff();
+        assert!(entry(&tiff, 8, TAG_GPS_IFD).is_none());
+        assert!(entry(&tiff, 8, TAG_EXIF_IFD).is_some());
+    }
+
+    /// Size of a written IFD including its out-of-line values
+    fn ifd_len_at(tiff: &[u8], ifd: usize) -> usize {
+        let count = u16::from_le_bytes([tiff[ifd], tiff[ifd + 1]]) as usize;
+        let data: usize = (0..count)
+            .map(|i| ifd + 2 + 12 * i)
+            .map(|at| {
+                let kind = u16::from_le_bytes([tiff[at + 2], tiff[at + 3]]);
+                let count = u32::from_le_bytes(tiff[at + 4..at + 8].try_into().unwrap()) as usize;
+                let size = match kind {
+                    3 => 2,
+                    4 => 4,
+                    5 => 8,
+                    _ => 1,
+                } * count;
+                if size > 4 {
+                    size + size % 2
+                } else {
+                    0
+                }
+            })
+            .sum();
+        2 + 12 * count + 4 + data
+    }
+}
@@ -0,0 +1,232 @@
+use crate::error::{ImagoError, Result};
+use crate::http;
+use crate::provider::{
+    download, parse_dimensions, request_id, retry_after, GeneratedImage, GenerationOptions,
+    GenerationResult,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::{Duration, Instant};
+
+const QUEUE_BASE_URL: &str = "https://queue.fal.run";
+const POLL_INTERVAL: Duration = Duration::from_millis(750);
+const MAX_WAIT: Duration = Duration::from_secs(600);
+
+/// fal.ai queue API client
+pub struct FalClient {
+    client: Client,
+    api_key: String,
+    model: String,
+}
+
+/// Response from submitting a request to the queue
+#[derive(Debug, Deserialize)]
+struct QueueSubmission {
+    status_url: String,
+    response_url: String,
+}
+
+/// Queue status while polling
+#[derive(Debug, Deserialize)]
+struct QueueStatus {
+    status: String,
+    queue_position: Option<u32>,
+}
+
+/// Final generation result
+#[derive(Debug, Deserialize)]
+struct FalResult {
+    images: Vec<FalImage>,
+    seed: Option<u64>,
+    has_nsfw_concepts: Option<Vec<bool>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FalImage {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    detail: Value,
+}
+
+impl FalClient {
+    /// Create a new fal.ai client
+    pub fn new(api_key: String, model: String, client: Client) -> Self {
+        Self {
+            client,
+            api_key,
+            model,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Generate images from a text prompt
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let url = format!("{}/{}", QUEUE_BASE_URL, self.model);
+        let input = Self::build_input(prompt, options);
+        let request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::AUTHORIZATION, self.auth_header())
+            .json(&input);
+        let submission: QueueSubmission = self.send(request, Some(&input)).await?;
+
+        let started = Instant::now();
+        let mut last_reported = None;
+        loop {
+            let request = self
+                .client
+                .get(&submission.status_url)
+                .header(reqwest::header::AUTHORIZATION, self.auth_header());
+            let status: QueueStatus = self.send(request, None).await?;
+
+            if status.status == "COMPLETED" {
+                break;
+            }
+
+            let report = match status.queue_position {
+                Some(position) => format!("{} (queue position {})", status.status, position),
+                None => status.status.clone(),
+            };
+            if last_reported.as_ref() != Some(&report) {
+                tracing::info!("fal: {}", report);
+                last_reported = Some(report);
+            }
+
+            if started.elapsed() > MAX_WAIT {
+                return Err(ImagoError::Timeout);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let request = self
+            .client
+            .get(&submission.response_url)
+            .header(reqwest::header::AUTHORIZATION, self.auth_header());
+        let fal_result: FalResult = self.send(request, None).await?;
+
+        let (mut result, urls) =
+            tracing::info_span!("decode").in_scope(|| normalize(&self.model, &fal_result))?;
+        for url in urls {
+            result.images.push(GeneratedImage {
+                data: download(&self.client, "fal", &url).await?,
+                text: None,
+                seed: fal_result.seed,
+            });
+        }
+
+        Ok(result)
+    }
+
+    fn auth_header(&self) -> String {
+        format!("Key {}", self.api_key)
+    }
+
+    /// Map shared options onto fal's Flux input schema
+    fn build_input(prompt: &str, options: &GenerationOptions) -> Value {
+        let mut input = Map::new();
+        input.insert("prompt".to_string(), json!(prompt));
+        input.insert("num_images".to_string(), json!(options.count));
+        let format = if options.compressed { "jpeg" } else { "png" };
+        input.insert("output_format".to_string(), json!(format));
+
+        if let Some(size) = options.size.as_deref() {
+            let image_size = match parse_dimensions(size) {
+                Some((width, height)) => json!({ "width": width, "height": height }),
+                // Named presets such as `landscape_16_9` are passed through
+                None => json!(size),
+            };
+            input.insert("image_size".to_string(), image_size);
+        }
+        if let Some(seed) = options.seed {
+            input.insert("seed".to_string(), json!(seed));
+        }
+        if let Some(cfg_scale) = options.cfg_scale {
+            input.insert("guidance_scale".to_string(), json!(cfg_scale));
+        }
+        if let Some(negative) = options.negative_prompt.as_deref() {
+            input.insert("negative_prompt".to_string(), json!(negative));
+        }
+
+        Value::Object(input)
+    }
+
+    /// Send a queue request and parse the response; `body` is the JSON set
+    /// on `request`, if any
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        body: Option<&Value>,
+    ) -> Result<T> {
+        let reply = http::send("fal", request, body, &self.api_key).await?;
+        let status = reply.status;
+        let retry_after = retry_after(&reply.headers);
+        let request_id = request_id(&reply.headers);
+        let response_text = reply.text();
+
+        if !status.is_success() {
+            let message = match serde_json::from_str::<ErrorResponse>(&response_text) {
+                Ok(err) => match err.detail {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                },
+                Err(_) => response_text,
+            };
+            return Err(ImagoError::ApiError {
+                status: status.as_u16(),
+                message,
+                retry_after,
+                request_id,
+            });
+        }
+
+        serde_json::from_str(&response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })
+    }
+}
+
+/// Map a completed queue result onto a [`GenerationResult`] plus the image URLs
+/// still to be downloaded into it
+fn normalize(model: &str, fal_result: &FalResult) -> Result<(GenerationResult, Vec<String>)> {
+    if fal_result
+        .has_nsfw_concepts
+        .as_ref()
+        .is_some_and(|flags| flags.iter().any(|&f| f))
+    {
+        return Err(ImagoError::SafetyFilter(
+            "Image content flagged by fal.ai safety checker".to_string(),
+        ));
+    }
+
+    let urls: Vec<String> = fal_result.images.iter().map(|i| i.url.clone()).collect();
+    if urls.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok((GenerationResult::new("fal", model), urls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_queue_result() {
+        let fal_result: FalResult = serde_json::from_str(&fixture("fal/result.json")).unwrap();
+        let normalized = normalize("fal-ai/flux/schnell", &fal_result).unwrap();
+        assert_golden("fal/result.golden.json", &normalized);
+    }
+}
@@ -0,0 +1,366 @@
+//! `imago gallery`: browse the generation history in a terminal UI
+//!
+//! Runs are listed on the left and the selected image is previewed on the
+//! right with the terminal's graphics protocol (kitty, iTerm2, sixel, or half
+//! blocks as a fallback). Re-running leaves the UI and hands the run back to
+//! the caller, which owns the async generation path.
+
+use crate::error::{ImagoError, Result};
+use crate::history::{self, Filter, History, Run, RunStatus};
+use crate::trash;
+use base64::prelude::*;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use ratatui_image::picker::Picker;
+use ratatui_image::protocol::StatefulProtocol;
+use ratatui_image::StatefulImage;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+const HELP: &str =
+    "↑↓ select  ←→ image  o open  y copy path  r re-run  t tag  f fav  d delete  q quit";
+
+/// How the gallery was left
+pub enum Exit {
+    Quit,
+    /// Re-run the run with this id
+    Redo(i64),
+}
+
+enum Mode {
+    Browse,
+    /// Typing a tag for the selected run
+    Tag(String),
+    ConfirmDelete,
+}
+
+struct App {
+    history: History,
+    runs: Vec<Run>,
+    list: ListState,
+    /// Index of the previewed image within the selected run
+    image: usize,
+    picker: Picker,
+    preview: Option<StatefulProtocol>,
+    mode: Mode,
+    /// Result of the last action, shown instead of the key help
+    status: Option<String>,
+}
+
+/// Open the gallery on the runs matching `filter`
+pub fn run(filter: &Filter) -> Result<Exit> {
+    if !std::io::stdout().is_terminal() {
+        return Err(ImagoError::HistoryError(
+            "imago gallery needs an interactive terminal".to_string(),
+        ));
+    }
+    let history = History::open()?;
+    let runs = history.search(filter)?;
+    if runs.is_empty() {
+        println!("No matching generations");
+        return Ok(Exit::Quit);
+    }
+
+    let mut terminal = ratatui::try_init()?;
+    // The terminal answers graphics queries only once raw mode is on
+    let picker = Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 16)));
+    let mut app = App {
+        history,
+        runs,
+        list: ListState::default().with_selected(Some(0)),
+        image: 0,
+        picker,
+        preview: None,
+        mode: Mode::Browse,
+        status: None,
+    };
+    app.load_preview();
+
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| app.draw(frame)) {
+            break Err(e.into());
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if let Some(exit) = app.handle_key(key.code) {
+                    break Ok(exit);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn selected(&self) -> Option<&Run> {
+        self.list.selected().and_then(|i| self.runs.get(i))
+    }
+
+    /// Decode the selected image for the preview pane
+    fn load_preview(&mut self) {
+        let path = self
+            .selected()
+            .and_then(|run| run.images.get(self.image))
+            .map(|image| image.path.clone());
+        self.preview = path
+            .and_then(|path| image::open(path).ok())
+            .map(|image| self.picker.new_resize_protocol(image));
+    }
+
+    fn select(&mut self, index: usize) {
+        self.list.select(Some(index));
+        self.image = 0;
+        self.load_preview();
+    }
+
+    fn handle_key(&mut self, code: KeyCode) -> Option<Exit> {
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Tag(mut input) => {
+                match code {
+                    KeyCode::Enter => self.tag(input.trim()),
+                    KeyCode::Esc => {}
+                    KeyCode::Backspace => {
+                        input.pop();
+                        self.mode = Mode::Tag(input);
+                    }
+                    KeyCode::Char(c) => {
+                        input.push(c);
+                        self.mode = Mode::Tag(input);
+                    }
+                    _ => self.mode = Mode::Tag(input),
+                }
+                None
+            }
+            Mode::ConfirmDelete => {
+                if code == KeyCode::Char('y') {
+                    self.delete();
+                }
+                None
+            }
+            Mode::Browse => self.browse_key(code),
+        }
+    }
+
+    fn browse_key(&mut self, code: KeyCode) -> Option<Exit> {
+        self.status = None;
+        let selected = self.list.selected().unwrap_or(0);
+        let images = self.selected().map_or(0, |run| run.images.len());
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Exit::Quit),
+            KeyCode::Up | KeyCode::Char('k') if selected > 0 => self.select(selected - 1),
+            KeyCode::Down | KeyCode::Char('j') if selected + 1 < self.runs.len() => {
+                self.select(selected + 1)
+            }
+            KeyCode::Left | KeyCode::Char('h') if self.image > 0 => {
+                self.image -= 1;
+                self.load_preview();
+            }
+            KeyCode::Right | KeyCode::Char('l') if self.image + 1 < images => {
+                self.image += 1;
+                self.load_preview();
+            }
+            KeyCode::Char('o') => self.open(),
+            KeyCode::Char('y') => self.copy_path(),
+            KeyCode::Char('r') => return self.selected().map(|run| Exit::Redo(run.id)),
+            KeyCode::Char('t') => self.mode = Mode::Tag(String::new()),
+            KeyCode::Char('f') => self.tag(history::FAVORITE_TAG),
+            KeyCode::Char('d') => self.mode = Mode::ConfirmDelete,
+            _ => {}
+        }
+        None
+    }
+
+    fn image_path(&self) -> Option<String> {
+        self.selected()
+            .and_then(|run| run.images.get(self.image))
+            .map(|image| image.path.to_string_lossy().into_owned())
+    }
+
+    /// Open the image in the system viewer
+    fn open(&mut self) {
+        let Some(path) = self.image_path() else {
+            self.status = Some("This run has no image".to_string());
+            return;
+        };
+        let mut command = if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        } else {
+            Command::new("xdg-open")
+        };
+        let spawned = command
+            .arg(&path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        self.status = Some(match spawned {
+            Ok(_) => format!("Opened {}", path),
+            Err(e) => format!("Could not open {}: {}", path, e),
+        });
+    }
+
+    /// Copy the image path with an OSC 52 escape, which also works over SSH
+    fn copy_path(&mut self) {
+        let Some(path) = self.image_path() else {
+            self.status = Some("This run has no image".to_string());
+            return;
+        };
+        let sequence = format!("\x1b]52;c;{}\x07", BASE64_STANDARD.encode(&path));
+        let mut stdout = std::io::stdout();
+        self.status = Some(
+            match stdout
+                .write_all(sequence.as_bytes())
+                .and_then(|_| stdout.flush())
+            {
+                Ok(()) => format!("Copied {}", path),
+                Err(e) => format!("Could not copy the path: {}", e),
+            },
+        );
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let Some(index) = self.list.selected() else {
+            return;
+        };
+        if tag.is_empty() {
+            return;
+        }
+        let run = &mut self.runs[index];
+        self.status = Some(match self.history.add_tag(run.id, tag) {
+            Ok(()) => {
+                if !run.tags.iter().any(|existing| existing == tag) {
+                    run.tags.push(tag.to_string());
+                    run.tags.sort();
+                }
+                format!("Tagged #{} {}", run.id, tag)
+            }
+            Err(e) => e.to_string(),
+        });
+    }
+
+    /// Move the run's images to the trash and drop it from the history
+    fn delete(&mut self) {
+        let Some(index) = self.list.selected() else {
+            return;
+        };
+        let id = self.runs[index].id;
+        let trashed = self.runs[index]
+            .images
+            .iter()
+            .filter(|image| image.path.is_file())
+            .map(|image| trash::put(&image.path))
+            .collect::<Result<Vec<_>>>();
+        let outcome = trashed.and_then(|trashed| {
+            self.history.delete(id)?;
+            Ok(trashed.len())
+        });
+        match outcome {
+            Ok(files) => {
+                self.status = Some(format!(
+                    "Deleted #{}, {} file(s) moved to the trash (imago trash list)",
+                    id, files
+                ));
+                self.runs.remove(index);
+                if self.runs.is_empty() {
+                    self.list.select(None);
+                    self.preview = None;
+                } else {
+                    self.select(index.min(self.runs.len() - 1));
+                }
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self.runs.iter().map(list_item).collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" History ({}) ", self.runs.len())))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        self.draw_preview(frame, preview_area);
+
+        let footer_text = match &self.mode {
+            Mode::Tag(input) => format!("Tag: {}_  (Enter to save, Esc to cancel)", input),
+            Mode::ConfirmDelete => {
+                "Delete this run and move its images to the trash? (y/n)".to_string()
+            }
+            Mode::Browse => self.status.clone().unwrap_or_else(|| HELP.to_string()),
+        };
+        frame.render_widget(Paragraph::new(footer_text).dim(), footer);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(run) = self.list.selected().and_then(|i| self.runs.get(i)) else {
+            frame.render_widget(Block::bordered(), area);
+            return;
+        };
+        let title = match run.images.get(self.image) {
+            Some(image) => format!(
+                " {} ({}/{}) ",
+                image.path.display(),
+                self.image + 1,
+                run.images.len()
+            ),
+            None => " No image ".to_string(),
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        match &mut self.preview {
+            Some(preview) => frame.render_stateful_widget(StatefulImage::default(), inner, preview),
+            None => {
+                let message = match run.status {
+                    RunStatus::Failed => run.error.clone().unwrap_or_default(),
+                    _ if run.images.is_empty() => String::new(),
+                    _ => "File missing or unreadable".to_string(),
+                };
+                frame.render_widget(Paragraph::new(message).dim(), inner);
+            }
+        }
+    }
+}
+
+/// Two lines per run: id, time, model and tags, then the prompt
+fn list_item(run: &Run) -> ListItem<'static> {
+    let when = run
+        .timestamp
+        .get(..16)
+        .unwrap_or(&run.timestamp)
+        .replace('T', " ");
+    let mut header = vec![
+        Span::from(format!("#{} ", run.id)).bold(),
+        Span::from(format!("{}  ", when)),
+        Span::from(run.model.clone()).cyan(),
+    ];
+    if run.status == RunStatus::Failed {
+        header.push(Span::from("  failed").red());
+    }
+    if !run.tags.is_empty() {
+        header.push(Span::from(format!("  [{}]", run.tags.join(", "))).yellow());
+    }
+    ListItem::new(vec![
+        Line::from(header),
+        Line::from(format!("  {}", run.prompt)).dim(),
+    ])
+}
@@ -1,23 +1,44 @@
 use crate::error::{ImagoError, Result};
+use crate::http::{self, HttpSettings};
+use crate::progress::{Progress, Reporter};
+use crate::provider::{self, GeneratedImage, GenerationOptions, GenerationResult, Provider, Usage};
 use base64::prelude::*;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 
 const API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
-const DEFAULT_TIMEOUT: u64 = 120;
 const MODEL_FALLBACKS: [&str; 4] = [
     "gemini-2.5-flash-image",
     "gemini-3.1-flash-image-preview",
     "gemini-3-pro-image-preview",
     "gemini-2.0-flash-exp-image-generation",
 ];
+const SUPPORTED_ASPECT_RATIOS: [&str; 10] = [
+    "1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9",
+];
 
 /// Gemini API client
 pub struct GeminiClient {
     client: Client,
     api_key: String,
     model: String,
+    base_url: String,
+    timeout: Option<Duration>,
+    progress: Reporter,
+}
+
+/// Configures a [`GeminiClient`]; see [`GeminiClient::builder`]
+#[derive(Debug, Default)]
+pub struct GeminiClientBuilder {
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    timeout: Option<Duration>,
+    client: Option<Client>,
+    progress: Option<UnboundedSender<Progress>>,
 }
 
 /// Request payload for content generation
@@ -43,6 +64,16 @@ enum Part {
 struct GenerationConfig {
     #[serde(rename = "responseModalities")]
     response_modalities: Vec<String>,
+    #[serde(rename = "imageConfig", skip_serializing_if = "Option::is_none")]
+    image_config: Option<ImageConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImageConfig {
+    #[serde(rename = "aspectRatio")]
+    aspect_ratio: String,
 }
 
 /// Response from content generation
@@ -51,7 +82,6 @@ struct GenerateContentResponse {
     candidates: Option<Vec<Candidate>>,
     #[serde(rename = "promptFeedback")]
     prompt_feedback: Option<PromptFeedback>,
-    #[allow(dead_code)]
     #[serde(rename = "usageMetadata")]
     usage_metadata: Option<UsageMetadata>,
 }
@@ -61,7 +91,6 @@ struct Candidate {
     content: Option<CandidateContent>,
     #[serde(rename = "finishReason")]
     finish_reason: Option<String>,
-    #[allow(dead_code)]
     #[serde(rename = "safetyRatings")]
     safety_ratings: Option<Vec<SafetyRating>>,
 }
@@ -108,35 +137,56 @@ struct PromptFeedback {
 
 #[derive(Debug, Deserialize)]
 struct UsageMetadata {
-    #[allow(dead_code)]
     #[serde(rename = "promptTokenCount")]
     prompt_token_count: Option<u64>,
-    #[allow(dead_code)]
     #[serde(rename = "candidatesTokenCount")]
     candidates_token_count: Option<u64>,
-    #[allow(dead_code)]
     #[serde(rename = "totalTokenCount")]
     total_token_count: Option<u64>,
 }
 
 impl GeminiClient {
     /// Create a new Gemini client
-    pub fn new(api_key: String, model: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(DEFAULT_TIMEOUT))
-            .connect_timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to build HTTP client");
-
+    pub fn new(api_key: String, model: String, client: Client) -> Self {
         Self {
             client,
             api_key,
             model,
+            base_url: API_BASE_URL.to_string(),
+            timeout: None,
+            progress: Reporter::default(),
         }
     }
 
+    /// Report progress events to `sender` while generating
+    pub fn with_progress(mut self, sender: UnboundedSender<Progress>) -> Self {
+        self.progress = Reporter::new(sender);
+        self
+    }
+
+    /// Start configuring a client; only the API key is required
+    pub fn builder() -> GeminiClientBuilder {
+        GeminiClientBuilder::default()
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
     /// Generate an image from a text prompt
-    pub async fn generate_image(&self, prompt: &str) -> Result<(Vec<u8>, Option<String>)> {
+    pub async fn generate_image(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let image_config = options
+            .size
+            .as_deref()
+            .map(aspect_ratio_for_size)
+            .transpose()?
+            .map(|aspect_ratio| ImageConfig { aspect_ratio });
+
         let request = GenerateContentRequest {
             contents: vec![Content {
                 parts: vec![Part::Text {
@@ -145,48 +195,84 @@ impl GeminiClient {
             }],
             generation_config: GenerationConfig {
                 response_modalities: vec!["IMAGE".to_string()],
+                image_config,
+                seed: options.seed,
             },
         };
 
-        let response = self.send_request(&request).await?;
-        self.extract_image_data(response)
+        let (model, response_text) = self.send_request(&request).await?;
+        self.progress.emit(Progress::Decoding);
+        let mut result =
+            tracing::info_span!("decode").in_scope(|| normalize(&model, &response_text))?;
+        for image in &mut result.images {
+            image.seed = options.seed;
+        }
+        Ok(result)
     }
 
-    /// Send the API request
-    async fn send_request(
-        &self,
-        request: &GenerateContentRequest,
-    ) -> Result<GenerateContentResponse> {
+    /// Send the API request, returning the model that answered and the
+    /// streamed events merged into one generateContent response
+    async fn send_request(&self, request: &GenerateContentRequest) -> Result<(String, String)> {
+        let body = serde_json::to_value(request)?;
         let mut tried = Vec::new();
 
         for model in std::iter::once(self.model.as_str()).chain(MODEL_FALLBACKS.iter().copied()) {
             if tried.contains(&model.to_string()) {
                 continue;
             }
+            if let Some(previous) = tried.last() {
+                self.progress.emit(Progress::FallbackTriggered {
+                    model: previous.clone(),
+                    next: model.to_string(),
+                });
+            }
             tried.push(model.to_string());
+            self.progress.emit(Progress::ModelAttempted {
+                model: model.to_string(),
+            });
 
             let url = format!(
-                "{}/{}:generateContent?key={}",
-                API_BASE_URL, model, self.api_key
+                "{}/{}:streamGenerateContent?alt=sse&key={}",
+                self.base_url, model, self.api_key
             );
 
-            let response = self.client.post(&url).json(request).send().await?;
-            let status = response.status();
+            let mut http_request = self.client.post(&url).json(&body);
+            if let Some(timeout) = self.timeout {
+                http_request = http_request.timeout(timeout);
+            }
+            // Text parts (and thoughts) arrive well before the image, so pass
+            // them on as they come
+            let mut pending = Vec::new();
+            let mut on_chunk = |chunk: &[u8]| {
+                for text in stream_text(&mut pending, chunk) {
+                    tracing::info!("gemini: {}", text);
+                    self.progress.emit(Progress::PartialText { text });
+                }
+            };
+            let reply = http::send_streaming(
+                "gemini",
+                http_request,
+                Some(&body),
+                &self.api_key,
+                &self.progress,
+                &mut on_chunk,
+            )
+            .await?;
+            let status = reply.status;
+            let retry_after = provider::retry_after(&reply.headers);
+            let request_id = provider::request_id(&reply.headers);
+            let response_text = reply.text();
 
             if status.is_success() {
-                let response_text = response.text().await?;
-                let parsed: GenerateContentResponse = serde_json::from_str(&response_text)
-                    .map_err(|e| ImagoError::ResponseFormatError {
-                        message: format!("Failed to parse API response: {}", e),
-                    })?;
-                return Ok(parsed);
+                return Ok((model.to_string(), merge_stream(&response_text)?));
             }
 
-            let error_text = response.text().await.unwrap_or_default();
             if status.as_u16() != 404 {
                 return Err(ImagoError::ApiError {
                     status: status.as_u16(),
-                    message: error_text,
+                    retry_after: retry_after.or_else(|| retry_delay(&response_text)),
+                    request_id,
+                    message: response_text,
                 });
             }
         }
@@ -196,81 +282,363 @@ impl GeminiClient {
             tried.join(", ")
         )))
     }
+}
 
-    /// Extract image data from API response
-    fn extract_image_data(
-        &self,
-        response: GenerateContentResponse,
-    ) -> Result<(Vec<u8>, Option<String>)> {
-        // Check for prompt feedback (blocks, etc.)
-        if let Some(feedback) = response.prompt_feedback {
-            if let Some(reason) = feedback.block_reason {
-                return Err(ImagoError::SafetyFilter(format!(
-                    "Request blocked: {}",
-                    reason
-                )));
-            }
+impl GeminiClientBuilder {
+    /// Gemini API key
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Model tried first; defaults to the provider's default model
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Whole-request timeout, applied on top of whatever client is used
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// API root the model path is appended to, e.g. for a proxy or test server
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// HTTP client to send requests with, instead of one with default settings
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Report progress events to `sender` while generating
+    pub fn progress(mut self, sender: UnboundedSender<Progress>) -> Self {
+        self.progress = Some(sender);
+        self
+    }
+
+    /// Fails without an API key, or if a default HTTP client can't be built
+    pub fn build(self) -> Result<GeminiClient> {
+        let api_key = self
+            .api_key
+            .filter(|key| !key.is_empty())
+            .ok_or(ImagoError::MissingApiKey("GEMINI_API_KEY"))?;
+        let client = match self.client {
+            Some(client) => client,
+            None => HttpSettings::default().client()?,
+        };
+        Ok(GeminiClient {
+            client,
+            api_key,
+            model: self
+                .model
+                .unwrap_or_else(|| Provider::Gemini.default_model().to_string()),
+            base_url: self
+                .base_url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| API_BASE_URL.to_string()),
+            timeout: self.timeout,
+            progress: self.progress.map(Reporter::new).unwrap_or_default(),
+        })
+    }
+}
+
+/// Text parts in the complete `data:` lines of a streamed response so far;
+/// an incomplete last line stays in `pending` for the next chunk
+fn stream_text(pending: &mut Vec<u8>, chunk: &[u8]) -> Vec<String> {
+    pending.extend_from_slice(chunk);
+    let mut texts = Vec::new();
+    while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = pending.drain(..=end).collect();
+        let event = std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| line.trim().strip_prefix("data:"))
+            .and_then(|data| serde_json::from_str::<Value>(data.trim()).ok());
+        if let Some(parts) = event
+            .as_ref()
+            .and_then(|event| event["candidates"][0]["content"]["parts"].as_array())
+        {
+            texts.extend(
+                parts
+                    .iter()
+                    .filter_map(|part| part["text"].as_str())
+                    .filter(|text| !text.trim().is_empty())
+                    .map(str::to_string),
+            );
         }
+    }
+    texts
+}
 
-        // Get candidates
-        let candidates = response.candidates.ok_or_else(|| ImagoError::NoImageData)?;
-
-        let candidate = candidates
-            .into_iter()
-            .next()
-            .ok_or(ImagoError::NoImageData)?;
-
-        // Check finish reason
-        if let Some(reason) = candidate.finish_reason {
-            if reason != "STOP" {
-                // Check safety ratings for more info
-                if let Some(ratings) = candidate.safety_ratings {
-                    let blocked: Vec<_> = ratings
-                        .iter()
-                        .filter(|r| r.blocked.unwrap_or(false))
-                        .map(|r| format!("{}: {}", r.category, r.probability))
-                        .collect();
-
-                    if !blocked.is_empty() {
-                        return Err(ImagoError::SafetyFilter(blocked.join(", ")));
-                    }
+/// Merge the `data:` events of a streamed response into the single response
+/// `generateContent` would have returned: parts are concatenated, and later
+/// events win for everything else (finish reason, safety ratings, usage)
+fn merge_stream(body: &str) -> Result<String> {
+    let mut response = Map::new();
+    let mut candidate = Map::new();
+    let mut parts = Vec::new();
+    let mut has_candidate = false;
+
+    for data in body
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data:"))
+    {
+        let event: Value =
+            serde_json::from_str(data.trim()).map_err(|e| ImagoError::ResponseFormatError {
+                message: format!("Failed to parse API response: {}", e),
+            })?;
+        let Value::Object(mut event) = event else {
+            continue;
+        };
+        if let Some(Value::Array(candidates)) = event.remove("candidates") {
+            if let Some(Value::Object(mut first)) = candidates.into_iter().next() {
+                has_candidate = true;
+                if let Some(Value::Array(more)) = first
+                    .remove("content")
+                    .and_then(|mut content| content.get_mut("parts").map(Value::take))
+                {
+                    parts.extend(more);
                 }
+                candidate.extend(first);
+            }
+        }
+        response.extend(event);
+    }
 
-                if reason == "IMAGE_SAFETY" {
-                    return Err(ImagoError::SafetyFilter(
-                        "Image content blocked by safety filters".to_string(),
-                    ));
-                }
+    if response.is_empty() && !has_candidate {
+        return Err(ImagoError::ResponseFormatError {
+            message: "Failed to parse API response: no events in the stream".to_string(),
+        });
+    }
+    if has_candidate {
+        candidate.insert("content".to_string(), json!({ "parts": parts }));
+        response.insert("candidates".to_string(), json!([candidate]));
+    }
+    Ok(Value::Object(response).to_string())
+}
+
+/// Map a raw generateContent response onto a [`GenerationResult`]
+fn normalize(model: &str, response_text: &str) -> Result<GenerationResult> {
+    let response: GenerateContentResponse =
+        serde_json::from_str(response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })?;
+
+    let mut result = GenerationResult::new("gemini", model);
+    result.usage = response.usage_metadata.map(|usage| Usage {
+        input_tokens: usage.prompt_token_count,
+        output_tokens: usage.candidates_token_count,
+        total_tokens: usage.total_token_count,
+    });
+
+    // Check for prompt feedback (blocks, etc.)
+    if let Some(feedback) = response.prompt_feedback {
+        if let Some(reason) = feedback.block_reason {
+            return Err(ImagoError::SafetyFilter(format!(
+                "Request blocked: {}",
+                reason
+            )));
+        }
+    }
+
+    // Get candidates
+    let candidates = response.candidates.ok_or(ImagoError::NoImageData)?;
+
+    let candidate = candidates
+        .into_iter()
+        .next()
+        .ok_or(ImagoError::NoImageData)?;
+
+    result.safety = candidate
+        .safety_ratings
+        .unwrap_or_default()
+        .into_iter()
+        .map(|r| provider::SafetyRating {
+            category: r.category,
+            probability: Some(r.probability),
+            blocked: r.blocked.unwrap_or(false),
+        })
+        .collect();
+    result.finish_reason = candidate.finish_reason;
+
+    // Check finish reason
+    if let Some(reason) = result.finish_reason.as_deref() {
+        if reason != "STOP" {
+            // Check safety ratings for more info
+            let blocked: Vec<_> = result
+                .safety
+                .iter()
+                .filter(|r| r.blocked)
+                .map(|r| {
+                    format!(
+                        "{}: {}",
+                        r.category,
+                        r.probability.as_deref().unwrap_or("UNKNOWN")
+                    )
+                })
+                .collect();
+
+            if !blocked.is_empty() {
+                return Err(ImagoError::SafetyFilter(blocked.join(", ")));
+            }
+
+            if reason == "IMAGE_SAFETY" {
+                return Err(ImagoError::SafetyFilter(
+                    "Image content blocked by safety filters".to_string(),
+                ));
             }
         }
+    }
 
-        // Extract content
-        let content = candidate.content.ok_or(ImagoError::NoImageData)?;
-        let mut text_response = None;
-
-        // Find the inline data part
-        for part in content.parts {
-            match part {
-                ResponsePart::InlineData { inline_data } => {
-                    if inline_data.mime_type.starts_with("image/") {
-                        let image_bytes = BASE64_STANDARD.decode(&inline_data.data)?;
-                        return Ok((image_bytes, text_response));
-                    }
-                }
-                ResponsePart::Text { text } => {
-                    text_response = Some(text);
+    // Extract content
+    let content = candidate.content.ok_or(ImagoError::NoImageData)?;
+
+    for part in content.parts {
+        match part {
+            ResponsePart::InlineData { inline_data } => {
+                if inline_data.mime_type.starts_with("image/") {
+                    result.images.push(GeneratedImage {
+                        data: BASE64_STANDARD.decode(&inline_data.data)?,
+                        text: None,
+                        seed: None,
+                    });
                 }
             }
+            ResponsePart::Text { text } => {
+                result.text = Some(text);
+            }
         }
+    }
 
-        // If we got here and have text but no image, the model probably returned a message
-        if let Some(text) = text_response {
+    if result.images.is_empty() {
+        // Text but no image means the model probably returned a message
+        if let Some(text) = result.text {
             return Err(ImagoError::ApiResponseError(format!(
                 "Model returned text instead of image: {}",
                 text
             )));
         }
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+/// Map a `WIDTHxHEIGHT` size or `W:H` ratio onto a Gemini-supported aspect ratio
+fn aspect_ratio_for_size(size: &str) -> Result<String> {
+    let invalid = || {
+        ImagoError::InvalidArgument(format!(
+            "Unsupported size '{}' for Gemini. Use one of: {}",
+            size,
+            SUPPORTED_ASPECT_RATIOS.join(", ")
+        ))
+    };
+
+    let (w, h) = size
+        .split_once(['x', ':'])
+        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)))
+        .filter(|(w, h)| *w > 0 && *h > 0)
+        .ok_or_else(invalid)?;
+
+    let divisor = gcd(w, h);
+    let ratio = format!("{}:{}", w / divisor, h / divisor);
+    if SUPPORTED_ASPECT_RATIOS.contains(&ratio.as_str()) {
+        Ok(ratio)
+    } else {
+        Err(invalid())
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// `retryDelay` (e.g. `"37s"`) from the `RetryInfo` detail of a 429 body
+fn retry_delay(body: &str) -> Option<Duration> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    body["error"]["details"]
+        .as_array()?
+        .iter()
+        .find_map(|detail| detail["retryDelay"].as_str())
+        .and_then(|delay| delay.strip_suffix('s')?.parse::<f64>().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_image_response() {
+        let result = normalize("gemini-2.5-flash-image", &fixture("gemini/response.json")).unwrap();
+        assert_golden("gemini/response.golden.json", &result);
+    }
+
+    #[test]
+    fn reads_retry_delay_from_rate_limit_body() {
+        let body = r#"{"error": {"code": 429, "details": [
+            {"@type": "type.googleapis.com/google.rpc.QuotaFailure"},
+            {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "37s"}
+        ]}}"#;
+        assert_eq!(retry_delay(body), Some(Duration::from_secs(37)));
+        assert_eq!(retry_delay("not json"), None);
+
+        let huge = r#"{"error": {"details": [{"retryDelay": "1e20s"}]}}"#;
+        assert_eq!(retry_delay(huge), None);
+    }
+
+    #[test]
+    fn builder_requires_a_key_and_fills_in_defaults() {
+        assert!(matches!(
+            GeminiClient::builder().build(),
+            Err(ImagoError::MissingApiKey("GEMINI_API_KEY"))
+        ));
+
+        let client = GeminiClient::builder()
+            .api_key("key")
+            .base_url("http://localhost:8080/v1beta/models/")
+            .build()
+            .unwrap();
+        assert_eq!(client.model(), Provider::Gemini.default_model());
+        assert_eq!(client.base_url, "http://localhost:8080/v1beta/models");
+    }
+
+    #[test]
+    fn merges_streamed_events_into_one_response() {
+        let body = concat!(
+            "data: {\"candidates\": [{\"content\": {\"parts\": [{\"text\": \"Drawing a fox\"}]}}]}\r\n\r\n",
+            "data: {\"candidates\": [{\"content\": {\"parts\": [{\"inlineData\": ",
+            "{\"mimeType\": \"image/png\", \"data\": \"iVBORw==\"}}]}, \"finishReason\": \"STOP\"}], ",
+            "\"usageMetadata\": {\"totalTokenCount\": 1300}}\r\n\r\n",
+        );
+
+        let mut pending = Vec::new();
+        let (first, rest) = body.as_bytes().split_at(20);
+        assert!(stream_text(&mut pending, first).is_empty());
+        assert_eq!(stream_text(&mut pending, rest), vec!["Drawing a fox"]);
+
+        let result = normalize("gemini-2.5-flash-image", &merge_stream(body).unwrap()).unwrap();
+        assert_eq!(result.images.len(), 1);
+        assert_eq!(result.text.as_deref(), Some("Drawing a fox"));
+        assert_eq!(result.finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(
+            result.usage.and_then(|usage| usage.total_tokens),
+            Some(1300)
+        );
+    }
 
-        Err(ImagoError::NoImageData)
+    #[test]
+    fn blocked_image_is_a_safety_error() {
+        let err = normalize("gemini-2.5-flash-image", &fixture("gemini/blocked.json")).unwrap_err();
+        assert!(matches!(err, ImagoError::SafetyFilter(_)));
     }
 }
@@ -0,0 +1,49 @@
+//! Golden-file helpers for provider normalization tests
+//!
+//! Recorded provider responses live under `tests/golden/<provider>/`, next to
+//! the expected [`GenerationResult`](crate::provider::GenerationResult) JSON.
+//! Run the tests with `UPDATE_GOLDEN=1` to rewrite the expected files.
+
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Read a recorded provider response
+pub fn fixture(name: &str) -> String {
+    let path = golden_dir().join(name);
+    fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", path.display(), e))
+}
+
+/// Compare `value`, serialized as pretty JSON, against the golden file `name`
+pub fn assert_golden<T: Serialize>(name: &str, value: &T) {
+    let path = golden_dir().join(name);
+    let actual = serde_json::to_string_pretty(value).expect("serializable value") + "\n";
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create golden directory");
+        }
+        fs::write(&path, actual).expect("write golden file");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing golden file {}; run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    assert_eq!(
+        actual, expected,
+        "{} is out of date; run with UPDATE_GOLDEN=1 to update it",
+        name
+    );
+}
@@ -0,0 +1,536 @@
+//! Terminal graphics protocols written directly
+//!
+//! Used when a backend is picked with `--preview-backend`, so the image is
+//! drawn the way the user asked even where detection would choose another
+//! protocol, and for sixel terminals, which viuer can't draw on. Kitty and
+//! iTerm2 sizes are given in terminal cells and the terminal scales the
+//! image itself; sixel images are drawn pixel for pixel, so the caller sizes
+//! them with [`cell_size`].
+
+use base64::prelude::*;
+use image::RgbaImage;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Most base64 bytes the kitty protocol accepts in one escape sequence
+const KITTY_CHUNK: usize = 4096;
+/// Longest piece of a sequence GNU screen passes through at once
+const SCREEN_CHUNK: usize = 768;
+/// Red and blue levels in the sixel palette; green gets one more, since the
+/// eye tells more shades of it apart (6 × 7 × 6 = 252 colors)
+const SIXEL_LEVELS: u32 = 6;
+const SIXEL_GREEN_LEVELS: u32 = 7;
+/// Cell size assumed when the terminal doesn't report its pixel size
+const DEFAULT_CELL: (u32, u32) = (10, 20);
+/// How long a terminal gets to answer a query
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Terminal multiplexer the output goes through, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    None,
+    /// tmux drops graphics unless they are wrapped in its passthrough
+    /// sequence, which needs `set -g allow-passthrough on` (tmux 3.3+)
+    Tmux,
+    /// GNU screen passes device control strings through, in short pieces
+    Screen,
+    /// Zellij has no passthrough, but draws sixel images itself
+    Zellij,
+}
+
+impl Multiplexer {
+    pub fn detect() -> Self {
+        if std::env::var_os("TMUX").is_some() {
+            Multiplexer::Tmux
+        } else if std::env::var_os("ZELLIJ").is_some() {
+            Multiplexer::Zellij
+        } else if std::env::var_os("STY").is_some() {
+            Multiplexer::Screen
+        } else {
+            Multiplexer::None
+        }
+    }
+
+    /// Check if kitty and iTerm2 images get through; otherwise only sixel
+    /// or block characters will show
+    pub fn passes_graphics(self) -> bool {
+        self != Multiplexer::Zellij
+    }
+
+    /// Write one escape sequence so it reaches the outer terminal
+    fn write(self, out: &mut impl Write, sequence: &[u8]) -> io::Result<()> {
+        match self {
+            Multiplexer::None | Multiplexer::Zellij => out.write_all(sequence),
+            Multiplexer::Tmux => {
+                // Every ESC inside is doubled
+                let mut wrapped = b"\x1bPtmux;".to_vec();
+                for &byte in sequence {
+                    if byte == 0x1b {
+                        wrapped.push(0x1b);
+                    }
+                    wrapped.push(byte);
+                }
+                wrapped.extend_from_slice(b"\x1b\\");
+                out.write_all(&wrapped)
+            }
+            Multiplexer::Screen => {
+                // screen's buffer for one string is small, so the sequence
+                // goes out in pieces that the terminal joins back up
+                for piece in sequence.chunks(SCREEN_CHUNK) {
+                    out.write_all(b"\x1bP")?;
+                    out.write_all(piece)?;
+                    out.write_all(b"\x1b\\")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Finish an image `rows` tall
+    ///
+    /// A multiplexer never sees the image, so its cursor is still where the
+    /// image started; moving down past the image keeps text from being drawn
+    /// over it.
+    fn finish(self, out: &mut impl Write, rows: u32) -> io::Result<()> {
+        match self {
+            Multiplexer::None | Multiplexer::Zellij => writeln!(out)?,
+            Multiplexer::Tmux | Multiplexer::Screen => {
+                out.write_all("\n".repeat(rows as usize + 1).as_bytes())?
+            }
+        }
+        out.flush()
+    }
+}
+
+/// Terminal rows an image `width` × `height` pixels takes when drawn
+/// `columns` wide, with cells `cell` pixels in size
+pub fn rows(width: u32, height: u32, columns: u32, (cell_width, cell_height): (u32, u32)) -> u32 {
+    let pixels = u64::from(columns * cell_width) * u64::from(height) / u64::from(width.max(1));
+    (pixels.div_ceil(u64::from(cell_height.max(1))) as u32).max(1)
+}
+
+/// Rows `data` takes `columns` wide, or `rows` when given
+fn image_rows(data: &[u8], columns: u32, rows: Option<u32>) -> u32 {
+    rows.unwrap_or_else(|| {
+        image::ImageReader::new(io::Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .map_or(1, |(width, height)| {
+                self::rows(width, height, columns, cell_size())
+            })
+    })
+}
+
+/// Move the cursor `columns` to the right, where the next image starts
+pub fn indent(out: &mut impl Write, columns: u32) -> io::Result<()> {
+    if columns > 0 {
+        write!(out, "\x1b[{}C", columns)?;
+    }
+    Ok(())
+}
+
+/// Draw a PNG with the kitty graphics protocol, `columns` wide
+pub fn kitty(
+    out: &mut impl Write,
+    png: &[u8],
+    columns: u32,
+    rows: Option<u32>,
+    mux: Multiplexer,
+) -> io::Result<()> {
+    let encoded = BASE64_STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let mut sequence = Vec::with_capacity(chunk.len() + 64);
+        if i == 0 {
+            // Transmit and display at once, without replies from the terminal
+            write!(sequence, "\x1b_Gf=100,a=T,q=2,c={}", columns)?;
+            if let Some(rows) = rows {
+                write!(sequence, ",r={}", rows)?;
+            }
+            if matches!(mux, Multiplexer::Tmux | Multiplexer::Screen) {
+                // Leave the cursor alone; `finish` moves it for the multiplexer
+                sequence.extend_from_slice(b",C=1");
+            }
+            write!(sequence, ",m={};", more)?;
+        } else {
+            write!(sequence, "\x1b_Gm={};", more)?;
+        }
+        sequence.extend_from_slice(chunk);
+        sequence.extend_from_slice(b"\x1b\\");
+        mux.write(out, &sequence)?;
+    }
+    mux.finish(out, image_rows(png, columns, rows))
+}
+
+/// Draw an image of any format iTerm2 reads with its inline image protocol,
+/// `columns` wide
+pub fn iterm(
+    out: &mut impl Write,
+    data: &[u8],
+    columns: u32,
+    rows: Option<u32>,
+    mux: Multiplexer,
+) -> io::Result<()> {
+    let mut sequence = Vec::with_capacity(data.len() * 4 / 3 + 96);
+    write!(
+        sequence,
+        "\x1b]1337;File=inline=1;size={};width={}",
+        data.len(),
+        columns
+    )?;
+    if let Some(rows) = rows {
+        write!(sequence, ";height={}", rows)?;
+    }
+    write!(
+        sequence,
+        ";preserveAspectRatio=1:{}\x07",
+        BASE64_STANDARD.encode(data)
+    )?;
+    mux.write(out, &sequence)?;
+    mux.finish(out, image_rows(data, columns, rows))
+}
+
+/// Draw an image with sixel graphics, one sixel per pixel
+///
+/// Colors are mapped onto a fixed 252-color palette, and pixels that are
+/// mostly transparent are left unset so the background shows through.
+pub fn sixel(out: &mut impl Write, img: &RgbaImage, mux: Multiplexer) -> io::Result<()> {
+    let mut sequence = Vec::new();
+    encode_sixel(&mut sequence, img)?;
+    mux.write(out, &sequence)?;
+    let rows = img.height().div_ceil(cell_size().1.max(1));
+    mux.finish(out, rows)
+}
+
+fn encode_sixel(out: &mut impl Write, img: &RgbaImage) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    let colors: Vec<Option<usize>> = img
+        .pixels()
+        .map(|p| (p[3] >= 128).then(|| palette_index(p[0], p[1], p[2])))
+        .collect();
+
+    // P2 = 1: pixels without a color keep the background
+    write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height)?;
+    for (i, (r, g, b)) in palette().enumerate() {
+        write!(out, "#{};2;{};{};{}", i, r, g, b)?;
+    }
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let mut used = vec![false; palette_len()];
+        for y in rows.clone() {
+            for x in 0..width {
+                if let Some(color) = colors[(y * width + x) as usize] {
+                    used[color] = true;
+                }
+            }
+        }
+
+        // One pass over the band per color, returning to its start with `$`
+        let mut first = true;
+        for color in (0..used.len()).filter(|&c| used[c]) {
+            if !first {
+                out.write_all(b"$")?;
+            }
+            first = false;
+            write!(out, "#{}", color)?;
+
+            let mut run = (0u8, 0u32);
+            for x in 0..width {
+                let bits = rows.clone().fold(0u8, |bits, y| {
+                    if colors[(y * width + x) as usize] == Some(color) {
+                        bits | 1 << (y - top)
+                    } else {
+                        bits
+                    }
+                });
+                let sixel = b'?' + bits;
+                if sixel != run.0 && run.1 > 0 {
+                    write_run(out, run)?;
+                    run.1 = 0;
+                }
+                run = (sixel, run.1 + 1);
+            }
+            // Nothing to draw after the last pixel of this color
+            if run.0 != b'?' {
+                write_run(out, run)?;
+            }
+        }
+        out.write_all(b"-")?;
+    }
+    out.write_all(b"\x1b\\")
+}
+
+/// `count` copies of `sixel`, run-length encoded when that is shorter
+fn write_run(out: &mut impl Write, (sixel, count): (u8, u32)) -> io::Result<()> {
+    if count > 3 {
+        write!(out, "!{}{}", count, sixel as char)
+    } else {
+        out.write_all(&vec![sixel; count as usize])
+    }
+}
+
+fn palette_len() -> usize {
+    (SIXEL_LEVELS * SIXEL_GREEN_LEVELS * SIXEL_LEVELS) as usize
+}
+
+/// Palette colors as sixel percentages, in index order
+fn palette() -> impl Iterator<Item = (u32, u32, u32)> {
+    let percent = |level: u32, levels: u32| level * 100 / (levels - 1);
+    (0..SIXEL_LEVELS).flat_map(move |r| {
+        (0..SIXEL_GREEN_LEVELS).flat_map(move |g| {
+            (0..SIXEL_LEVELS).map(move |b| {
+                (
+                    percent(r, SIXEL_LEVELS),
+                    percent(g, SIXEL_GREEN_LEVELS),
+                    percent(b, SIXEL_LEVELS),
+                )
+            })
+        })
+    })
+}
+
+/// Closest palette entry to an RGB color
+fn palette_index(r: u8, g: u8, b: u8) -> usize {
+    let level = |value: u8, levels: u32| (u32::from(value) * (levels - 1) + 127) / 255;
+    let (r, g, b) = (
+        level(r, SIXEL_LEVELS),
+        level(g, SIXEL_GREEN_LEVELS),
+        level(b, SIXEL_LEVELS),
+    );
+    ((r * SIXEL_GREEN_LEVELS + g) * SIXEL_LEVELS + b) as usize
+}
+
+/// Check if the terminal says it draws sixel graphics
+///
+/// Asks once with a Primary Device Attributes query and remembers the
+/// answer; terminals that don't answer in time count as not supporting it.
+/// The Windows console can't be asked, and only Windows Terminal 1.22 and
+/// later draw sixel without telling its version, so Windows gets block
+/// characters unless sixel is picked with `--preview-backend sixel`.
+pub fn sixel_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        if !io::stdout().is_terminal() {
+            return false;
+        }
+        if cfg!(windows) {
+            return false;
+        }
+        query("\x1b[c", b'c').is_some_and(|reply| has_sixel_attribute(&reply))
+    })
+}
+
+/// `ESC [ ? 62 ; 4 ; 22 c` lists the terminal's features; 4 is sixel
+fn has_sixel_attribute(reply: &str) -> bool {
+    reply.rsplit_once("[?").is_some_and(|(_, attributes)| {
+        attributes
+            .trim_end_matches('c')
+            .split(';')
+            .any(|attribute| attribute == "4")
+    })
+}
+
+/// Row the cursor is on, counted from 1, as the terminal reports it
+pub fn cursor_row() -> Option<u32> {
+    // ESC [ row ; column R
+    let reply = query("\x1b[6n", b'R')?;
+    let (_, position) = reply.rsplit_once('[')?;
+    position.split(';').next()?.parse().ok()
+}
+
+/// Pixel size of one terminal cell, from the window size the terminal
+/// reports, or a common default
+pub fn cell_size() -> (u32, u32) {
+    window_size()
+        .filter(|size| size.pixels.0 > 0 && size.pixels.1 > 0)
+        .map_or(DEFAULT_CELL, |size| {
+            (size.pixels.0 / size.cells.0, size.pixels.1 / size.cells.1)
+        })
+}
+
+/// Columns and rows of the terminal
+pub fn terminal_size() -> Option<(u32, u32)> {
+    window_size().map(|size| size.cells)
+}
+
+/// Widest preview, in columns, that fits a terminal `terminal` cells in
+/// size: the full width less `margin`, narrowed for tall images so they fit
+/// the height too
+pub fn fit_columns(
+    (width, height): (u32, u32),
+    terminal: (u32, u32),
+    margin: (u32, u32),
+    (cell_width, cell_height): (u32, u32),
+) -> u32 {
+    let columns = terminal.0.saturating_sub(margin.0).max(1);
+    let rows = terminal.1.saturating_sub(margin.1).max(1);
+    let fits_height = u64::from(rows * cell_height) * u64::from(width)
+        / (u64::from(height.max(1)) * u64::from(cell_width.max(1)));
+    columns.min(fits_height.max(1) as u32)
+}
+
+/// Size of the terminal window in cells and in pixels; pixels are 0 when the
+/// terminal doesn't say
+struct WindowSize {
+    cells: (u32, u32),
+    pixels: (u32, u32),
+}
+
+/// Size of the terminal on stdout, or on stderr or stdin when stdout is
+/// redirected
+fn window_size() -> Option<WindowSize> {
+    let cells = terminal_size::terminal_size_of(io::stdout())
+        .or_else(|| terminal_size::terminal_size_of(io::stderr()))
+        .or_else(|| terminal_size::terminal_size_of(io::stdin()))
+        .map(|(width, height)| (u32::from(width.0), u32::from(height.0)))
+        .filter(|&(columns, rows)| columns > 0 && rows > 0)?;
+    Some(WindowSize {
+        cells,
+        pixels: window_pixels().unwrap_or((0, 0)),
+    })
+}
+
+/// Window size in pixels, which only the Unix ioctl reports
+#[cfg(unix)]
+fn window_pixels() -> Option<(u32, u32)> {
+    [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            // SAFETY: TIOCGWINSZ only writes a `winsize` into the struct passed
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            (unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0)
+                .then(|| (u32::from(size.ws_xpixel), u32::from(size.ws_ypixel)))
+        })
+}
+
+#[cfg(not(unix))]
+fn window_pixels() -> Option<(u32, u32)> {
+    None
+}
+
+/// Send `request` to the controlling terminal and read the reply up to and
+/// including `last`, with the terminal in raw mode meanwhile
+#[cfg(unix)]
+fn query(request: &str, last: u8) -> Option<String> {
+    use std::io::Read;
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let fd = tty.as_raw_fd();
+
+    // SAFETY: `termios` is plain data filled in by tcgetattr, and `fd` stays
+    // open for as long as `tty` lives
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+        return None;
+    }
+    let mut raw = saved;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return None;
+    }
+
+    let mut read_reply = || -> Option<Vec<u8>> {
+        tty.write_all(request.as_bytes()).ok()?;
+        tty.flush().ok()?;
+        let deadline = Instant::now() + QUERY_TIMEOUT;
+        let mut reply = Vec::new();
+        let mut byte = [0u8; 1];
+        while reply.last() != Some(&last) {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            let mut ready = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd
+            if unsafe { libc::poll(&mut ready, 1, left.as_millis() as libc::c_int) } <= 0 {
+                return None;
+            }
+            if tty.read(&mut byte).ok()? == 0 {
+                return None;
+            }
+            reply.push(byte[0]);
+        }
+        Some(reply)
+    };
+    let reply = read_reply();
+
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    String::from_utf8(reply?).ok()
+}
+
+#[cfg(not(unix))]
+fn query(_request: &str, _last: u8) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kitty_splits_large_images_into_chunks() {
+        let png = vec![0u8; KITTY_CHUNK];
+        let mut out = Vec::new();
+        kitty(&mut out, &png, 40, Some(20), Multiplexer::None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("\x1b_Gf=100,a=T,q=2,c=40,r=20,m=1;"));
+        assert_eq!(out.matches("\x1b_G").count(), 2);
+        assert!(out.contains("\x1b_Gm=0;"));
+        assert!(out.ends_with("\x1b\\\n"));
+    }
+
+    #[test]
+    fn sixel_encodes_bands_with_run_lengths() {
+        let red = image::Rgba([255, 0, 0, 255]);
+        let img = RgbaImage::from_pixel(8, 7, red);
+        let mut out = Vec::new();
+        sixel(&mut out, &img, Multiplexer::None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let index = palette_index(255, 0, 0);
+        assert!(out.starts_with("\x1bP0;1;0q\"1;1;8;7"));
+        // A full band of six rows, then the seventh row on its own
+        assert!(out.contains(&format!("#{}!8~-#{}!8@-", index, index)));
+        assert!(out.ends_with("\x1b\\\n"));
+    }
+
+    #[test]
+    fn tmux_passthrough_doubles_escapes() {
+        let mut out = Vec::new();
+        Multiplexer::Tmux
+            .write(&mut out, b"\x1b_Gm=0;AAAA\x1b\\")
+            .unwrap();
+        assert_eq!(out, b"\x1bPtmux;\x1b\x1b_Gm=0;AAAA\x1b\x1b\\\x1b\\");
+    }
+
+    #[test]
+    fn fits_wide_images_to_the_width_and_tall_ones_to_the_height() {
+        let terminal = (120, 40);
+        let margin = (2, 4);
+        let cell = (10, 20);
+        // Square: 36 rows of 20 px fit 72 columns of 10 px
+        assert_eq!(fit_columns((1024, 1024), terminal, margin, cell), 72);
+        // Wide: the width less the margin
+        assert_eq!(fit_columns((1920, 480), terminal, margin, cell), 118);
+        // Tall: 36 rows of 20 px are 720 px, a quarter of that across
+        assert_eq!(fit_columns((480, 1920), terminal, margin, cell), 18);
+    }
+
+    #[test]
+    fn reads_sixel_from_device_attributes() {
+        assert!(has_sixel_attribute("\x1b[?62;4;22c"));
+        assert!(!has_sixel_attribute("\x1b[?62;22c"));
+        assert!(!has_sixel_attribute("\x1b[?1;2c"));
+    }
+}
@@ -0,0 +1,576 @@
+//! Generation history in a local SQLite database
+//!
+//! Every generation is recorded in `<data dir>/imago/history.db`: one `runs`
+//! row with the prompt, settings, usage and outcome, and one `images` row per
+//! saved file, plus any tags. Failed runs are kept too, with their error. The
+//! database is the basis for searching, redoing and browsing past generations.
+
+use crate::error::{ImagoError, Result};
+use crate::provider::Usage;
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+use clap::ValueEnum;
+use rusqlite::{params, params_from_iter, Connection};
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const DB_FILE: &str = "history.db";
+/// Bumped with every schema change; see [`History::migrate`]
+const SCHEMA_VERSION: i32 = 3;
+/// Recent runs [`History::typical_duration`] looks at, and how many it needs
+const TYPICAL_SAMPLE: i64 = 20;
+const MIN_TYPICAL_SAMPLE: usize = 3;
+/// Tag set by `imago fav`
+pub const FAVORITE_TAG: &str = "fav";
+
+/// Outcome of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Succeeded,
+    /// Served from the response cache without calling the provider
+    Cached,
+    Failed,
+}
+
+impl RunStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Cached => "cached",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(text: &str) -> RunStatus {
+        match text {
+            "succeeded" => RunStatus::Succeeded,
+            "cached" => RunStatus::Cached,
+            _ => RunStatus::Failed,
+        }
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One generation: the request, its outcome and the images it saved
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    /// Row id; 0 until recorded
+    pub id: i64,
+    pub timestamp: String,
+    /// Prompt as sent, style preset included
+    pub prompt: String,
+    pub provider: String,
+    pub model: String,
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub count: u32,
+    pub seed: Option<u64>,
+    pub cfg_scale: Option<f32>,
+    pub negative_prompt: Option<String>,
+    pub usage: Usage,
+    /// How long the provider took to answer; `None` for cache hits and runs
+    /// recorded before this was tracked
+    pub duration_ms: Option<u64>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    pub images: Vec<HistoryImage>,
+    /// Sorted by name
+    pub tags: Vec<String>,
+}
+
+/// A saved image of a run
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryImage {
+    pub path: PathBuf,
+    pub seed: Option<u64>,
+}
+
+/// Format of `imago history export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// An array of runs with their images and tags
+    Json,
+    /// One row per run; image paths and tags joined with `;`
+    Csv,
+}
+
+const CSV_HEADER: [&str; 18] = [
+    "id",
+    "timestamp",
+    "prompt",
+    "provider",
+    "model",
+    "size",
+    "quality",
+    "count",
+    "seed",
+    "cfg_scale",
+    "negative_prompt",
+    "input_tokens",
+    "output_tokens",
+    "total_tokens",
+    "status",
+    "error",
+    "tags",
+    "images",
+];
+
+/// Which runs to list; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Text the prompt must contain (case-insensitive)
+    pub search: Option<String>,
+    /// Text the model name must contain
+    pub model: Option<String>,
+    /// Tag the run must have
+    pub tag: Option<String>,
+    /// RFC 3339 timestamp of the oldest run to include
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Parse `--since`: a duration back from now (`30m`, `12h`, `7d`, `2w`) or a
+/// date (`YYYY-MM-DD`), as an RFC 3339 timestamp comparable with stored ones
+pub fn parse_since(text: &str) -> Result<String> {
+    let invalid = || {
+        ImagoError::HistoryError(format!(
+            "--since expects a duration like 7d, 12h or 2w, or a date (YYYY-MM-DD), got '{}'",
+            text
+        ))
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        let local = Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .ok_or_else(invalid)?;
+        return Ok(local.to_rfc3339());
+    }
+
+    let split = text.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = text.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        _ => return Err(invalid()),
+    };
+    Ok((Local::now() - duration).to_rfc3339())
+}
+
+pub fn db_path() -> Result<PathBuf> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("imago").join(DB_FILE))
+        .ok_or_else(|| ImagoError::HistoryError("No data directory available".to_string()))
+}
+
+/// Handle to the history database
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Open (or create) the default database
+    pub fn open() -> Result<History> {
+        Self::open_at(&db_path()?)
+    }
+
+    pub fn open_at(path: &Path) -> Result<History> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(db_error)?;
+        // Concurrent imago processes (batches, detached jobs) write to the same file
+        conn.busy_timeout(std::time::Duration::from_secs(5))
+            .map_err(db_error)?;
+        let history = History { conn };
+        history.migrate()?;
+        Ok(history)
+    }
+
+    /// Create or upgrade the schema, tracked with `PRAGMA user_version`
+    fn migrate(&self) -> Result<()> {
+        let version: i32 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(db_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(ImagoError::HistoryError(format!(
+                "History database uses schema {}; this imago supports up to {}",
+                version, SCHEMA_VERSION
+            )));
+        }
+        if version < 1 {
+            self.conn
+                .execute_batch(
+                    "CREATE TABLE runs (
+                        id INTEGER PRIMARY KEY,
+                        timestamp TEXT NOT NULL,
+                        prompt TEXT NOT NULL,
+                        provider TEXT NOT NULL,
+                        model TEXT NOT NULL,
+                        size TEXT,
+                        quality TEXT,
+                        count INTEGER NOT NULL,
+                        seed INTEGER,
+                        cfg_scale REAL,
+                        negative_prompt TEXT,
+                        input_tokens INTEGER,
+                        output_tokens INTEGER,
+                        total_tokens INTEGER,
+                        status TEXT NOT NULL,
+                        error TEXT
+                    );
+                    CREATE TABLE images (
+                        id INTEGER PRIMARY KEY,
+                        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+                        path TEXT NOT NULL,
+                        seed INTEGER
+                    );
+                    CREATE INDEX runs_timestamp ON runs(timestamp);
+                    CREATE INDEX images_run ON images(run_id);
+                    PRAGMA user_version = 1;",
+                )
+                .map_err(db_error)?;
+        }
+        if version < 2 {
+            self.conn
+                .execute_batch(
+                    "CREATE TABLE tags (
+                        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+                        tag TEXT NOT NULL,
+                        PRIMARY KEY (run_id, tag)
+                    );
+                    PRAGMA user_version = 2;",
+                )
+                .map_err(db_error)?;
+        }
+        if version < 3 {
+            self.conn
+                .execute_batch(
+                    "ALTER TABLE runs ADD COLUMN duration_ms INTEGER;
+                    PRAGMA user_version = 3;",
+                )
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    /// Store a run and its images, returning the new run id
+    pub fn record(&mut self, run: &Run) -> Result<i64> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO runs (timestamp, prompt, provider, model, size, quality, count, seed,
+                cfg_scale, negative_prompt, input_tokens, output_tokens, total_tokens, status, error,
+                duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                run.timestamp,
+                run.prompt,
+                run.provider,
+                run.model,
+                run.size,
+                run.quality,
+                run.count,
+                run.seed.map(|seed| seed as i64),
+                run.cfg_scale,
+                run.negative_prompt,
+                run.usage.input_tokens.map(|n| n as i64),
+                run.usage.output_tokens.map(|n| n as i64),
+                run.usage.total_tokens.map(|n| n as i64),
+                run.status.as_str(),
+                run.error,
+                run.duration_ms.map(|ms| ms as i64),
+            ],
+        )
+        .map_err(db_error)?;
+        let id = tx.last_insert_rowid();
+        for image in &run.images {
+            tx.execute(
+                "INSERT INTO images (run_id, path, seed) VALUES (?1, ?2, ?3)",
+                params![
+                    id,
+                    image.path.to_string_lossy(),
+                    image.seed.map(|seed| seed as i64)
+                ],
+            )
+            .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+        Ok(id)
+    }
+
+    /// Tag a run; tagging it twice with the same tag is a no-op
+    pub fn add_tag(&self, id: i64, tag: &str) -> Result<()> {
+        self.require(id)?;
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO tags (run_id, tag) VALUES (?1, ?2)",
+                params![id, tag],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Remove a tag from a run; returns whether it had the tag
+    pub fn remove_tag(&self, id: i64, tag: &str) -> Result<bool> {
+        self.require(id)?;
+        let removed = self
+            .conn
+            .execute(
+                "DELETE FROM tags WHERE run_id = ?1 AND tag = ?2",
+                params![id, tag],
+            )
+            .map_err(db_error)?;
+        Ok(removed > 0)
+    }
+
+    /// Forget a run with its images and tags; the image files are left alone
+    #[cfg(feature = "gallery")]
+    pub fn delete(&mut self, id: i64) -> Result<()> {
+        self.require(id)?;
+        let tx = self.conn.transaction().map_err(db_error)?;
+        for table in ["images", "tags"] {
+            tx.execute(&format!("DELETE FROM {} WHERE run_id = ?1", table), [id])
+                .map_err(db_error)?;
+        }
+        tx.execute("DELETE FROM runs WHERE id = ?1", [id])
+            .map_err(db_error)?;
+        tx.commit().map_err(db_error)
+    }
+
+    fn require(&self, id: i64) -> Result<()> {
+        let exists: bool = self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM runs WHERE id = ?1)",
+                [id],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        if exists {
+            Ok(())
+        } else {
+            Err(ImagoError::HistoryError(format!("No run with id {}", id)))
+        }
+    }
+
+    /// A run by id, with its images
+    pub fn get(&self, id: i64) -> Result<Option<Run>> {
+        let query = format!("{} WHERE id = ?1", SELECT_RUNS);
+        let run = match self.conn.query_row(&query, [id], run_from_row) {
+            Ok(run) => run,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(db_error(e)),
+        };
+        self.with_details(run).map(Some)
+    }
+
+    /// Runs matching `filter`, newest first, with their images
+    pub fn search(&self, filter: &Filter) -> Result<Vec<Run>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+        if let Some(text) = &filter.search {
+            values.push(like_pattern(text));
+            conditions.push(format!("prompt LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+        if let Some(model) = &filter.model {
+            values.push(like_pattern(model));
+            conditions.push(format!("model LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+        if let Some(tag) = &filter.tag {
+            values.push(tag.clone());
+            conditions.push(format!(
+                "id IN (SELECT run_id FROM tags WHERE tag = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(since) = &filter.since {
+            values.push(since.clone());
+            conditions.push(format!("timestamp >= ?{}", values.len()));
+        }
+
+        let mut query = SELECT_RUNS.to_string();
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(" ORDER BY timestamp DESC, id DESC");
+        if let Some(limit) = filter.limit {
+            query.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut statement = self.conn.prepare(&query).map_err(db_error)?;
+        let runs = statement
+            .query_map(params_from_iter(values), run_from_row)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        runs.into_iter().map(|run| self.with_details(run)).collect()
+    }
+
+    /// Median time the last [`TYPICAL_SAMPLE`] successful runs of `model`
+    /// took, once there are at least [`MIN_TYPICAL_SAMPLE`] of them
+    pub fn typical_duration(&self, model: &str) -> Result<Option<std::time::Duration>> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT duration_ms FROM runs
+                 WHERE model = ?1 AND status = ?2 AND duration_ms IS NOT NULL
+                 ORDER BY id DESC LIMIT ?3",
+            )
+            .map_err(db_error)?;
+        let mut durations = statement
+            .query_map(
+                params![model, RunStatus::Succeeded.as_str(), TYPICAL_SAMPLE],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        if durations.len() < MIN_TYPICAL_SAMPLE {
+            return Ok(None);
+        }
+        durations.sort_unstable();
+        let median = durations[durations.len() / 2].max(0) as u64;
+        Ok(Some(std::time::Duration::from_millis(median)))
+    }
+
+    /// Fill in the images and tags of a run read from `runs`
+    fn with_details(&self, mut run: Run) -> Result<Run> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT path, seed FROM images WHERE run_id = ?1 ORDER BY id")
+            .map_err(db_error)?;
+        run.images = statement
+            .query_map([run.id], |row| {
+                Ok(HistoryImage {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    seed: row.get::<_, Option<i64>>(1)?.map(|seed| seed as u64),
+                })
+            })
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT tag FROM tags WHERE run_id = ?1 ORDER BY tag")
+            .map_err(db_error)?;
+        run.tags = statement
+            .query_map([run.id], |row| row.get(0))
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        Ok(run)
+    }
+}
+
+const SELECT_RUNS: &str = "SELECT id, timestamp, prompt, provider, model, size, quality, count,
+    seed, cfg_scale, negative_prompt, input_tokens, output_tokens, total_tokens, status, error,
+    duration_ms
+    FROM runs";
+
+fn run_from_row(row: &rusqlite::Row) -> rusqlite::Result<Run> {
+    let tokens = |index: usize| -> rusqlite::Result<Option<u64>> {
+        Ok(row.get::<_, Option<i64>>(index)?.map(|n| n as u64))
+    };
+    Ok(Run {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        prompt: row.get(2)?,
+        provider: row.get(3)?,
+        model: row.get(4)?,
+        size: row.get(5)?,
+        quality: row.get(6)?,
+        count: row.get(7)?,
+        seed: row.get::<_, Option<i64>>(8)?.map(|seed| seed as u64),
+        cfg_scale: row.get(9)?,
+        negative_prompt: row.get(10)?,
+        usage: Usage {
+            input_tokens: tokens(11)?,
+            output_tokens: tokens(12)?,
+            total_tokens: tokens(13)?,
+        },
+        duration_ms: tokens(16)?,
+        status: RunStatus::parse(&row.get::<_, String>(14)?),
+        error: row.get(15)?,
+        images: Vec::new(),
+        tags: Vec::new(),
+    })
+}
+
+/// `LIKE` pattern matching `text` anywhere, with wildcards in it taken literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Write `runs` to `out` for spreadsheets and other tools
+pub fn export(runs: &[Run], format: ExportFormat, out: &mut impl Write) -> Result<()> {
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, runs)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Csv => {
+            writeln!(out, "{}", CSV_HEADER.join(","))?;
+            for run in runs {
+                let optional = |value: Option<String>| value.unwrap_or_default();
+                let images: Vec<String> = run
+                    .images
+                    .iter()
+                    .map(|image| image.path.to_string_lossy().into_owned())
+                    .collect();
+                let fields = [
+                    run.id.to_string(),
+                    run.timestamp.clone(),
+                    run.prompt.clone(),
+                    run.provider.clone(),
+                    run.model.clone(),
+                    optional(run.size.clone()),
+                    optional(run.quality.clone()),
+                    run.count.to_string(),
+                    optional(run.seed.map(|seed| seed.to_string())),
+                    optional(run.cfg_scale.map(|scale| scale.to_string())),
+                    optional(run.negative_prompt.clone()),
+                    optional(run.usage.input_tokens.map(|n| n.to_string())),
+                    optional(run.usage.output_tokens.map(|n| n.to_string())),
+                    optional(run.usage.total_tokens.map(|n| n.to_string())),
+                    run.status.to_string(),
+                    optional(run.error.clone()),
+                    run.tags.join(";"),
+                    images.join(";"),
+                ];
+                let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> ImagoError {
+    ImagoError::HistoryError(e.to_string())
+}
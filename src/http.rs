@@ -0,0 +1,253 @@
+//! HTTP client shared by the built-in providers
+//!
+//! Built once per run from the command line, so every provider honors the
+//! same timeouts, proxy, TLS trust and extra headers. Without `--proxy`, the standard
+//! `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` variables apply.
+//!
+//! Response bodies are read through [`read_bytes`] and [`read_text`], which
+//! stream them in chunks and stop at the `--max-response-size` limit instead
+//! of buffering whatever the server sends. Generation requests go through
+//! [`send`], which is where `--dump-http`, `--record` and `--replay` hook in.
+
+use crate::error::{ImagoError, Result};
+use crate::progress::{Progress, Reporter};
+use crate::{dump, session};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Whole-request timeout; 4K generations can take minutes
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Largest response body read, in megabytes; a 4K image as base64 JSON is
+/// well under 50
+pub const DEFAULT_MAX_RESPONSE_MB: u64 = 200;
+
+static MAX_RESPONSE_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_MAX_RESPONSE_MB * 1024 * 1024);
+
+/// How provider requests are sent
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// `http://`, `https://` or `socks5://` (`socks5h://` to resolve names
+    /// through the proxy) URL for every request
+    pub proxy: Option<String>,
+    /// PEM bundle trusted in addition to the built-in roots, for proxies that
+    /// intercept TLS
+    pub ca_cert: Option<PathBuf>,
+    /// Skip certificate verification entirely
+    pub insecure: bool,
+    /// `Name: value` headers added to every request
+    pub headers: Vec<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            proxy: None,
+            ca_cert: None,
+            insecure: false,
+            headers: Vec::new(),
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Build a client with these settings
+    pub fn client(&self) -> Result<Client> {
+        let mut builder = Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout);
+        if let Some(url) = &self.proxy {
+            let proxy = Proxy::all(url)
+                .map_err(|e| ImagoError::ConfigError(format!("proxy '{}': {}", url, e)))?
+                .no_proxy(NoProxy::from_env());
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_cert {
+            let invalid = |e: String| {
+                ImagoError::ConfigError(format!("CA certificate {}: {}", path.display(), e))
+            };
+            let pem = fs::read(path).map_err(|e| invalid(e.to_string()))?;
+            let certificates =
+                Certificate::from_pem_bundle(&pem).map_err(|e| invalid(e.to_string()))?;
+            if certificates.is_empty() {
+                return Err(invalid("no PEM certificates found".to_string()));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if !self.headers.is_empty() {
+            builder = builder.default_headers(parse_headers(&self.headers)?);
+        }
+        builder
+            .build()
+            .map_err(|e| ImagoError::ConfigError(format!("Failed to build HTTP client: {}", e)))
+    }
+}
+
+/// Set the largest response body [`read_bytes`] accepts, in megabytes
+pub fn limit_response_size(megabytes: u64) {
+    MAX_RESPONSE_BYTES.store(megabytes.saturating_mul(1024 * 1024), Ordering::Relaxed);
+}
+
+/// Read a response body chunk by chunk, failing as soon as it passes the
+/// size limit (or up front, when `Content-Length` already does)
+pub async fn read_bytes(response: Response) -> Result<Vec<u8>> {
+    read_bytes_reporting(response, &Reporter::default(), &mut |_| {}).await
+}
+
+/// [`read_bytes`], handing each chunk to `on_chunk` and reporting the bytes
+/// received so far
+async fn read_bytes_reporting(
+    mut response: Response,
+    progress: &Reporter,
+    on_chunk: &mut (dyn FnMut(&[u8]) + Send),
+) -> Result<Vec<u8>> {
+    let limit = MAX_RESPONSE_BYTES.load(Ordering::Relaxed);
+    let too_large = || ImagoError::ResponseTooLarge {
+        limit_mb: limit / (1024 * 1024),
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit)
+    {
+        return Err(too_large());
+    }
+    let total = response.content_length();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+        on_chunk(&chunk);
+        progress.emit(Progress::BytesReceived {
+            bytes: body.len() as u64,
+            total,
+        });
+    }
+    Ok(body)
+}
+
+/// [`read_bytes`] as text; invalid UTF-8 is replaced rather than rejected
+pub async fn read_text(response: Response) -> Result<String> {
+    let body = read_bytes(response).await?;
+    Ok(String::from_utf8(body)
+        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
+
+/// A provider response, read in full
+#[derive(Debug)]
+pub struct Reply {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    /// Body as text; invalid UTF-8 is replaced rather than rejected
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Send a provider request and read the whole response
+///
+/// `body` is the JSON already set on `request`, kept for dumps and session
+/// files; `secret` is redacted from both. When replaying, the recorded
+/// response is returned and nothing goes over the network.
+pub async fn send(
+    provider: &str,
+    request: RequestBuilder,
+    body: Option<&Value>,
+    secret: &str,
+) -> Result<Reply> {
+    send_streaming(
+        provider,
+        request,
+        body,
+        secret,
+        &Reporter::default(),
+        &mut |_| {},
+    )
+    .await
+}
+
+/// [`send`], reporting the request and the bytes received to `progress` and
+/// handing the body to `on_chunk` piece by piece as it arrives, for streamed
+/// responses; a replayed body comes in one piece
+pub async fn send_streaming(
+    provider: &str,
+    request: RequestBuilder,
+    body: Option<&Value>,
+    secret: &str,
+    progress: &Reporter,
+    on_chunk: &mut (dyn FnMut(&[u8]) + Send),
+) -> Result<Reply> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+
+    let reply = match session::answer(provider, &method, &dump::redact(&url, secret))? {
+        Some(reply) => {
+            on_chunk(&reply.body);
+            reply
+        }
+        None => {
+            progress.emit(Progress::RequestSent {
+                provider: provider.to_string(),
+            });
+            let response = client.execute(request).await?;
+            Reply {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: read_bytes_reporting(response, progress, on_chunk)
+                    .instrument(tracing::info_span!("download"))
+                    .await?,
+            }
+        }
+    };
+    dump::exchange(
+        provider,
+        &method,
+        &url,
+        body,
+        reply.status.as_u16(),
+        &reply.body,
+        secret,
+    );
+    session::capture(provider, &method, &url, body, &reply, secret)?;
+    Ok(reply)
+}
+
+/// Parse `Name: value` lines, as accepted by `--header`
+pub fn parse_headers(lines: &[String]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        let invalid =
+            |reason: &str| ImagoError::ConfigError(format!("header '{}': {}", line, reason));
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("expected 'Name: value'"))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| invalid("invalid header name"))?;
+        let value =
+            HeaderValue::from_str(value.trim()).map_err(|_| invalid("invalid header value"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
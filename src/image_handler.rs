@@ -1,19 +1,129 @@
+use crate::charart;
 use crate::error::{ImagoError, Result};
+use crate::graphics::{self, Multiplexer};
+use crate::interrupt;
+use crate::naming::{self, NameFields};
+use crate::progress::{Progress, Reporter};
+use base64::prelude::*;
 use chrono::Local;
+use clap::ValueEnum;
 use colored::Colorize;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::borrow::Cow;
 use std::fs;
+use std::io::{Cursor, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::UnboundedSender;
+#[cfg(feature = "preview")]
 use viuer::{get_kitty_support, is_iterm_supported, print, Config, KittySupport};
 
+const LAUNCHER_THUMB_SIZE: u32 = 128;
+/// Longest side of the preview copy drawn under `--low-bandwidth`
+const LOW_BANDWIDTH_PREVIEW_SIZE: u32 = 384;
+/// Preview width in columns when it isn't fitted to the terminal
+pub const DEFAULT_PREVIEW_WIDTH: u32 = 60;
+/// Columns and rows left free around a preview fitted to the terminal, for
+/// the status lines around it
+const FIT_MARGIN: (u32, u32) = (2, 4);
+/// File name prefix of preview scratch files in the temp directory
+pub const PREVIEW_PREFIX: &str = "imago_preview_";
+
+/// How status and results are written to the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStyle {
+    /// Colored banners and terminal previews
+    Pretty,
+    /// Plain, stable lines for Raycast/Alfred-style launchers
+    Launcher { thumbnail: bool },
+    /// Labelled plain-text lines for screen readers, with text descriptions
+    /// in place of previews
+    Accessible,
+    /// Nothing but warnings and errors on stderr; the caller prints a JSON
+    /// report
+    Json,
+    /// Saved paths on stdout and errors on stderr, nothing else
+    Quiet,
+}
+
+/// How generated files are arranged in the output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Organize {
+    /// Everything directly in the output directory
+    #[default]
+    Flat,
+    /// One subdirectory per day, `<out>/YYYY/MM/DD/`
+    Date,
+}
+
+/// What draws terminal previews (`--preview-backend`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PreviewBackend {
+    /// `viu` when installed, otherwise the built-in renderer with the
+    /// protocol the terminal appears to support
+    #[default]
+    Auto,
+    /// The `viu` command
+    Viu,
+    /// Kitty graphics protocol (also WezTerm, Ghostty, Konsole)
+    Kitty,
+    /// iTerm2 inline images (also WezTerm, VS Code)
+    Iterm,
+    /// Sixel graphics
+    Sixel,
+    /// A ueberzugpp overlay window, for X11 and Wayland terminals without a
+    /// graphics protocol; never picked by `Auto`, since it waits for Enter
+    #[value(alias = "ueberzugpp")]
+    Ueberzug,
+    /// Truecolor block characters, for any terminal
+    Blocks,
+    /// Braille dots without color, for minimal terminals and logs
+    Braille,
+    /// No preview
+    None,
+}
+
+/// Where previews sit across the terminal (`--preview-align`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PreviewAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// What happens when an explicit output file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Stop with an error before anything is generated
+    #[default]
+    Refuse,
+    /// Replace the file (`--force` or `--overwrite`)
+    Replace,
+    /// Save next to it as `<stem>_2` and so on (`--auto-suffix`)
+    Suffix,
+}
+
 /// Handles image saving and terminal display
 pub struct ImageHandler {
     width: u32,
     height: Option<u32>,
     enable_preview: bool,
+    fit_terminal: bool,
+    preview_backend: PreviewBackend,
+    preview_align: PreviewAlign,
+    preview_pad: u32,
+    framed: bool,
+    force_preview: bool,
+    style: OutputStyle,
+    downscale_preview: bool,
+    organize: Organize,
+    name_template: String,
+    overwrite: Overwrite,
+    numbered: bool,
+    messages_to_stderr: bool,
+    progress: Reporter,
 }
 
 impl ImageHandler {
@@ -23,32 +133,189 @@ impl ImageHandler {
             width,
             height,
             enable_preview,
+            fit_terminal: false,
+            preview_backend: PreviewBackend::Auto,
+            preview_align: PreviewAlign::Left,
+            preview_pad: 0,
+            framed: false,
+            force_preview: false,
+            style: OutputStyle::Pretty,
+            downscale_preview: false,
+            organize: Organize::Flat,
+            name_template: naming::DEFAULT_TEMPLATE.to_string(),
+            overwrite: Overwrite::Refuse,
+            numbered: false,
+            messages_to_stderr: false,
+            progress: Reporter::default(),
         }
     }
 
-    /// Generate a filename with timestamp and random suffix
-    pub fn generate_filename() -> String {
-        let timestamp = Local::now().format("%Y%m%d%H%M");
-        let random_str: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
-            .take(8)
-            .map(char::from)
-            .collect();
-        format!("{}_{}.png", timestamp, random_str)
+    /// Set the output style; launcher output never draws previews
+    pub fn with_style(mut self, style: OutputStyle) -> Self {
+        if style != OutputStyle::Pretty {
+            self.enable_preview = false;
+        }
+        if style == OutputStyle::Json {
+            self.messages_to_stderr = true;
+        }
+        self.style = style;
+        self
     }
 
-    /// Resolve the output path
-    pub fn resolve_output_path(&self, output: Option<&Path>) -> PathBuf {
-        let filename = Self::generate_filename();
+    /// Draw previews from a small downscaled copy instead of the full image,
+    /// which keeps terminal output light over slow SSH links
+    pub fn with_downscaled_preview(mut self, downscale: bool) -> Self {
+        self.downscale_preview = downscale;
+        self
+    }
+
+    /// Size previews to the terminal window instead of `width` and `height`,
+    /// keeping the aspect ratio; `width` is used when the size is unknown
+    pub fn with_fit_to_terminal(mut self, fit: bool) -> Self {
+        self.fit_terminal = fit;
+        self
+    }
 
+    /// Draw previews with `backend` instead of picking one automatically
+    pub fn with_preview_backend(mut self, backend: PreviewBackend) -> Self {
+        self.preview_backend = backend;
+        self
+    }
+
+    /// Place previews at the `align` side of the terminal, `pad` columns
+    /// from the edge
+    pub fn with_preview_placement(mut self, align: PreviewAlign, pad: u32) -> Self {
+        self.preview_align = align;
+        self.preview_pad = pad;
+        self
+    }
+
+    /// Draw a box around previews, with a caption line when one is given
+    pub fn with_preview_frame(mut self, framed: bool) -> Self {
+        self.framed = framed;
+        self
+    }
+
+    /// Draw automatic previews even over SSH, in CI or when stdout is not a
+    /// terminal, where they are skipped by default
+    pub fn with_forced_preview(mut self, force: bool) -> Self {
+        self.force_preview = force;
+        self
+    }
+
+    /// Arrange generated files in subdirectories; explicit file paths are kept as given
+    pub fn with_organize(mut self, organize: Organize) -> Self {
+        self.organize = organize;
+        self
+    }
+
+    /// Name generated files with a `--name-template` instead of the default scheme
+    pub fn with_name_template(mut self, template: Option<String>) -> Self {
+        if let Some(template) = template {
+            self.name_template = template;
+        }
+        self
+    }
+
+    /// Number generated files `<name>-001.png`, `<name>-002.png`..., continuing
+    /// from the highest number already in the directory
+    pub fn with_numbering(mut self, numbered: bool) -> Self {
+        self.numbered = numbered;
+        self
+    }
+
+    /// Report a [`Progress::Saving`] event to `sender` for each image saved
+    pub fn with_progress(mut self, sender: UnboundedSender<Progress>) -> Self {
+        self.progress = Reporter::new(sender);
+        self
+    }
+
+    /// Send every message to stderr and skip previews, leaving stdout to image
+    /// data (`-o -`)
+    pub fn with_messages_to_stderr(mut self, stderr: bool) -> Self {
+        if stderr {
+            self.enable_preview = false;
+        }
+        self.messages_to_stderr |= stderr;
+        self
+    }
+
+    /// Print a line of output on stdout, or stderr when stdout carries image data
+    pub fn say(&self, line: std::fmt::Arguments) {
+        if self.style == OutputStyle::Quiet {
+            return;
+        }
+        if self.messages_to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// How to treat an explicit output file that already exists
+    pub fn with_overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Fail if saving to `path` would replace a file the user did not allow replacing
+    pub fn check_overwrite(&self, path: &Path) -> Result<()> {
+        if self.overwrite == Overwrite::Refuse && path.exists() {
+            return Err(ImagoError::OutputExists(path.display().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Subdirectory of the output directory for a file saved now
+    fn subdirectory(&self) -> PathBuf {
+        match self.organize {
+            Organize::Flat => PathBuf::new(),
+            Organize::Date => {
+                let now = Local::now();
+                [now.format("%Y"), now.format("%m"), now.format("%d")]
+                    .iter()
+                    .map(|part| part.to_string())
+                    .collect()
+            }
+        }
+    }
+
+    /// File name from the name template
+    ///
+    /// With several images per run, a template that does not tell them apart
+    /// (no `{n}` or `{rand}`) gets a numeric suffix.
+    fn generate_filename(&self, fields: &NameFields, index: Option<usize>) -> PathBuf {
+        let name = PathBuf::from(naming::render(
+            &self.name_template,
+            fields,
+            index.unwrap_or(1),
+        ));
+        match index {
+            Some(i) if !naming::is_unique_per_image(&self.name_template) => {
+                Self::with_index_suffix(&name, i)
+            }
+            _ => name,
+        }
+    }
+
+    /// Resolve the output path
+    ///
+    /// `index` is set when several images are generated in one run, so an
+    /// explicit file path gets a numeric suffix instead of being overwritten.
+    /// Generated names never replace an existing file; see [`Self::unique_path`].
+    pub fn resolve_output_path(
+        &self,
+        output: Option<&Path>,
+        index: Option<usize>,
+        fields: &NameFields,
+    ) -> PathBuf {
         match output {
             Some(path) => {
                 if path.is_dir() || path.as_os_str().to_string_lossy().ends_with('/') {
-                    path.join(filename)
+                    self.generated_path(path, fields, index)
                 } else {
                     let path_str = path.as_os_str().to_string_lossy();
-                    if !path_str.ends_with(".png")
+                    let path = if !path_str.ends_with(".png")
                         && !path_str.ends_with(".jpg")
                         && !path_str.ends_with(".jpeg")
                         && !path_str.ends_with(".gif")
@@ -57,15 +324,112 @@ impl ImageHandler {
                         path.with_extension("png")
                     } else {
                         path.to_path_buf()
+                    };
+
+                    let path = match index {
+                        Some(i) => Self::with_index_suffix(&path, i),
+                        None => path,
+                    };
+                    if self.overwrite == Overwrite::Suffix {
+                        Self::unique_path(&path)
+                    } else {
+                        path
                     }
                 }
             }
-            None => PathBuf::from(filename),
+            None => self.generated_path(Path::new(""), fields, index),
+        }
+    }
+
+    /// Path for a name from the template inside the output directory `dir`
+    fn generated_path(&self, dir: &Path, fields: &NameFields, index: Option<usize>) -> PathBuf {
+        let dir = dir.join(self.subdirectory());
+        if self.numbered {
+            let name = naming::render(&self.name_template, fields, index.unwrap_or(1));
+            return Self::next_numbered(&dir.join(name));
+        }
+        Self::unique_path(&dir.join(self.generate_filename(fields, index)))
+    }
+
+    /// `<stem>-NNN.<ext>` one past the highest number next to `path`
+    fn next_numbered(path: &Path) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "png".to_string());
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let listed = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+
+        let prefix = format!("{}-", stem);
+        let suffix = format!(".{}", extension);
+        let highest = fs::read_dir(listed)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let number = name.strip_prefix(&prefix)?.strip_suffix(&suffix)?;
+                if number.chars().all(|c| c.is_ascii_digit()) {
+                    number.parse::<u32>().ok()
+                } else {
+                    None
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        parent.join(format!("{}{:03}{}", prefix, highest + 1, suffix))
+    }
+
+    /// `path`, or the first free `<stem>_2`, `<stem>_3`... next to it
+    pub fn unique_path(path: &Path) -> PathBuf {
+        if !path.exists() {
+            return path.to_path_buf();
         }
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        (2..)
+            .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+            .find(|candidate| !candidate.exists())
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Append `-<index>` to the file stem, keeping the extension
+    fn with_index_suffix(path: &Path, index: usize) -> PathBuf {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut name = format!("{}-{}", stem, index);
+        if let Some(ext) = path.extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+        path.with_file_name(name)
     }
 
     /// Save image bytes to file
+    ///
+    /// The bytes go to a hidden temporary file in the same directory, which is
+    /// renamed over `path` once fully written, so an interrupted run never
+    /// leaves a truncated image behind.
     pub async fn save_image(&self, image_data: &[u8], path: &Path) -> Result<()> {
+        self.progress.emit(Progress::Saving {
+            path: path.to_path_buf(),
+        });
         if let Some(parent) = path.parent() {
             tokio::fs::create_dir_all(parent).await.map_err(|e| {
                 ImagoError::IoError(std::io::Error::other(format!(
@@ -75,97 +439,693 @@ impl ImageHandler {
             })?;
         }
 
-        let mut file = File::create(path).await?;
-        file.write_all(image_data).await?;
-        file.flush().await?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let tmp_path =
+            path.with_file_name(format!(".{}.{}.tmp", file_name, naming::random_suffix()));
+        interrupt::track(&tmp_path);
+        let written = async {
+            let mut file = File::create(&tmp_path).await?;
+            file.write_all(image_data).await?;
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp_path, path).await
+        }
+        .await;
+        interrupt::finished(&tmp_path);
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Point `latest.<ext>` in the `-o` directory at the file just saved
+    ///
+    /// A relative symlink where supported, a copy on Windows. The link is
+    /// built under a temporary name and renamed into place.
+    pub fn update_latest(&self, output: &Path, saved: &Path) -> Result<()> {
+        // The output directory, above any --organize subdirectories
+        let base = if output.is_dir() || output.to_string_lossy().ends_with('/') {
+            output.to_path_buf()
+        } else {
+            saved.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+        let extension = saved
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "png".to_string());
+        let latest = base.join(format!("latest.{}", extension));
+        if latest == saved {
+            return Ok(());
+        }
+
+        let tmp = base.join(format!(".latest.{}.tmp", naming::random_suffix()));
+        #[cfg(unix)]
+        {
+            let target = match saved.strip_prefix(&base) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => fs::canonicalize(saved)?,
+            };
+            std::os::unix::fs::symlink(&target, &tmp)?;
+        }
+        #[cfg(not(unix))]
+        fs::copy(saved, &tmp)?;
 
+        if let Err(e) = fs::rename(&tmp, &latest) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
         Ok(())
     }
 
     /// Display image in terminal
     pub fn display_in_terminal(&self, image_data: &[u8]) -> Result<()> {
-        if !self.enable_preview {
+        self.display_with_caption(image_data, None)
+    }
+
+    /// Display image in terminal, with `caption` under it when previews are
+    /// framed
+    pub fn display_with_caption(&self, image_data: &[u8], caption: Option<&str>) -> Result<()> {
+        if !self.enable_preview || self.preview_backend == PreviewBackend::None {
+            return Ok(());
+        }
+        // Escape sequences would end up as noise in logs; the saved path is
+        // printed already
+        let auto = self.preview_backend == PreviewBackend::Auto;
+        if auto && !self.force_preview && Self::plain_session().is_some() {
             return Ok(());
         }
 
-        // Prefer system `viu` preview because it renders correctly in user's Kitty setup.
-        // Fallback to viuer when `viu` binary is unavailable.
-        if Self::has_viu() {
-            let tmp_path = std::env::temp_dir()
-                .join(format!("imago_preview_{}.png", Self::generate_filename()));
-            fs::write(&tmp_path, image_data)?;
+        let downscaled;
+        let image_data = if self.downscale_preview {
+            downscaled = Self::downscale(image_data, LOW_BANDWIDTH_PREVIEW_SIZE)?;
+            downscaled.as_slice()
+        } else {
+            image_data
+        };
+
+        // The frame takes a column on each side; the overlay is a window of
+        // its own, with a prompt under it, so it goes without
+        let framed = self.framed && self.preview_backend != PreviewBackend::Ueberzug;
+        let border = if framed { 2 } else { 0 };
+        let (columns, rows) = self.preview_size(image_data, border);
+        let indent = self.indent(columns + border);
+        if !framed {
+            return self.render(image_data, columns, rows, indent);
+        }
+
+        let mut stdout = std::io::stdout();
+        let margin = " ".repeat(indent as usize);
+        let rule = "─".repeat(columns as usize);
+        writeln!(stdout, "{}┌{}┐", margin, rule)?;
+        self.render(image_data, columns, rows, indent + 1)?;
+
+        // Sides go in after the image, once the rows it took are known
+        let height = rows.unwrap_or_else(|| {
+            let (width, height) = Self::dimensions(image_data);
+            graphics::rows(width, height, columns, graphics::cell_size())
+        });
+        write!(stdout, "\x1b[{}A", height)?;
+        for _ in 0..height {
+            writeln!(
+                stdout,
+                "\x1b[{}G│\x1b[{}G│",
+                indent + 1,
+                indent + columns + 2
+            )?;
+        }
+        if let Some(caption) = caption {
+            writeln!(stdout, "{}├{}┤", margin, rule)?;
+            writeln!(
+                stdout,
+                "{}│{}│",
+                margin,
+                charart::fit_text(caption, columns as usize)
+            )?;
+        }
+        writeln!(stdout, "{}└{}┘", margin, rule)?;
+        stdout.flush()?;
+        Ok(())
+    }
 
-            let mut cmd = Command::new("viu");
-            cmd.arg("-w").arg(self.width.to_string());
-            if let Some(h) = self.height {
-                cmd.arg("-h").arg(h.to_string());
+    /// Caption for a framed preview: the prompt and the file it was saved to
+    pub fn caption(prompt: &str, path: &Path) -> String {
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        format!("{} · {}", prompt, name.to_string_lossy())
+    }
+
+    /// Draw the image with the selected backend, `indent` columns from the left
+    fn render(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        match self.preview_backend {
+            PreviewBackend::None => Ok(()),
+            // Prefer system `viu` preview because it renders correctly in user's Kitty setup.
+            // Fallback to viuer when `viu` binary is unavailable.
+            PreviewBackend::Auto if Self::has_viu() => {
+                self.render_viu(image_data, columns, rows, indent)
+            }
+            PreviewBackend::Viu => self.render_viu(image_data, columns, rows, indent),
+            PreviewBackend::Kitty => self.render_kitty(image_data, columns, rows, indent),
+            PreviewBackend::Iterm => self.render_iterm(image_data, columns, rows, indent),
+            PreviewBackend::Sixel => self.render_sixel(image_data, columns, rows, indent),
+            PreviewBackend::Ueberzug => self.render_ueberzug(image_data, columns, rows, indent),
+            PreviewBackend::Blocks => self.render_blocks(image_data, columns, rows, indent),
+            PreviewBackend::Braille => {
+                let img = image::load_from_memory(image_data)
+                    .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+                charart::braille(&mut std::io::stdout(), &img, columns, rows, indent).map_err(|e| {
+                    ImagoError::DisplayError(format!("Failed to display image: {}", e))
+                })
             }
-            cmd.arg(&tmp_path);
+            PreviewBackend::Auto => self.render_builtin(image_data, columns, rows, indent),
+        }
+    }
 
-            let status = cmd
-                .status()
-                .map_err(|e| ImagoError::DisplayError(format!("Failed to launch viu: {}", e)))?;
+    /// Preview size in columns and rows: as set, or the widest that fits the
+    /// terminal with `border` columns to spare
+    fn preview_size(&self, image_data: &[u8], border: u32) -> (u32, Option<u32>) {
+        let fitted = self.fit_terminal.then(graphics::terminal_size).flatten();
+        let Some(terminal) = fitted else {
+            return (self.width, self.height);
+        };
+        let dimensions = Self::dimensions(image_data);
+        let margin = (
+            FIT_MARGIN.0 + 2 * self.preview_pad + border,
+            FIT_MARGIN.1 + border,
+        );
+        let columns = graphics::fit_columns(dimensions, terminal, margin, graphics::cell_size());
+        (columns, None)
+    }
 
-            let _ = fs::remove_file(&tmp_path);
+    /// Width and height of an image in pixels, read from its header
+    fn dimensions(image_data: &[u8]) -> (u32, u32) {
+        image::ImageReader::new(Cursor::new(image_data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .unwrap_or((1, 1))
+    }
 
-            if !status.success() {
-                return Err(ImagoError::DisplayError(
-                    "viu preview process exited with non-zero status".to_string(),
-                ));
+    /// Columns to leave blank left of a preview `columns` wide, for its
+    /// alignment and padding; alignment needs the terminal width
+    fn indent(&self, columns: u32) -> u32 {
+        let free = graphics::terminal_size()
+            .map(|(width, _)| width.saturating_sub(columns + 2 * self.preview_pad));
+        self.preview_pad
+            + match (self.preview_align, free) {
+                (PreviewAlign::Left, _) | (_, None) => 0,
+                (PreviewAlign::Center, Some(free)) => free / 2,
+                (PreviewAlign::Right, Some(free)) => free,
             }
+    }
 
-            return Ok(());
+    /// Draw with the `viu` command, through a scratch file
+    fn render_viu(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let tmp_path = Self::scratch_file(image_data)?;
+
+        let mut cmd = Command::new("viu");
+        cmd.arg("-w").arg(columns.to_string());
+        cmd.arg("-x").arg(indent.to_string());
+        if let Some(h) = rows {
+            cmd.arg("-h").arg(h.to_string());
         }
+        cmd.arg(&tmp_path);
+
+        let status = cmd
+            .status()
+            .map_err(|e| ImagoError::DisplayError(format!("Failed to launch viu: {}", e)))?;
+
+        let _ = fs::remove_file(&tmp_path);
+        interrupt::finished(&tmp_path);
 
+        if !status.success() {
+            return Err(ImagoError::DisplayError(
+                "viu preview process exited with non-zero status".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Show a ueberzugpp overlay anchored at the cursor until Enter is
+    /// pressed; the overlay only lasts as long as its process
+    fn render_ueberzug(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        if !std::io::stdin().is_terminal() {
+            return Err(ImagoError::DisplayError(
+                "the ueberzugpp overlay needs an interactive terminal to stay open".to_string(),
+            ));
+        }
         let img = image::load_from_memory(image_data)
             .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+        let rows = rows.unwrap_or_else(|| {
+            graphics::rows(img.width(), img.height(), columns, graphics::cell_size())
+        });
 
-        let mut conf = Config {
-            width: Some(self.width),
-            height: self.height,
-            ..Default::default()
+        // Make room first, so the overlay lands where the text scrolled to
+        let mut stdout = std::io::stdout();
+        stdout.write_all("\n".repeat(rows as usize).as_bytes())?;
+        stdout.flush()?;
+        let top = graphics::cursor_row().map_or(0, |row| row.saturating_sub(rows + 1));
+
+        let tmp_path = Self::scratch_file(image_data)?;
+        let launched = Command::new("ueberzugpp")
+            .args(["layer", "--silent"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut layer = match launched {
+            Ok(layer) => layer,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                interrupt::finished(&tmp_path);
+                return Err(ImagoError::DisplayError(format!(
+                    "Failed to launch ueberzugpp: {}",
+                    e
+                )));
+            }
         };
 
-        let support = Self::detect_terminal_support();
-        match support {
-            TerminalSupport::Kitty => conf.use_kitty = true,
-            TerminalSupport::ITerm2 => conf.use_iterm = true,
-            _ => {}
+        let add = serde_json::json!({
+            "action": "add",
+            "identifier": "imago",
+            "x": indent,
+            "y": top,
+            "max_width": columns,
+            "max_height": rows,
+            "path": tmp_path,
+        });
+        if let Some(stdin) = layer.stdin.as_mut() {
+            let _ = writeln!(stdin, "{}", add);
+        }
+        self.say(format_args!(
+            "{}",
+            "Press Enter to close the preview".dimmed()
+        ));
+        let _ = std::io::stdin().read_line(&mut String::new());
+
+        // The layer exits once its input is closed
+        drop(layer.stdin.take());
+        let _ = layer.wait();
+        let _ = fs::remove_file(&tmp_path);
+        interrupt::finished(&tmp_path);
+        Ok(())
+    }
+
+    /// Copy of `image_data` in the temp directory for external viewers,
+    /// removed on Ctrl-C
+    fn scratch_file(image_data: &[u8]) -> Result<PathBuf> {
+        let tmp_path =
+            std::env::temp_dir().join(format!("{}{}.png", PREVIEW_PREFIX, naming::random_suffix()));
+        interrupt::track(&tmp_path);
+        fs::write(&tmp_path, image_data)?;
+        Ok(tmp_path)
+    }
+
+    /// Draw with sixel graphics, scaled to the preview size in pixels
+    fn render_sixel(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let img = image::load_from_memory(image_data)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+        let (cell_width, cell_height) = graphics::cell_size();
+        let max_height = rows.map_or(u32::MAX, |rows| rows * cell_height);
+        let img = img.resize(
+            columns * cell_width,
+            max_height,
+            image::imageops::FilterType::Triangle,
+        );
+        let mut stdout = std::io::stdout();
+        graphics::indent(&mut stdout, indent)
+            .and_then(|()| graphics::sixel(&mut stdout, &img.to_rgba8(), Multiplexer::detect()))
+            .map_err(|e| ImagoError::DisplayError(format!("Failed to display image: {}", e)))
+    }
+
+    /// Draw with the kitty graphics protocol, placed at the cursor with the
+    /// cursor moved below it, so the image scrolls with the text
+    fn render_kitty(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let mux = Multiplexer::detect();
+        if !mux.passes_graphics() {
+            return self.render_fallback(image_data, columns, rows, indent);
+        }
+        let png = Self::as_png(image_data)?;
+        let mut stdout = std::io::stdout();
+        graphics::indent(&mut stdout, indent)
+            .and_then(|()| graphics::kitty(&mut stdout, &png, columns, rows, mux))
+            .map_err(|e| ImagoError::DisplayError(format!("Failed to display image: {}", e)))
+    }
+
+    /// Draw with iTerm2 inline images
+    fn render_iterm(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let mux = Multiplexer::detect();
+        if !mux.passes_graphics() {
+            return self.render_fallback(image_data, columns, rows, indent);
+        }
+        let mut stdout = std::io::stdout();
+        graphics::indent(&mut stdout, indent)
+            .and_then(|()| graphics::iterm(&mut stdout, image_data, columns, rows, mux))
+            .map_err(|e| ImagoError::DisplayError(format!("Failed to display image: {}", e)))
+    }
+
+    /// Draw with sixel graphics when the terminal has them and with block
+    /// characters otherwise, where kitty and iTerm2 images can't get through
+    fn render_fallback(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        tracing::debug!("graphics can't pass through the multiplexer, falling back");
+        if graphics::sixel_supported() {
+            self.render_sixel(image_data, columns, rows, indent)
+        } else {
+            self.render_blocks(image_data, columns, rows, indent)
+        }
+    }
+
+    /// Draw with block characters, picking for each cell the quarter, half
+    /// or partial block that best follows the image
+    fn render_blocks(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let img = image::load_from_memory(image_data)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+        charart::render(&mut std::io::stdout(), &img, columns, rows, indent)
+            .map_err(|e| ImagoError::DisplayError(format!("Failed to display image: {}", e)))
+    }
+
+    /// Draw with the protocol the terminal supports: viuer for iTerm2,
+    /// the kitty protocol written here (viuer's placement breaks scrollback
+    /// in kitty), sixel graphics when the terminal only has those, and block
+    /// characters otherwise
+    ///
+    /// Inside tmux, screen or Zellij iTerm2 images are written here too,
+    /// wrapped so they pass through to the terminal or replaced where they
+    /// can't.
+    #[cfg(feature = "preview")]
+    fn render_builtin(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        let multiplexed = Multiplexer::detect() != Multiplexer::None;
+        match Self::detect_terminal_support() {
+            TerminalSupport::Kitty => return self.render_kitty(image_data, columns, rows, indent),
+            TerminalSupport::ITerm2 if multiplexed => {
+                return self.render_iterm(image_data, columns, rows, indent)
+            }
+            TerminalSupport::ITerm2 => {}
+            TerminalSupport::Sixel => return self.render_sixel(image_data, columns, rows, indent),
+            TerminalSupport::Blocks => {
+                return self.render_blocks(image_data, columns, rows, indent)
+            }
         }
 
+        let conf = Config {
+            width: Some(columns),
+            height: rows,
+            x: indent.min(u32::from(u16::MAX)) as u16,
+            absolute_offset: false,
+            use_kitty: false,
+            use_iterm: true,
+            ..Default::default()
+        };
+        let img = image::load_from_memory(image_data)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
         print(&img, &conf)
             .map_err(|e| ImagoError::DisplayError(format!("Failed to display image: {}", e)))?;
 
         Ok(())
     }
 
-    fn has_viu() -> bool {
+    /// Without terminal detection, block characters are the one choice that
+    /// works everywhere; the other backends can still be picked by name
+    #[cfg(not(feature = "preview"))]
+    fn render_builtin(
+        &self,
+        image_data: &[u8],
+        columns: u32,
+        rows: Option<u32>,
+        indent: u32,
+    ) -> Result<()> {
+        self.render_blocks(image_data, columns, rows, indent)
+    }
+
+    pub fn has_viu() -> bool {
         Command::new("viu").arg("--help").output().is_ok()
     }
 
+    pub fn has_ueberzug() -> bool {
+        Command::new("ueberzugpp").arg("--version").output().is_ok()
+    }
+
+    /// Check if the terminal answers that it draws sixel graphics
+    pub fn has_sixel() -> bool {
+        graphics::sixel_supported()
+    }
+
+    /// Print the result for a saved image
+    ///
+    /// Launcher output is one line per image: the path, optionally followed by
+    /// a tab and a base64 PNG thumbnail as a data URI.
+    pub fn print_result(&self, path: &Path, image_data: &[u8]) {
+        match self.style {
+            OutputStyle::Pretty | OutputStyle::Accessible | OutputStyle::Quiet => {
+                self.print_success(path)
+            }
+            OutputStyle::Json => {}
+            OutputStyle::Launcher { thumbnail } => {
+                let thumb = if thumbnail {
+                    Self::thumbnail_data_uri(image_data)
+                } else {
+                    None
+                };
+                match thumb {
+                    Some(uri) => self.say(format_args!("{}\t{}", path.display(), uri)),
+                    None => self.say(format_args!("{}", path.display())),
+                }
+            }
+        }
+    }
+
     /// Print success message
     pub fn print_success(&self, path: &Path) {
+        match self.style {
+            OutputStyle::Pretty => {}
+            OutputStyle::Accessible => {
+                self.say(format_args!("Saved: {}", path.display()));
+                return;
+            }
+            OutputStyle::Launcher { .. } | OutputStyle::Json => {
+                self.say(format_args!("{}", path.display()));
+                return;
+            }
+            OutputStyle::Quiet => {
+                if !self.messages_to_stderr {
+                    println!("{}", path.display());
+                }
+                return;
+            }
+        }
         let path_str = path.display().to_string();
-        println!("{} {}", "✅ Success!".green().bold(), "Saved to:".white());
-        println!("   {}", path_str.cyan().underline());
+        self.say(format_args!(
+            "{} {}",
+            "✅ Success!".green().bold(),
+            "Saved to:".white()
+        ));
+        self.say(format_args!("   {}", path_str.cyan().underline()));
     }
 
     /// Print generation started message
     pub fn print_generating(&self, prompt: &str) {
-        println!("{} {}", "🎨 Generating:".blue().bold(), prompt.white());
+        match self.style {
+            OutputStyle::Pretty => {}
+            OutputStyle::Accessible => {
+                self.say(format_args!("Generating: {}", prompt));
+                return;
+            }
+            OutputStyle::Launcher { .. } | OutputStyle::Json | OutputStyle::Quiet => return,
+        }
+        self.say(format_args!(
+            "{} {}",
+            "🎨 Generating:".blue().bold(),
+            prompt.white()
+        ));
     }
 
     /// Print error message
     pub fn print_error(&self, error: &ImagoError) {
+        match self.style {
+            OutputStyle::Pretty => {}
+            OutputStyle::Accessible => {
+                eprintln!("Error: {}", error);
+                return;
+            }
+            OutputStyle::Json => {
+                let mut details = serde_json::json!({
+                    "kind": error.kind(),
+                    "message": error.to_string(),
+                    "exit_code": error.exit_code(),
+                });
+                if let ImagoError::ApiError {
+                    status, request_id, ..
+                } = error
+                {
+                    details["status"] = (*status).into();
+                    details["request_id"] = request_id.clone().into();
+                }
+                eprintln!("{}", serde_json::json!({ "error": details }));
+                return;
+            }
+            OutputStyle::Launcher { .. } | OutputStyle::Quiet => {
+                eprintln!("error: {}", error);
+                return;
+            }
+        }
         eprintln!("{} {}", "❌ Error:".red().bold(), error.to_string().red());
     }
 
     /// Print warning message
     pub fn print_warning(&self, message: &str) {
-        println!("{} {}", "⚠️  Warning:".yellow(), message.yellow());
+        match self.style {
+            OutputStyle::Pretty => {}
+            OutputStyle::Accessible => {
+                eprintln!("Warning: {}", message);
+                return;
+            }
+            OutputStyle::Quiet => return,
+            OutputStyle::Launcher { .. } | OutputStyle::Json => {
+                eprintln!("warning: {}", message);
+                return;
+            }
+        }
+        self.say(format_args!(
+            "{} {}",
+            "⚠️  Warning:".yellow(),
+            message.yellow()
+        ));
+    }
+
+    /// Print a text description in place of a preview (accessible output only)
+    pub fn print_description(&self, description: &str) {
+        if self.style == OutputStyle::Accessible {
+            self.say(format_args!("Description: {}", description));
+        }
+    }
+
+    /// Re-encode as a PNG no larger than `max` pixels on either side
+    fn downscale(image_data: &[u8], max: u32) -> Result<Vec<u8>> {
+        let img = image::load_from_memory(image_data)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+        let mut buf = Vec::new();
+        img.thumbnail(max, max)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to downscale preview: {}", e)))?;
+        Ok(buf)
+    }
+
+    /// `image_data` as a PNG, re-encoded when it is in another format
+    fn as_png(image_data: &[u8]) -> Result<Cow<'_, [u8]>> {
+        if image::guess_format(image_data).ok() == Some(image::ImageFormat::Png) {
+            return Ok(Cow::Borrowed(image_data));
+        }
+        let img = image::load_from_memory(image_data)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to load image: {}", e)))?;
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .map_err(|e| ImagoError::ImageError(format!("Failed to encode preview: {}", e)))?;
+        Ok(Cow::Owned(buf))
+    }
+
+    /// Encode a small PNG thumbnail as a `data:` URI
+    fn thumbnail_data_uri(image_data: &[u8]) -> Option<String> {
+        let img = image::load_from_memory(image_data).ok()?;
+        let mut buf = Vec::new();
+        img.thumbnail(LAUNCHER_THUMB_SIZE, LAUNCHER_THUMB_SIZE)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .ok()?;
+        Some(format!(
+            "data:image/png;base64,{}",
+            BASE64_STANDARD.encode(&buf)
+        ))
+    }
+
+    /// Graphics protocol the built-in renderer would use, for `imago doctor`
+    #[cfg(feature = "preview")]
+    pub fn graphics_protocol() -> &'static str {
+        match Self::detect_terminal_support() {
+            TerminalSupport::Kitty => "kitty",
+            TerminalSupport::ITerm2 => "iTerm2",
+            TerminalSupport::Sixel => "sixel",
+            TerminalSupport::Blocks => "block characters (no graphics protocol)",
+        }
+    }
+
+    #[cfg(not(feature = "preview"))]
+    pub fn graphics_protocol() -> &'static str {
+        "block characters (built without the preview feature, so no detection)"
+    }
+
+    /// Why automatic previews are skipped in this session, if they are: a
+    /// remote login, a CI job or output that is not a terminal
+    pub fn plain_session() -> Option<&'static str> {
+        let set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        if !std::io::stdout().is_terminal() {
+            Some("stdout is not a terminal")
+        } else if set("CI") && std::env::var("CI").as_deref() != Ok("false") {
+            Some("running in CI")
+        } else if set("SSH_TTY") || set("SSH_CONNECTION") {
+            Some("connected over SSH")
+        } else {
+            None
+        }
     }
 
     /// Detect terminal graphics support
+    #[cfg(feature = "preview")]
     fn detect_terminal_support() -> TerminalSupport {
         if get_kitty_support() != KittySupport::None {
             return TerminalSupport::Kitty;
@@ -190,13 +1150,29 @@ impl ImageHandler {
             }
         }
 
-        TerminalSupport::HalfBlocks
+        // Inside a multiplexer TERM names it, but these survive from the terminal
+        // the session was started in
+        if std::env::var_os("KITTY_WINDOW_ID").is_some() {
+            return TerminalSupport::Kitty;
+        }
+        if std::env::var("LC_TERMINAL").is_ok_and(|term| term == "iTerm2") {
+            return TerminalSupport::ITerm2;
+        }
+
+        // xterm, mlterm, foot and others only say so when asked
+        if graphics::sixel_supported() {
+            return TerminalSupport::Sixel;
+        }
+
+        TerminalSupport::Blocks
     }
 }
 
+#[cfg(feature = "preview")]
 #[derive(Debug, Clone, Copy)]
 enum TerminalSupport {
     Kitty,
     ITerm2,
-    HalfBlocks,
+    Sixel,
+    Blocks,
 }
@@ -0,0 +1,190 @@
+//! `imago init`: interactive first-run setup
+//!
+//! Asks for the provider, API key, model, output directory and preview
+//! preference, writes them with [`config::set`] so an existing file keeps its
+//! other settings and comments, then optionally runs a test generation. Also
+//! launched automatically on the first run when no key or config exists.
+
+use crate::auth;
+use crate::config;
+use crate::error::{ImagoError, Result};
+use crate::provider::Provider;
+use colored::Colorize;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const TEST_PROMPT: &str = "a small watercolor lighthouse on a cliff at sunrise";
+
+/// Run the wizard against the config file at `path`, offering a test
+/// generation at the end when `offer_test` is set
+pub fn run(path: &Path, offer_test: bool) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        return Err(ImagoError::ConfigError(
+            "imago init needs an interactive terminal; use `imago config set` in scripts"
+                .to_string(),
+        ));
+    }
+
+    println!("{}", "imago setup".bold());
+    println!(
+        "Settings are saved to {}\n",
+        path.display().to_string().cyan()
+    );
+
+    let provider = loop {
+        let answer = ask(
+            "Provider (gemini, openai, stability, replicate, fal, azure, bedrock)",
+            "gemini",
+        )?;
+        match answer.parse::<Provider>() {
+            Ok(provider) => break provider,
+            Err(e) => println!("{}", e.yellow()),
+        }
+    };
+    config::set(path, "provider", provider.name())?;
+
+    if let Some(var) = provider.api_key_env() {
+        setup_key(path, &provider, var)?;
+    }
+    match provider {
+        Provider::Azure => println!("  Also set AZURE_OPENAI_ENDPOINT to your resource URL."),
+        Provider::Bedrock => {
+            println!("  Also set AWS_SECRET_ACCESS_KEY (and AWS_REGION if not us-east-1).")
+        }
+        _ => {}
+    }
+
+    let model_question = match provider {
+        Provider::Azure => "Deployment name",
+        _ => "Model",
+    };
+    let model = ask(model_question, provider.default_model())?;
+    let model_key = match provider {
+        Provider::Azure => "deployment",
+        _ => "model",
+    };
+    config::set(path, model_key, &model)?;
+
+    let output = ask(
+        "Default output directory (empty for the current directory)",
+        "",
+    )?;
+    if !output.is_empty() {
+        config::set(path, "output", &output)?;
+    }
+
+    let preview = confirm("Show a preview in the terminal after generating?", true)?;
+    config::set(path, "no-preview", &(!preview).to_string())?;
+
+    println!("\n{} {}", "✅ Saved".green().bold(), path.display());
+
+    if offer_test && confirm("Run a test generation now?", true)? {
+        test_generation(path)?;
+    }
+    Ok(())
+}
+
+/// Keep the key from the environment, or store a pasted one in the system
+/// keychain, falling back to a private file next to the config
+fn setup_key(path: &Path, provider: &Provider, var: &str) -> Result<()> {
+    if std::env::var(var).is_ok_and(|v| !v.is_empty()) {
+        println!("  Using the API key from ${}.", var);
+        return Ok(());
+    }
+    if auth::get_key(provider).is_some() {
+        println!("  Using the API key stored in the system keychain.");
+        return Ok(());
+    }
+
+    println!(
+        "  Paste your API key (input is hidden), or press Enter to set ${} later.",
+        var
+    );
+    let key = match auth::read_key(provider) {
+        Ok(key) => key,
+        Err(ImagoError::AuthError(_)) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    match auth::set_key(provider, &key) {
+        Ok(()) => println!("  Key stored in the system keychain."),
+        Err(e) => {
+            let key_path = key_file(path, provider);
+            write_private(&key_path, &key)?;
+            config::set(path, "api-key-file", &key_path.display().to_string())?;
+            println!("  {}; key stored in {} instead.", e, key_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Re-run imago with the new config so the test goes through the normal path
+fn test_generation(path: &Path) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let status = Command::new(exe)
+        .arg(TEST_PROMPT)
+        .arg("--config")
+        .arg(path)
+        .status()?;
+    if !status.success() {
+        return Err(ImagoError::ConfigError(
+            "Test generation failed; adjust settings with `imago config set` or rerun `imago init`"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn key_file(config_path: &Path, provider: &Provider) -> PathBuf {
+    let dir = config_path.parent().unwrap_or(Path::new("."));
+    dir.join(format!("{}.key", provider.name()))
+}
+
+/// Write a file only the current user can read
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", contents)?;
+    Ok(())
+}
+
+/// Prompt for a line, returning `default` for an empty answer
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default.dimmed());
+    }
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(ImagoError::ConfigError("Setup cancelled".to_string()));
+    }
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = ask(&format!("{} ({})", question, hint), "")?;
+        match answer.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("{}", "Please answer y or n.".yellow()),
+        }
+    }
+}
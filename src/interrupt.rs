@@ -0,0 +1,100 @@
+//! Ctrl+C: stop cleanly instead of aborting mid-write
+//!
+//! Files that are only half written (atomic-save temp files, preview
+//! scratch files) are tracked while they exist. On Ctrl+C they are removed,
+//! the in-flight request is abandoned, the terminal is put back the way it
+//! was found (echo after a hidden key prompt, the cursor after the spinner)
+//! and imago exits with [`EXIT_INTERRUPTED`](crate::error::EXIT_INTERRUPTED).
+
+use crate::error::ImagoError;
+use crate::image_handler::{ImageHandler, OutputStyle, DEFAULT_PREVIEW_WIDTH};
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static PARTIAL: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Handle Ctrl+C for the rest of the process
+///
+/// Runs on its own task, so it fires even while the main task is blocked on
+/// a preview or a prompt.
+pub fn install(style: OutputStyle) {
+    let terminal = TerminalState::save();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        terminal.restore();
+        let partial = std::mem::take(&mut *PARTIAL.lock().unwrap_or_else(|e| e.into_inner()));
+        for path in partial {
+            let _ = fs::remove_file(path);
+        }
+        let error = ImagoError::Interrupted;
+        ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false)
+            .with_style(style)
+            .print_error(&error);
+        std::process::exit(error.exit_code());
+    });
+}
+
+/// Remove `path` if imago is interrupted before [`finished`] is called
+pub fn track(path: &Path) {
+    PARTIAL
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(path.to_path_buf());
+}
+
+/// `path` is complete or already gone
+pub fn finished(path: &Path) {
+    PARTIAL
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|partial| partial != path);
+}
+
+/// Terminal settings at startup, restored on Ctrl+C
+struct TerminalState {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl TerminalState {
+    #[cfg(unix)]
+    fn save() -> Self {
+        use std::os::fd::AsRawFd;
+
+        let saved = fs::File::open("/dev/tty").ok().and_then(|tty| {
+            // SAFETY: `termios` is plain data filled in by tcgetattr, and the
+            // descriptor stays open for as long as `tty` lives
+            let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+            (unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut saved) } == 0).then_some(saved)
+        });
+        Self { saved }
+    }
+
+    #[cfg(not(unix))]
+    fn save() -> Self {
+        Self {}
+    }
+
+    /// Turn echo back on after an interrupted key prompt, clear the spinner
+    /// line and show the cursor again
+    fn restore(&self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            use std::os::fd::AsRawFd;
+
+            if let Ok(tty) = fs::File::open("/dev/tty") {
+                // SAFETY: `saved` came from tcgetattr on the same terminal
+                unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, saved) };
+            }
+        }
+        let mut stderr = std::io::stderr();
+        if stderr.is_terminal() {
+            let _ = write!(stderr, "\r\x1b[2K\x1b[?25h");
+            let _ = stderr.flush();
+        }
+    }
+}
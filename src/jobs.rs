@@ -0,0 +1,254 @@
+//! Detached generation jobs
+//!
+//! `imago --detach` re-runs the same command in the background with a hidden
+//! `--job-id`, output going to a log file. The background process records its
+//! progress in `<data dir>/imago/jobs/<id>/job.json`, which `imago jobs` and
+//! `imago attach` read, so nothing is lost when the terminal goes away.
+
+use crate::error::{ImagoError, Result};
+use crate::image_handler::ImageHandler;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const JOB_FILE: &str = "job.json";
+const LOG_FILE: &str = "output.log";
+const ATTACH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lifecycle of a detached job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// State of a detached job, shared through its `job.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub prompt: String,
+    pub provider: String,
+    pub model: String,
+    pub pid: Option<u32>,
+    pub status: JobStatus,
+    pub started: String,
+    pub finished: Option<String>,
+    pub outputs: Vec<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Directory holding one subdirectory per job
+pub fn jobs_dir() -> Result<PathBuf> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("imago").join("jobs"))
+        .ok_or_else(|| ImagoError::JobError("No data directory available".to_string()))
+}
+
+impl Job {
+    /// Load a job by id
+    pub fn load(id: &str) -> Result<Job> {
+        let path = jobs_dir()?.join(id).join(JOB_FILE);
+        let text = fs::read_to_string(&path)
+            .map_err(|_| ImagoError::JobError(format!("No job with id '{}'", id)))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Write the job state, replacing the file atomically so readers never
+    /// see a partial write
+    fn save(&self) -> Result<()> {
+        let dir = jobs_dir()?.join(&self.id);
+        fs::create_dir_all(&dir)?;
+        let tmp = dir.join(format!("{}.tmp", JOB_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(JOB_FILE))?;
+        Ok(())
+    }
+
+    /// Log file capturing the background process output
+    pub fn log_path(&self) -> Result<PathBuf> {
+        Ok(jobs_dir()?.join(&self.id).join(LOG_FILE))
+    }
+
+    /// Whether a job marked running still has a live process
+    pub fn is_alive(&self) -> bool {
+        match self.pid {
+            Some(pid) => process_alive(pid),
+            // Not started yet
+            None => true,
+        }
+    }
+
+    /// Status for display, noticing jobs whose process disappeared
+    pub fn display_status(&self) -> &'static str {
+        match self.status {
+            JobStatus::Running if !self.is_alive() => "lost",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "done",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Register a job and re-run the current command in the background
+pub fn spawn(prompt: &str, provider: &str, model: &str) -> Result<Job> {
+    let id = format!("{:08x}", rand::random::<u32>());
+    let job = Job {
+        id: id.clone(),
+        prompt: prompt.to_string(),
+        provider: provider.to_string(),
+        model: model.to_string(),
+        pid: None,
+        status: JobStatus::Running,
+        started: Local::now().to_rfc3339(),
+        finished: None,
+        outputs: Vec::new(),
+        error: None,
+    };
+    // Saved before spawning so the child always finds its job file
+    job.save()?;
+
+    let log = fs::File::create(job.log_path()?)?;
+    // Flags added below are dropped first, since clap rejects repeated flags
+    let args = env::args_os().skip(1).filter(|arg| {
+        !matches!(
+            arg.to_str(),
+            Some("--detach" | "--no-preview" | "--no-color")
+        )
+    });
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .args(args)
+        .arg("--job-id")
+        .arg(&id)
+        .arg("--no-preview")
+        .arg("--no-color")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    #[cfg(unix)]
+    {
+        // Own session, away from the controlling terminal, so closing the
+        // terminal (and the SIGHUP that comes with it) doesn't take the job along
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setsid is async-signal-safe and touches no state shared
+        // with the parent
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    command.spawn()?;
+
+    Ok(job)
+}
+
+/// Mark a job as picked up by the background process
+pub fn start(id: &str) -> Result<()> {
+    let mut job = Job::load(id)?;
+    job.pid = Some(std::process::id());
+    job.save()
+}
+
+/// Record an image saved by the background process
+pub fn record_output(id: &str, path: &Path) -> Result<()> {
+    let mut job = Job::load(id)?;
+    job.outputs
+        .push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+    job.save()
+}
+
+/// Record the final outcome of a job
+pub fn finish<T>(id: &str, result: &Result<T>) -> Result<()> {
+    let mut job = Job::load(id)?;
+    job.finished = Some(Local::now().to_rfc3339());
+    match result {
+        Ok(_) => job.status = JobStatus::Succeeded,
+        Err(e) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(e.to_string());
+        }
+    }
+    job.save()
+}
+
+/// All known jobs, oldest first
+pub fn list() -> Result<Vec<Job>> {
+    let dir = jobs_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut jobs: Vec<Job> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join(JOB_FILE)).ok())
+        .filter_map(|text| serde_json::from_str(&text).ok())
+        .collect();
+    jobs.sort_by(|a, b| a.started.cmp(&b.started));
+    Ok(jobs)
+}
+
+/// Follow a job until it finishes, printing each image as it is saved
+pub async fn attach(id: &str, handler: &ImageHandler) -> Result<()> {
+    let mut shown = 0;
+    loop {
+        let job = Job::load(id)?;
+
+        for path in &job.outputs[shown..] {
+            let data = fs::read(path)?;
+            handler.print_result(path, &data);
+            let caption = ImageHandler::caption(&job.prompt, path);
+            if let Err(e) = handler.display_with_caption(&data, Some(&caption)) {
+                handler.print_warning(&format!("Could not display preview: {}", e));
+            }
+        }
+        shown = job.outputs.len();
+
+        match job.status {
+            JobStatus::Succeeded => return Ok(()),
+            JobStatus::Failed => {
+                return Err(ImagoError::JobError(format!(
+                    "Job {} failed: {}",
+                    id,
+                    job.error.as_deref().unwrap_or("unknown error")
+                )))
+            }
+            JobStatus::Running if !job.is_alive() => {
+                return Err(ImagoError::JobError(format!(
+                    "Job {} stopped without finishing; see {}",
+                    id,
+                    job.log_path()?.display()
+                )))
+            }
+            JobStatus::Running => tokio::time::sleep(ATTACH_POLL_INTERVAL).await,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Some(pid) = libc::pid_t::try_from(pid).ok().filter(|&pid| pid > 0) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists and may be signalled
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    // It exists but belongs to someone else
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
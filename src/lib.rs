@@ -0,0 +1,62 @@
+//! Image generation pipeline behind the `imago` command
+//!
+//! Provider clients, the shared HTTP client and the error type live here so
+//! other programs can generate images the same way the CLI does; the binary
+//! in `main.rs` only adds argument parsing, configuration and history.
+//!
+//! ```no_run
+//! use imago::{GenerationOptions, ImageClient, ImageHandler, Provider};
+//! use std::path::Path;
+//!
+//! # async fn run(api_key: String) -> imago::Result<()> {
+//! let http = imago::http::HttpSettings::default().client()?;
+//! let client = ImageClient::new(
+//!     Provider::Gemini,
+//!     api_key,
+//!     Provider::Gemini.default_model().to_string(),
+//!     http,
+//! );
+//! let result = client
+//!     .generate("a lighthouse at dusk", &GenerationOptions::default())
+//!     .await?;
+//!
+//! let handler = ImageHandler::new(60, None, false);
+//! for (i, image) in result.images.iter().enumerate() {
+//!     let path = format!("lighthouse-{}.png", i);
+//!     handler.save_image(&image.data, Path::new(&path)).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod bedrock;
+mod charart;
+pub mod dump;
+pub mod error;
+pub mod fal;
+pub mod gemini;
+#[cfg(test)]
+mod golden;
+mod graphics;
+pub mod http;
+pub mod image_handler;
+pub mod interrupt;
+pub mod mock;
+pub mod naming;
+pub mod openai;
+pub mod plugin;
+pub mod progress;
+pub mod provider;
+pub mod replicate;
+pub mod retry;
+pub mod session;
+pub mod sigv4;
+pub mod stability;
+
+pub use crate::error::{ImagoError, Result};
+pub use crate::gemini::{GeminiClient, GeminiClientBuilder};
+pub use crate::image_handler::ImageHandler;
+pub use crate::progress::Progress;
+pub use crate::provider::{
+    GeneratedImage, GenerationOptions, GenerationResult, ImageClient, Provider,
+};
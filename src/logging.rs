@@ -0,0 +1,138 @@
+//! Diagnostic logging on stderr
+//!
+//! Verbose messages are `tracing` events, and the phases of a generation
+//! (`request`, `download`, `decode`, `save`, `preview`) are spans around them. `RUST_LOG`
+//! picks what is shown, e.g. `RUST_LOG=imago=debug`; without it, `--verbose`
+//! shows imago's info events plus each phase's timing when it closes, and
+//! nothing is logged otherwise.
+//!
+//! Phase spans are also timed from open to close and summed by name, so a
+//! generation can end with one breakdown line (see [`take_phase_timing`]).
+
+use clap::ValueEnum;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Time spent in each phase since the last [`take_phase_timing`]
+static PHASES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One human-readable line per event
+    #[default]
+    Text,
+    /// One JSON object per event, for log collectors
+    Json,
+}
+
+/// Install the global subscriber; call once, after the config is applied
+pub fn init(verbose: bool, format: LogFormat, color: bool) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(if verbose { "imago=info" } else { "off" }));
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false);
+    let fmt = match format {
+        LogFormat::Text => fmt.with_ansi(color).boxed(),
+        LogFormat::Json => fmt.json().with_current_span(true).boxed(),
+    };
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt)
+        .with(PhaseTimer)
+        .try_init();
+}
+
+/// `request 12.4s (download 3.1s, decode 0.2s), save 0.1s, preview 0.5s` for
+/// the phases timed since the last call; `None` when nothing was logged
+pub fn take_phase_timing() -> Option<String> {
+    let phases = std::mem::take(&mut *PHASES.lock().unwrap_or_else(|e| e.into_inner()));
+    format_phases(&phases)
+}
+
+fn format_phases(phases: &[(&str, Duration)]) -> Option<String> {
+    let total = |name: &str| phases.iter().find(|(n, _)| *n == name).map(|(_, d)| *d);
+    let timed = |name: &str| total(name).map(|d| format!("{} {:.1}s", name, d.as_secs_f64()));
+
+    let mut parts = Vec::new();
+    if let Some(request) = timed("request") {
+        // Downloading and decoding happen inside the request
+        let inner: Vec<String> = ["download", "decode"]
+            .into_iter()
+            .filter_map(timed)
+            .collect();
+        if inner.is_empty() {
+            parts.push(request);
+        } else {
+            parts.push(format!("{} ({})", request, inner.join(", ")));
+        }
+    }
+    parts.extend(["save", "preview"].into_iter().filter_map(timed));
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Adds the lifetime of every closed span to [`PHASES`]
+struct PhaseTimer;
+
+/// When a span was created
+struct Opened(Instant);
+
+impl<S> Layer<S> for PhaseTimer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Opened(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span.extensions().get::<Opened>().map(|o| o.0.elapsed()) else {
+            return;
+        };
+        let mut phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+        match phases.iter_mut().find(|(name, _)| *name == span.name()) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((span.name(), elapsed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_phases_with_request_internals_nested() {
+        let secs = Duration::from_secs_f64;
+        let phases = [
+            ("decode", secs(0.2)),
+            ("request", secs(12.4)),
+            ("preview", secs(0.5)),
+            ("download", secs(3.1)),
+            ("save", secs(0.1)),
+        ];
+        assert_eq!(
+            format_phases(&phases).as_deref(),
+            Some("request 12.4s (download 3.1s, decode 0.2s), save 0.1s, preview 0.5s")
+        );
+        assert_eq!(
+            format_phases(&[("save", secs(0.1))]).as_deref(),
+            Some("save 0.1s")
+        );
+        assert_eq!(format_phases(&[]), None);
+    }
+}
@@ -1,85 +1,1518 @@
+mod alt_text;
+mod auth;
+mod cache;
+mod calibrate;
 mod cli;
-mod error;
-mod gemini;
-mod image_handler;
+mod config;
+mod doctor;
+mod emit;
+mod examples;
+mod exif;
+#[cfg(feature = "gallery")]
+mod gallery;
+mod history;
+mod init;
+mod jobs;
+mod logging;
+mod manifest;
+mod metadata;
+mod models;
+mod notes;
+mod pipe;
+mod pricing;
+mod probe;
+mod prompt_log;
+mod publish;
+mod report;
+mod run_log;
+mod spinner;
+mod trash;
+mod usage;
 
-use crate::cli::Cli;
+use imago::{
+    dump, error, http, image_handler, interrupt, naming, progress, provider, retry, session,
+};
+
+use crate::cache::CacheKind;
+use crate::cli::{AuthAction, CacheAction, Cli, Command, ConfigAction, HistoryAction, TrashAction};
+use crate::config::KeySource;
 use crate::error::{ImagoError, Result};
-use crate::gemini::GeminiClient;
-use crate::image_handler::ImageHandler;
-use clap::Parser;
-use colored::control;
+use crate::history::{Filter, History, HistoryImage, Run, RunStatus};
+use crate::image_handler::{ImageHandler, OutputStyle, DEFAULT_PREVIEW_WIDTH};
+use crate::manifest::{Manifest, ManifestOptions, ManifestOutput};
+use crate::metadata::ImageMetadata;
+use crate::naming::NameFields;
+use crate::notes::VaultNote;
+use crate::prompt_log::PromptLogEntry;
+use crate::provider::{GenerationOptions, ImageClient, Usage};
+use crate::report::{OutputFormat, Report, ReportImage};
+use crate::retry::RetryPolicy;
+use crate::spinner::Spinner;
+use crate::usage::UsageRecord;
+use chrono::Local;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use colored::{control, Colorize};
 use std::env;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 #[tokio::main]
 async fn main() {
+    // Windows consoles only act on colors and other escape sequences once
+    // asked to; where that fails, everything stays plain text
+    #[cfg(windows)]
+    if control::set_virtual_terminal(true).is_err() {
+        control::set_override(false);
+    }
+
+    // Load .env before parsing so it can provide API keys and IMAGO_* options;
+    // the opt-out has to be checked by hand for the same reason, and only
+    // among the flags before a `--`
+    let skip_dotenv = std::env::args_os()
+        .skip(1)
+        .take_while(|arg| arg != "--")
+        .any(|arg| arg == "--no-dotenv")
+        || std::env::var("IMAGO_NO_DOTENV").is_ok_and(|v| v == "true");
+    if !skip_dotenv {
+        let handler = ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false);
+        match config::load_dotenv() {
+            Ok(ignored) if !ignored.is_empty() => handler.print_warning(&format!(
+                "Ignored {} from {}: it may only set provider keys and output, style and \
+                 preview options. Export the rest in your shell or use the user config",
+                ignored.join(", "),
+                config::DOTENV_FILE
+            )),
+            Ok(_) => {}
+            Err(e) => handler.print_warning(&e.to_string()),
+        }
+    }
+
     // Parse CLI arguments
-    let cli = Cli::parse();
+    let matches = examples::with_examples(Cli::command()).get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    cli.explicit = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect();
+
+    // Fill in defaults from the config file; `imago init` and `imago config`
+    // skip this so they can still repair a file that no longer parses
+    let loaded = if matches!(cli.command, Some(Command::Init | Command::Config { .. })) {
+        Ok(())
+    } else {
+        config::load(cli.config.as_deref()).and_then(|c| c.apply(&mut cli, &matches))
+    };
+    if let Err(e) = loaded {
+        let handler =
+            ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false).with_style(cli.output_style());
+        handler.print_error(&e);
+        std::process::exit(e.exit_code());
+    }
 
-    // Setup colored output
-    if cli.no_color {
+    // Setup colored output; launchers and screen readers never get escape sequences
+    if cli.no_color || cli.launcher_mode || cli.a11y {
         control::set_override(false);
     }
 
+    // Shell completion hook: answer quietly, even when something is misconfigured
+    if cli.complete_model {
+        let api_key = resolve_api_keys(&cli)
+            .ok()
+            .map(|mut keys| keys.swap_remove(0));
+        for name in models::completions(&cli.provider, api_key.as_deref()).await {
+            println!("{}", name);
+        }
+        return;
+    }
+
     // Validate arguments
     if let Err(e) = cli.validate() {
-        let handler = ImageHandler::new(cli.width, cli.height, false);
+        let handler = ImageHandler::new(cli.preview_width(), cli.height, false)
+            .with_style(cli.output_style());
         handler.print_error(&e);
-        std::process::exit(1);
+        std::process::exit(error::EXIT_USAGE);
+    }
+
+    logging::init(
+        cli.verbose && !cli.launcher_mode,
+        cli.log_format,
+        !(cli.no_color || cli.launcher_mode || cli.a11y),
+    );
+
+    if cli.insecure {
+        ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false)
+            .with_style(cli.output_style())
+            .print_warning(
+                "--insecure: TLS certificate verification is OFF. Anyone on the network path \
+                 can read your API key and prompts. Use --ca-cert with your proxy's CA instead.",
+            );
+    }
+
+    http::limit_response_size(cli.max_response_size);
+    let traffic = cli
+        .dump_http
+        .as_deref()
+        .map_or(Ok(()), dump::enable)
+        .and_then(|_| match (&cli.record, &cli.replay) {
+            (Some(path), _) => session::record(path),
+            (None, Some(path)) => session::replay(path),
+            (None, None) => Ok(()),
+        });
+    if let Err(e) = traffic {
+        let handler =
+            ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false).with_style(cli.output_style());
+        handler.print_error(&e);
+        std::process::exit(e.exit_code());
     }
 
     // Run the application
-    if let Err(e) = run(cli).await {
-        let handler = ImageHandler::new(60, None, false);
+    interrupt::install(cli.output_style());
+    let result = match cli.command.take() {
+        Some(command) => run_command(&cli, command).await,
+        None => run(&cli).await,
+    };
+    if let Err(e) = result {
+        let handler =
+            ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false).with_style(cli.output_style());
         handler.print_error(&e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
     }
 }
 
-async fn run(cli: Cli) -> Result<()> {
-    // Get API key
-    let api_key = cli
-        .api_key
-        .or_else(|| env::var("GEMINI_API_KEY").ok())
-        .ok_or(ImagoError::MissingApiKey)?;
+async fn run_command(cli: &Cli, command: Command) -> Result<()> {
+    match command {
+        Command::PublishGallery { dir, format, title } => {
+            let published = publish::publish(&dir, format, title.as_deref())?;
+            let handler = ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false)
+                .with_style(cli.output_style());
+            for (path, e) in &published.skipped {
+                handler.print_warning(&format!("Skipped {}: {}", path.display(), e));
+            }
+            handler.print_success(&published.page);
+            Ok(())
+        }
+        Command::Note {
+            prompt,
+            vault,
+            note,
+        } => {
+            let note = VaultNote::new(&vault, &note)?;
+            let attachments = note.attachment_dir().join("");
+            for path in generate(cli, &prompt, vec![attachments]).await? {
+                let note_path = note.append_embed(&path)?;
+                tracing::info!("Linked into {}", note_path.display());
+            }
+            Ok(())
+        }
+        Command::Init => init::run(&config::target_path(cli.config.as_deref(), false)?, true),
+        Command::Auth { action } => {
+            let handler = ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false)
+                .with_style(cli.output_style());
+            let provider = &cli.provider;
+            if !matches!(action, AuthAction::Check) && provider.api_key_env().is_none() {
+                return Err(ImagoError::AuthError(format!(
+                    "{} does not use an API key",
+                    provider
+                )));
+            }
+            match action {
+                AuthAction::Check => return check_keys(cli, &handler).await,
+                AuthAction::SetKey => {
+                    auth::set_key(provider, &auth::read_key(provider)?)?;
+                    println!("Stored the {} API key in the system keychain", provider);
+                }
+                AuthAction::DeleteKey => {
+                    if auth::delete_key(provider)? {
+                        println!("Removed the {} API key from the system keychain", provider);
+                    } else {
+                        handler.print_warning(&format!("No stored key for {}", provider));
+                    }
+                }
+            }
+            Ok(())
+        }
+        Command::Doctor => {
+            let checks = doctor::run(cli, resolve_api_keys(cli)).await;
+            match doctor::print(cli, &checks) {
+                0 => Ok(()),
+                failed => Err(ImagoError::ChecksFailed(failed)),
+            }
+        }
+        Command::Models { all } => {
+            let api_key = resolve_api_keys(cli)?.swap_remove(0);
+            let client = cli.http_settings().client()?;
+            let models = models::list(&client, &cli.provider, &api_key).await?;
+            models::save_cache(&cli.provider, &models);
+            let models: Vec<_> = models
+                .into_iter()
+                .filter(|model| all || model.is_image_model())
+                .collect();
+            if models.is_empty() {
+                println!("No image models available to this key");
+                return Ok(());
+            }
+
+            let limit = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+            let width = models.iter().map(|m| m.name.len()).max().unwrap_or(0);
+            println!(
+                "{:width$}  {:>8}  {:>8}  METHODS",
+                "MODEL", "INPUT", "OUTPUT"
+            );
+            for model in &models {
+                let name = format!("{:width$}", model.name);
+                let name = if model.name == cli.model() {
+                    name.bold()
+                } else {
+                    name.normal()
+                };
+                println!(
+                    "{}  {:>8}  {:>8}  {}",
+                    name,
+                    limit(model.input_token_limit),
+                    limit(model.output_token_limit),
+                    model.methods.join(", ")
+                );
+            }
+            Ok(())
+        }
+        Command::Calibrate { probes, dry_run } => {
+            let api_key = resolve_api_keys(cli)?.swap_remove(0);
+            let model = cli.model();
+            println!(
+                "Probing {} ({}) with metadata requests; no images are generated",
+                cli.provider, model
+            );
+            let client = cli.http_settings().client()?;
+            let result = calibrate::run(&client, &cli.provider, &api_key, &model, probes).await?;
+
+            println!("Probes sent:      {}", result.probes);
+            println!("Median latency:   {} ms", result.median_latency.as_millis());
+            if let Some(limit) = result.advertised_rpm {
+                println!("Advertised limit: {} requests/min", limit);
+            }
+            println!(
+                "Peak rate:        {} requests/min{}",
+                result.peak_rpm,
+                if result.hit_limit {
+                    " (rate limited above this)"
+                } else {
+                    " (no limit reached)"
+                }
+            );
+            println!(
+                "Recommended:      --jobs {} --rpm {}",
+                result.jobs, result.rpm
+            );
 
-    if cli.verbose {
-        println!("Using model: {}", cli.model);
+            if dry_run {
+                return Ok(());
+            }
+            let path = config::target_path(cli.config.as_deref(), false)?;
+            let prefix = match &cli.profile {
+                Some(profile) => format!("profile.{}.", profile),
+                None => String::new(),
+            };
+            config::set(&path, &format!("{}jobs", prefix), &result.jobs.to_string())?;
+            config::set(&path, &format!("{}rpm", prefix), &result.rpm.to_string())?;
+            println!(
+                "Saved to {}{}",
+                path.display(),
+                cli.profile
+                    .as_ref()
+                    .map(|p| format!(" [profile.{}]", p))
+                    .unwrap_or_default()
+            );
+            Ok(())
+        }
+        Command::Config { project, action } => {
+            let path = config::target_path(cli.config.as_deref(), project)?;
+            match action {
+                ConfigAction::Get { key } => match config::get(&path, &key)? {
+                    Some(value) => println!("{}", value),
+                    None => {
+                        return Err(ImagoError::ConfigError(format!(
+                            "{} is not set in {}",
+                            key,
+                            path.display()
+                        )))
+                    }
+                },
+                ConfigAction::Set { key, value } => config::set(&path, &key, &value)?,
+                ConfigAction::Unset { key } => config::unset(&path, &key)?,
+                ConfigAction::List => {
+                    for (key, value) in config::list(&path)? {
+                        println!("{} = {}", key, value);
+                    }
+                }
+                ConfigAction::Path => println!("{}", path.display()),
+            }
+            Ok(())
+        }
+        Command::Examples { topic } => examples::print(topic.as_deref()),
+        Command::Jobs => {
+            for job in jobs::list()? {
+                println!(
+                    "{}  {:<8} {}  {} image(s)  {}",
+                    job.id,
+                    job.display_status(),
+                    job.started,
+                    job.outputs.len(),
+                    job.prompt
+                );
+            }
+            Ok(())
+        }
+        Command::Gallery { search, tag } => {
+            let filter = Filter {
+                search,
+                model: cli.model.clone().filter(|_| cli.is_explicit("model")),
+                tag,
+                ..Filter::default()
+            };
+            browse_gallery(cli, &filter).await
+        }
+        Command::History {
+            action,
+            search,
+            tag,
+            since,
+            limit,
+        } => {
+            let history = History::open()?;
+            if let Some(HistoryAction::Show { id }) = action {
+                let run = history
+                    .get(id)?
+                    .ok_or_else(|| ImagoError::HistoryError(format!("No run with id {}", id)))?;
+                return show_run(cli, &run);
+            }
+
+            let mut filter = Filter {
+                search,
+                model: cli.model.clone().filter(|_| cli.is_explicit("model")),
+                tag,
+                since: since.as_deref().map(history::parse_since).transpose()?,
+                limit: Some(limit),
+            };
+            if let Some(HistoryAction::Export { format }) = action {
+                filter.limit = None;
+                let runs = history.search(&filter)?;
+                return history::export(&runs, format, &mut std::io::stdout().lock());
+            }
+            let runs = history.search(&filter)?;
+            if runs.is_empty() {
+                println!("No matching generations");
+            }
+            for run in &runs {
+                print_run_summary(run);
+            }
+            Ok(())
+        }
+        Command::Tag { id, tag, remove } => tag_run(id, tag.trim(), remove),
+        Command::Fav { id, remove } => tag_run(id, history::FAVORITE_TAG, remove),
+        Command::Usage { since } => {
+            if let Some(since) = &since {
+                chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d").map_err(|_| {
+                    ImagoError::InvalidArgument(format!(
+                        "--since expects YYYY-MM-DD, got '{}'",
+                        since
+                    ))
+                })?;
+            }
+            let summary = usage::summarize(&usage::read_all()?, since.as_deref());
+            if summary.is_empty() {
+                println!("No usage recorded yet");
+                return Ok(());
+            }
+
+            let width = summary.keys().map(|(_, m)| m.len()).max().unwrap_or(0);
+            println!(
+                "{:10}  {:width$}  {:>5}  {:>6}  {:>10}  {:>10}  {:>10}",
+                "DAY", "MODEL", "RUNS", "IMAGES", "INPUT", "OUTPUT", "TOTAL"
+            );
+            let mut total = usage::UsageSummary::default();
+            for ((day, model), row) in &summary {
+                println!(
+                    "{:10}  {:width$}  {:>5}  {:>6}  {:>10}  {:>10}  {:>10}",
+                    day,
+                    model,
+                    row.runs,
+                    row.images,
+                    row.input_tokens,
+                    row.output_tokens,
+                    row.total_tokens
+                );
+                total.merge(row);
+            }
+            println!(
+                "{:10}  {:width$}  {:>5}  {:>6}  {:>10}  {:>10}  {:>10}",
+                "total",
+                "",
+                total.runs,
+                total.images,
+                total.input_tokens,
+                total.output_tokens,
+                total.total_tokens
+            );
+            if let Some(budget) = cli.budget {
+                let spent =
+                    usage::spend_since(&usage::read_all()?, &usage::month_start(), &cli.pricing);
+                println!(
+                    "\nThis month: ~{} of {} budget",
+                    pricing::format_usd(spent),
+                    pricing::format_usd(budget)
+                );
+            }
+            Ok(())
+        }
+        Command::Trash { action } => {
+            let handler = ImageHandler::new(DEFAULT_PREVIEW_WIDTH, None, false)
+                .with_style(cli.output_style());
+            match action {
+                TrashAction::Put { files } => {
+                    for file in files {
+                        let entry = trash::put(&file)?;
+                        if cli.launcher_mode {
+                            println!("{}", entry.id);
+                        } else {
+                            println!("Trashed {} ({})", file.display(), entry.id);
+                        }
+                    }
+                }
+                TrashAction::List => {
+                    trash::purge_expired()?;
+                    for entry in trash::list()? {
+                        println!(
+                            "{}  {}  {}",
+                            entry.id,
+                            entry.deleted,
+                            entry.original_path.display()
+                        );
+                    }
+                }
+                TrashAction::Restore { id } => {
+                    let path = trash::restore(&id)?;
+                    handler.print_success(&path);
+                }
+                TrashAction::Empty => {
+                    let removed = trash::empty()?;
+                    println!("Permanently deleted {} file(s)", removed);
+                }
+            }
+            Ok(())
+        }
+        Command::Cache { action } => {
+            let describe = |stats: &cache::FileStats| {
+                format!(
+                    "{} file(s), {}",
+                    stats.files,
+                    cache::format_bytes(stats.bytes)
+                )
+            };
+            match action {
+                CacheAction::Stats => {
+                    let stats = cache::stats()?;
+                    let oldest = |stats: &cache::FileStats| match stats.oldest {
+                        Some(oldest) => {
+                            let age = oldest.elapsed().unwrap_or_default().as_secs();
+                            format!(", oldest {}d {}h", age / 86400, age % 86400 / 3600)
+                        }
+                        None => String::new(),
+                    };
+                    println!(
+                        "Responses: {}{} in {}",
+                        describe(&stats.responses),
+                        oldest(&stats.responses),
+                        cache::default_dir()?.display()
+                    );
+                    println!(
+                        "Previews:  {}{} in {}",
+                        describe(&stats.previews),
+                        oldest(&stats.previews),
+                        env::temp_dir().display()
+                    );
+                }
+                CacheAction::Clear => {
+                    println!("Deleted {}", describe(&cache::prune(None)?));
+                }
+                CacheAction::Prune { older_than } => {
+                    let older_than = match older_than.as_deref().or(cli.cache_ttl.as_deref()) {
+                        Some(age) => cache::parse_duration(age)?,
+                        None => cache::DEFAULT_TTL,
+                    };
+                    println!("Deleted {}", describe(&cache::prune(Some(older_than))?));
+                }
+            }
+            Ok(())
+        }
+        Command::Attach { id } => {
+            let handler = cli.image_handler();
+            jobs::attach(&id, &handler).await
+        }
+        Command::Reproduce { path } => reproduce(cli, &path).await,
+        Command::Redo { id } => redo(cli, id).await,
+    }
+}
+
+/// `imago tag` and `imago fav`
+fn tag_run(id: i64, tag: &str, remove: bool) -> Result<()> {
+    if tag.is_empty() {
+        return Err(ImagoError::HistoryError(
+            "Tag must not be empty".to_string(),
+        ));
+    }
+    let history = History::open()?;
+    if !remove {
+        history.add_tag(id, tag)?;
+        println!("Tagged #{} {}", id, tag);
+    } else if history.remove_tag(id, tag)? {
+        println!("Removed {} from #{}", tag, id);
+    } else {
+        println!("#{} is not tagged {}", id, tag);
     }
+    Ok(())
+}
 
-    // Create components
-    let client = GeminiClient::new(api_key, cli.model.clone());
-    let handler = ImageHandler::new(cli.width, cli.height, !cli.no_preview);
+/// Browse history in the interactive gallery, redoing the run picked there
+#[cfg(feature = "gallery")]
+async fn browse_gallery(cli: &Cli, filter: &Filter) -> Result<()> {
+    match gallery::run(filter)? {
+        gallery::Exit::Redo(id) => redo(cli, Some(id)).await,
+        gallery::Exit::Quit => Ok(()),
+    }
+}
 
-    // Print generation message
-    handler.print_generating(&cli.prompt);
+#[cfg(not(feature = "gallery"))]
+async fn browse_gallery(_cli: &Cli, _filter: &Filter) -> Result<()> {
+    Err(ImagoError::ConfigError(
+        "this build has no gallery; rebuild with the `gallery` feature".to_string(),
+    ))
+}
 
-    // Generate image
-    let (image_data, _) = client.generate_image(&cli.prompt).await?;
+/// Re-run a recorded generation; settings typed on the command line win
+async fn redo(cli: &Cli, id: Option<i64>) -> Result<()> {
+    let history = History::open()?;
+    let run = match id {
+        Some(id) => history
+            .get(id)?
+            .ok_or_else(|| ImagoError::HistoryError(format!("No run with id {}", id)))?,
+        None => history
+            .search(&Filter {
+                limit: Some(1),
+                ..Filter::default()
+            })?
+            .pop()
+            .ok_or_else(|| ImagoError::HistoryError("No generations recorded yet".to_string()))?,
+    };
+    drop(history);
 
-    if cli.verbose {
-        println!("Image generated: {} bytes", image_data.len());
+    let mut again = cli.clone();
+    // A different provider gets its own default model unless one is given
+    if !cli.is_explicit("provider") {
+        again.provider = run
+            .provider
+            .parse()
+            .map_err(|e: String| ImagoError::HistoryError(e))?;
+        if !cli.is_explicit("model") {
+            again.model = Some(run.model.clone());
+            again.deployment = Some(run.model.clone());
+        }
+    }
+    if !cli.is_explicit("count") {
+        again.count = run.count;
+    }
+    if !cli.is_explicit("size") {
+        again.size = run.size.clone();
+    }
+    if !cli.is_explicit("quality") {
+        again.quality = run.quality.clone();
+    }
+    if !cli.is_explicit("seed") {
+        again.seed = run.seed;
+    }
+    if !cli.is_explicit("cfg_scale") {
+        again.cfg_scale = run.cfg_scale;
+    }
+    if !cli.is_explicit("negative_prompt") {
+        again.negative_prompt = run.negative_prompt.clone();
     }
+    // The recorded prompt already includes the style preset
+    again.style = None;
+    again.no_cache = true;
+    again.detach = false;
 
-    // Resolve output path
-    let output_path = handler.resolve_output_path(cli.output.as_deref());
+    println!("Redoing #{}: {}", run.id, run.prompt);
+    generate(&again, &run.prompt, cli.output.clone()).await?;
+    Ok(())
+}
 
-    // Save the image
-    handler.save_image(&image_data, &output_path).await?;
+/// Regenerate an image from its manifest and compare it with the original
+async fn reproduce(cli: &Cli, manifest_path: &Path) -> Result<()> {
+    let record = manifest::read(manifest_path)?;
+    let dir = manifest_path.parent().unwrap_or(Path::new("."));
 
-    // Print success message
-    handler.print_success(&output_path);
+    match manifest::verify(manifest_path)? {
+        Some(true) => println!("Signature: good"),
+        Some(false) => {
+            return Err(ImagoError::ManifestError(format!(
+                "Signature check failed for {}",
+                manifest_path.display()
+            )))
+        }
+        None => println!("Signature: none"),
+    }
 
-    // Display in terminal
-    if !cli.no_preview {
-        println!();
-        match handler.display_in_terminal(&image_data) {
-            Ok(_) => {}
+    let original = dir.join(&record.output.file);
+    match fs::read(&original) {
+        Ok(data) if manifest::sha256_hex(&data) == record.output.sha256 => {
+            println!("Original: {} matches the manifest", original.display())
+        }
+        Ok(_) => println!(
+            "Original: {} was modified after generation",
+            original.display()
+        ),
+        Err(_) => println!("Original: {} not found", original.display()),
+    }
+
+    // Same request, nothing layered on top: no style preset, one image, no cache
+    let mut replay = cli.clone();
+    replay.provider = record
+        .provider
+        .parse()
+        .map_err(|e: String| ImagoError::ManifestError(e))?;
+    replay.model = Some(record.model.clone());
+    replay.deployment = Some(record.model.clone());
+    replay.seed = record.seed;
+    replay.size = record.options.size.clone();
+    replay.quality = record.options.quality.clone();
+    replay.cfg_scale = record.options.cfg_scale;
+    replay.negative_prompt = record.options.negative_prompt.clone();
+    replay.low_bandwidth = record.options.compressed;
+    replay.style = None;
+    replay.count = 1;
+    replay.no_cache = true;
+    replay.manifest = false;
+    replay.detach = false;
+
+    let output = cli.output.first().cloned().unwrap_or_else(|| {
+        let stem = Path::new(&record.output.file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".to_string());
+        dir.join(format!("{}.reproduced.png", stem))
+    });
+    let saved = generate(&replay, &record.prompt, vec![output]).await?;
+    let reproduced = saved
+        .first()
+        .ok_or_else(|| ImagoError::ManifestError("Provider returned no image".to_string()))?;
+
+    let pixels = manifest::pixels_sha256(&fs::read(reproduced)?);
+    match (&pixels, &record.output.pixels_sha256) {
+        (Some(new), Some(old)) if new == old => {
+            println!("Pixels: identical to the original");
+            Ok(())
+        }
+        _ => Err(ImagoError::ManifestError(format!(
+            "{} differs from the original; {} may not be deterministic even with a fixed seed",
+            reproduced.display(),
+            record.provider
+        ))),
+    }
+}
+
+async fn run(cli: &Cli) -> Result<()> {
+    let prompt = cli.prompt.clone().unwrap_or_default();
+
+    if is_first_run(cli) {
+        return first_run_setup();
+    }
+
+    if cli.detach {
+        let job = jobs::spawn(&prompt, &cli.provider.to_string(), &cli.model())?;
+        if cli.launcher_mode || cli.quiet {
+            println!("{}", job.id);
+        } else {
+            println!("Started job {} in the background", job.id);
+            println!("Follow it with: imago attach {}", job.id);
+        }
+        return Ok(());
+    }
+
+    // With --emit and no explicit output, save into the site's asset directory
+    let mut output = cli.output.clone();
+    if let (true, Some(site)) = (output.is_empty(), cli.emit) {
+        output.push(cli.site_root.join(site.asset_dir()).join(""));
+    }
+
+    match &cli.job_id {
+        Some(id) => {
+            jobs::start(id)?;
+            let result = generate(cli, &prompt, output).await;
+            jobs::finish(id, &result)?;
+            result?;
+        }
+        None => {
+            generate(cli, &prompt, output).await?;
+        }
+    }
+    Ok(())
+}
+
+/// `imago auth check`: validate every configured key with a metadata request
+async fn check_keys(cli: &Cli, handler: &ImageHandler) -> Result<()> {
+    let keys = resolve_api_keys(cli)?;
+    let client = cli.http_settings().client()?;
+    let mut rejected = 0;
+    for (i, key) in keys.iter().enumerate() {
+        let label = key_label(i, key);
+        match probe::check(&client, &cli.provider, key).await {
+            Ok(info) => {
+                println!("{}: {} for {}", label, "valid".green(), cli.provider);
+                if let Some(account) = &info.account {
+                    println!("  Account: {}", account);
+                }
+                if !info.models.is_empty() {
+                    println!("  Image models: {}", info.models.join(", "));
+                }
+            }
+            Err(e) if e.is_rate_limited() => handler.print_warning(&format!(
+                "{} is accepted but rate limited or out of quota",
+                label
+            )),
+            Err(e @ ImagoError::ApiError { .. }) => {
+                rejected += 1;
+                handler.print_warning(&format!("{} was rejected: {}", label, e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if rejected > 0 {
+        return Err(ImagoError::AuthError(format!(
+            "{} of {} {} key(s) rejected",
+            rejected,
+            keys.len(),
+            cli.provider
+        )));
+    }
+    Ok(())
+}
+
+/// Refuse to call the provider once this month's estimated spend reaches the
+/// configured budget, or only warn with --force or --ignore-budget
+fn check_budget(cli: &Cli, handler: &ImageHandler) -> Result<()> {
+    let Some(budget) = cli.budget else {
+        return Ok(());
+    };
+    let spent = usage::spend_since(&usage::read_all()?, &usage::month_start(), &cli.pricing);
+    if spent < budget {
+        return Ok(());
+    }
+    let message = format!(
+        "estimated spend this month is ~{} of a {} budget",
+        pricing::format_usd(spent),
+        pricing::format_usd(budget)
+    );
+    if cli.force || cli.ignore_budget {
+        handler.print_warning(&format!(
+            "Budget exceeded: {}; continuing because of {}",
+            message,
+            if cli.force {
+                "--force"
+            } else {
+                "--ignore-budget"
+            }
+        ));
+        Ok(())
+    } else {
+        Err(ImagoError::BudgetExceeded(format!(
+            "{}. Pass --ignore-budget to generate anyway or raise `budget` in the config",
+            message
+        )))
+    }
+}
+
+/// No key anywhere and no config file yet, in an interactive terminal
+fn is_first_run(cli: &Cli) -> bool {
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let unconfigured = cli.config.is_none()
+        && config::default_path().is_some_and(|path| !path.exists())
+        && env::current_dir()
+            .ok()
+            .and_then(|cwd| config::find_project(&cwd))
+            .is_none();
+    interactive
+        && unconfigured
+        && !cli.launcher_mode
+        && cli.job_id.is_none()
+        && matches!(resolve_api_keys(cli), Err(ImagoError::MissingApiKey(_)))
+}
+
+/// Run the setup wizard, then the original command again with the new config
+fn first_run_setup() -> Result<()> {
+    println!("No API key or config found, starting setup.\n");
+    init::run(&config::target_path(None, false)?, false)?;
+    println!();
+
+    let status = std::process::Command::new(env::current_exe()?)
+        .args(env::args_os().skip(1))
+        .status()?;
+    if !status.success() {
+        // The rerun already reported its error
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// API keys for the selected provider: --api-key, then --api-key-file, then
+/// the profile's key source, then the provider env var, then the system keychain
+///
+/// Any of these may hold several keys separated by commas or newlines; they
+/// are tried in order when one is rate limited. Never empty. Under `--replay`
+/// no key is needed and the recorded placeholder is used.
+fn resolve_api_keys(cli: &Cli) -> Result<Vec<String>> {
+    if cli.replay.is_some() {
+        return Ok(vec![session::REPLAY_KEY.to_string()]);
+    }
+    let key_file = cli.api_key_file.clone().map(KeySource::File);
+    let api_key = match (&cli.api_key, key_file.as_ref().or(cli.key_source.as_ref())) {
+        (Some(key), _) => Some(key.clone()),
+        (None, Some(source)) => Some(source.resolve()?),
+        (None, None) => cli
+            .provider
+            .api_key_env()
+            .and_then(|name| env::var(name).ok())
+            .or_else(|| auth::get_key(&cli.provider)),
+    };
+    let keys = api_key
+        .map(|key| split_keys(&key))
+        .filter(|keys| !keys.is_empty());
+    match (keys, cli.provider.api_key_env()) {
+        (Some(keys), _) => Ok(keys),
+        (None, Some(api_key_env)) => Err(ImagoError::MissingApiKey(api_key_env)),
+        (None, None) => Ok(vec![String::new()]),
+    }
+}
+
+fn split_keys(value: &str) -> Vec<String> {
+    value
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Identify a key in messages without revealing it
+fn key_label(index: usize, key: &str) -> String {
+    let tail = key.get(key.len().saturating_sub(4)..).unwrap_or_default();
+    format!("key {} (...{})", index + 1, tail)
+}
+
+/// Generate, save and preview images for `prompt`, returning the saved paths;
+/// the first of `outputs` is the primary copy that history, manifests and
+/// printed results refer to, the rest get plain copies
+async fn generate(cli: &Cli, prompt: &str, outputs: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let started = Instant::now();
+    // `-o -` streams the image to stdout, alongside any file destinations
+    let stream = outputs.iter().any(|path| path.as_os_str() == "-");
+    let outputs: Vec<PathBuf> = outputs
+        .into_iter()
+        .filter(|path| path.as_os_str() != "-")
+        .collect();
+    let save = !stream || !outputs.is_empty();
+    let output = outputs.first().map(PathBuf::as_path);
+    let destinations: Vec<Option<&Path>> = if outputs.is_empty() {
+        vec![None]
+    } else {
+        outputs.iter().map(|path| Some(path.as_path())).collect()
+    };
+    // File names describe the prompt without the style preset
+    let name_prompt = prompt;
+    let prompt = &cli.styled_prompt(prompt);
+    let api_keys = resolve_api_keys(cli)?;
+
+    let model = cli.model();
+    let handler = cli
+        .image_handler()
+        .with_organize(cli.organize.unwrap_or_default())
+        .with_name_template(cli.name_template.clone())
+        .with_overwrite(cli.overwrite())
+        .with_numbering(cli.numbered)
+        .with_messages_to_stderr(stream);
+    if !cli.no_dotenv && Path::new(config::DOTENV_FILE).is_file() {
+        tracing::info!("Loaded environment from {}", config::DOTENV_FILE);
+    }
+    for note in &cli.applied_rules {
+        tracing::info!("{}", note);
+    }
+    tracing::info!("Using provider: {}", cli.provider);
+    tracing::info!("Using model: {}", model);
+
+    // How long this model usually takes, from past runs
+    let typical = if cli.no_history {
+        None
+    } else {
+        History::open()
+            .and_then(|history| history.typical_duration(&model))
+            .unwrap_or_else(|e| {
+                tracing::debug!("No typical duration: {}", e);
+                None
+            })
+    };
+    if let Some(typical) = typical {
+        tracing::info!("Usually ~{}s with {}", typical.as_secs().max(1), model);
+    }
+
+    // Create components; logs would draw over the spinner, so -v goes without
+    let spinner = Spinner::new(
+        &model,
+        typical,
+        cli.output_style() == OutputStyle::Pretty
+            && !cli.verbose
+            && std::io::stderr().is_terminal(),
+    );
+    let http = cli.http_settings().client()?;
+    let client = spinner.follow(ImageClient::new(
+        cli.provider.clone(),
+        api_keys[0].clone(),
+        model.clone(),
+        http.clone(),
+    ));
+    let options = GenerationOptions {
+        size: cli.size.clone(),
+        count: cli.count,
+        quality: cli.quality.clone(),
+        seed: cli.seed,
+        cfg_scale: cli.cfg_scale,
+        negative_prompt: cli.negative_prompt.clone(),
+        compressed: cli.low_bandwidth,
+        jobs: cli.jobs,
+        rpm: cli.rpm,
+        max_failures: cli.max_failures,
+        cooldown_secs: cli.cooldown,
+    };
+
+    // History entry, completed once the outcome is known
+    let mut run = Run {
+        id: 0,
+        timestamp: Local::now().to_rfc3339(),
+        prompt: prompt.to_string(),
+        provider: cli.provider.to_string(),
+        model: client.model().to_string(),
+        size: options.size.clone(),
+        quality: options.quality.clone(),
+        count: options.count,
+        seed: options.seed,
+        cfg_scale: options.cfg_scale,
+        negative_prompt: options.negative_prompt.clone(),
+        usage: Usage::default(),
+        duration_ms: None,
+        status: RunStatus::Succeeded,
+        error: None,
+        images: Vec::new(),
+        tags: Vec::new(),
+    };
+
+    // Refuse to replace an existing output file before paying for the request
+    let planned = NameFields {
+        prompt: name_prompt,
+        provider: &run.provider,
+        model: &run.model,
+        seed: options.seed,
+    };
+    for index in (1..=options.count as usize).map(|i| (options.count > 1).then_some(i)) {
+        for destination in destinations.iter().filter(|_| save) {
+            handler.check_overwrite(&handler.resolve_output_path(*destination, index, &planned))?;
+        }
+    }
+
+    // Audit the prompt before anything is sent
+    if let Some(log_path) = &cli.prompt_log {
+        let provider = cli.provider.to_string();
+        let entry = PromptLogEntry {
+            count: options.count,
+            size: options.size.as_deref(),
+            ..PromptLogEntry::new(&provider, client.model(), prompt)
+        };
+        prompt_log::append(log_path, &entry)?;
+    }
+
+    // Print generation message; the spinner shows it instead
+    if !spinner.is_enabled() {
+        handler.print_generating(prompt);
+    }
+
+    // Generate images, serving identical requests from the cache when enabled.
+    // Low-bandwidth mode turns the disk cache on and keeps entries longer.
+    let (cache_kind, default_ttl) = if cli.low_bandwidth {
+        (
+            cli.cache_backend.or(Some(CacheKind::Disk)),
+            cache::LOW_BANDWIDTH_TTL,
+        )
+    } else {
+        (cli.cache_backend, cache::DEFAULT_TTL)
+    };
+    let ttl = match &cli.cache_ttl {
+        Some(ttl) => cache::parse_duration(ttl)?,
+        None => default_ttl,
+    };
+    // Recording and replaying are about provider traffic, so never short-circuit it
+    let use_cache = !cli.no_cache && cli.record.is_none() && cli.replay.is_none();
+    let cache = match cache_kind.filter(|_| use_cache) {
+        Some(kind) => Some(cache::open(kind, cli.redis_url.as_deref(), ttl)?),
+        None => None,
+    };
+    let cache_key = cache::request_key(&cli.provider.to_string(), client.model(), prompt, &options);
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| match cache.get(&cache_key) {
+            Ok(value) => value.and_then(|v| cache::decode_result(&v)),
             Err(e) => {
-                handler.print_warning(&format!("Could not display preview: {}", e));
+                handler.print_warning(&format!("Cache lookup failed: {}", e));
+                None
+            }
+        });
+
+    let from_cache = cached.is_some();
+    let result = match cached {
+        Some(result) => {
+            tracing::info!("Cache hit: {}", cache_key);
+            result
+        }
+        None => {
+            check_budget(cli, &handler)?;
+
+            // Retry transient failures, and move on to the next key when one
+            // is rate limited or out of quota; only the last key waits out a
+            // rate limit
+            let retry = cli.retry_policy();
+            let retry_key = |i: usize| RetryPolicy {
+                rate_limits: i + 1 == api_keys.len(),
+                ..retry
+            };
+            let on_retry = |e: &ImagoError, wait: Duration, attempt: u32| {
+                spinner.suspend(|| {
+                    handler.print_warning(&format!(
+                        "{}; retrying in {:.1}s ({} of {})",
+                        e,
+                        wait.as_secs_f64(),
+                        attempt,
+                        retry.retries
+                    ))
+                });
+            };
+            spinner.start();
+            let requested = Instant::now();
+            let request = tracing::info_span!("request", provider = %cli.provider, model = %model);
+            let mut result = retry_key(0)
+                .run(|| client.generate(prompt, &options), on_retry)
+                .instrument(request.clone())
+                .await;
+            for (i, key) in api_keys.iter().enumerate().skip(1) {
+                if !result.as_ref().is_err_and(ImagoError::is_rate_limited) {
+                    break;
+                }
+                spinner.suspend(|| {
+                    handler.print_warning(&format!(
+                        "{} is rate limited or out of quota, trying {}",
+                        key_label(i - 1, &api_keys[i - 1]),
+                        key_label(i, key)
+                    ))
+                });
+                let client = spinner.follow(ImageClient::new(
+                    cli.provider.clone(),
+                    key.clone(),
+                    model.clone(),
+                    http.clone(),
+                ));
+                result = retry_key(i)
+                    .run(|| client.generate(prompt, &options), on_retry)
+                    .instrument(request.clone())
+                    .await;
+            }
+            spinner.finish();
+            run.duration_ms = Some(requested.elapsed().as_millis() as u64);
+            if api_keys.len() > 1 && result.as_ref().is_err_and(ImagoError::is_rate_limited) {
+                handler.print_warning(&format!(
+                    "All {} keys are rate limited or out of quota",
+                    api_keys.len()
+                ));
+            }
+            let result = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    run.status = RunStatus::Failed;
+                    run.error = Some(e.to_string());
+                    record_history(cli, &handler, &run);
+                    log_run(cli, &handler, &run, started.elapsed());
+                    return Err(e);
+                }
+            };
+            if let Some(failure) = result.failures.last() {
+                handler.print_warning(&format!(
+                    "{} of {} images failed, keeping the rest: {}",
+                    result.failures.len(),
+                    result.failures.len() + result.images.len(),
+                    failure
+                ));
+                run.error = Some(failure.clone());
+            }
+            if let Err(e) = usage::record(&UsageRecord::from_result(&result)) {
+                handler.print_warning(&format!("Could not record usage: {}", e));
+            }
+            // A partial batch would otherwise stand in for the full one later
+            if let Some(cache) = cache.as_ref().filter(|_| result.failures.is_empty()) {
+                if let Err(e) =
+                    cache::encode_result(&result).and_then(|v| cache.put(&cache_key, &v))
+                {
+                    handler.print_warning(&format!("Could not cache response: {}", e));
+                }
+            }
+            result
+        }
+    };
+    if result.model != client.model() {
+        tracing::info!("Served by model: {}", result.model);
+    }
+    if let Some(usage) = &result.usage {
+        let count = |n: Option<u64>| n.map_or_else(|| "-".to_string(), |n| n.to_string());
+        tracing::info!(
+            "Tokens: prompt {}, candidates {}, total {}",
+            count(usage.input_tokens),
+            count(usage.output_tokens),
+            count(usage.total_tokens)
+        );
+    }
+    if let Some(reason) = &result.finish_reason {
+        tracing::info!("Finish reason: {}", reason);
+    }
+    if let Some(text) = &result.text {
+        tracing::info!("Model response: {}", text);
+    }
+
+    // Estimated cost, spread evenly over the images; cache hits are free
+    let show_cost = cli.show_cost && !cli.launcher_mode && !from_cache;
+    let image_cost = pricing::lookup(&cli.pricing, &result.model)
+        .and_then(|price| {
+            pricing::estimate(&price, result.images.len() as u32, result.usage.as_ref())
+        })
+        .map(|cost| cost / result.images.len().max(1) as f64);
+    if show_cost && image_cost.is_none() {
+        handler.print_warning(&format!(
+            "No price known for {0}; add [pricing.\"{0}\"] to the config",
+            result.model
+        ));
+    }
+
+    let multiple = result.images.len() > 1;
+    let print_cost = |i: usize| {
+        if let Some(cost) = image_cost.filter(|_| show_cost) {
+            if multiple {
+                handler.say(format_args!(
+                    "Estimated cost: ~{} (running total ~{})",
+                    pricing::format_usd(cost),
+                    pricing::format_usd(cost * (i + 1) as f64)
+                ));
+            } else {
+                handler.say(format_args!(
+                    "Estimated cost: ~{}",
+                    pricing::format_usd(cost)
+                ));
+            }
+        }
+    };
+    let mut saved = Vec::with_capacity(result.images.len());
+    let mut reported = Vec::new();
+    let created = Local::now().to_rfc3339();
+
+    for (i, image) in result.images.iter().enumerate() {
+        tracing::info!("Image generated: {} bytes", image.data.len());
+        if let Some(text) = &image.text {
+            tracing::info!("Model response: {}", text);
+        }
+
+        // Resolve output path
+        let index = multiple.then_some(i + 1);
+        let name_fields = NameFields {
+            prompt: name_prompt,
+            provider: &result.provider,
+            model: &result.model,
+            seed: image.seed.or(options.seed),
+        };
+        // Save the image with generation parameters embedded
+        let image_metadata = ImageMetadata {
+            prompt: prompt.to_string(),
+            provider: result.provider.clone(),
+            model: result.model.clone(),
+            size: options.size.clone(),
+            quality: options.quality.clone(),
+            seed: image.seed,
+            negative_prompt: options.negative_prompt.clone(),
+            cfg_scale: options.cfg_scale,
+            created: created.clone(),
+        };
+        let mut data = metadata::embed(&image.data, &image_metadata);
+        if let Some(exif) = &cli.fake_exif {
+            data = metadata::embed_exif(&data, &exif.to_tiff());
+        }
+        if stream {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&data)?;
+            stdout.flush()?;
+        }
+        if !save {
+            print_cost(i);
+            continue;
+        }
+        let output_path = handler.resolve_output_path(output, index, &name_fields);
+        handler.check_overwrite(&output_path)?;
+        let mut copies = Vec::new();
+        for destination in &destinations[1..] {
+            let copy_path = handler.resolve_output_path(*destination, index, &name_fields);
+            handler.check_overwrite(&copy_path)?;
+            copies.push((*destination, copy_path));
+        }
+        let saving = tracing::info_span!("save", path = %output_path.display());
+        handler
+            .save_image(&data, &output_path)
+            .instrument(saving.clone())
+            .await?;
+        for (_, copy_path) in &copies {
+            handler
+                .save_image(&data, copy_path)
+                .instrument(saving.clone())
+                .await?;
+        }
+        // Only with -o, so plain runs don't leave links in the working directory
+        if !cli.no_latest {
+            let saved_to = std::iter::once((output, &output_path)).chain(
+                copies
+                    .iter()
+                    .map(|(destination, path)| (*destination, path)),
+            );
+            for (destination, path) in saved_to {
+                let Some(destination) = destination else {
+                    continue;
+                };
+                if let Err(e) = handler.update_latest(destination, path) {
+                    handler.print_warning(&format!("Could not update latest image link: {}", e));
+                }
             }
         }
+        if let Some(id) = &cli.job_id {
+            jobs::record_output(id, &output_path)?;
+        }
+        if let Some(command) = &cli.pipe_to {
+            pipe::run(command, &output_path, &data, stream).await?;
+        }
+
+        if cli.manifest {
+            let record = Manifest {
+                manifest_version: manifest::MANIFEST_VERSION,
+                imago_version: env!("CARGO_PKG_VERSION").to_string(),
+                created: created.clone(),
+                prompt: prompt.to_string(),
+                provider: result.provider.clone(),
+                model: result.model.clone(),
+                seed: image.seed.or(options.seed),
+                options: ManifestOptions {
+                    size: options.size.clone(),
+                    quality: options.quality.clone(),
+                    cfg_scale: options.cfg_scale,
+                    negative_prompt: options.negative_prompt.clone(),
+                    compressed: options.compressed,
+                },
+                output: ManifestOutput::for_image(&output_path, &data),
+            };
+            let manifest_path = manifest::write(&output_path, &record)?;
+            if cli.sign_manifest {
+                manifest::sign(&manifest_path, cli.signing_key.as_deref())?;
+            }
+            tracing::info!("Manifest: {}", manifest_path.display());
+        }
+
+        // Print success message
+        handler.print_result(&output_path, &image.data);
+        for (_, copy_path) in &copies {
+            handler.print_result(copy_path, &image.data);
+        }
+        print_cost(i);
+
+        if let Some(site) = cli.emit {
+            handler.say(format_args!(
+                "{}",
+                site.snippet(&cli.site_root, &output_path, prompt)
+            ));
+        }
+
+        // Describe the image for screen readers, or display it in the terminal
+        if cli.a11y && !cli.launcher_mode {
+            let model_text = image.text.as_deref().or(result.text.as_deref());
+            handler.print_description(&alt_text::describe(&image.data, prompt, model_text));
+        } else if !cli.no_preview && !cli.launcher_mode && !stream {
+            handler.say(format_args!(""));
+            let preview = tracing::info_span!("preview");
+            match preview.in_scope(|| {
+                let caption = ImageHandler::caption(prompt, &output_path);
+                handler.display_with_caption(&image.data, Some(&caption))
+            }) {
+                Ok(_) => {}
+                Err(e) => {
+                    handler.print_warning(&format!("Could not display preview: {}", e));
+                }
+            }
+        }
+
+        run.images.push(HistoryImage {
+            path: fs::canonicalize(&output_path).unwrap_or_else(|_| output_path.clone()),
+            seed: image.seed,
+        });
+        if cli.output_format == OutputFormat::Json {
+            let copies = copies.into_iter().map(|(_, path)| path).collect();
+            reported.push(ReportImage::new(
+                output_path.clone(),
+                copies,
+                &data,
+                image.seed.or(options.seed),
+            ));
+        }
+        saved.push(output_path);
+    }
+
+    run.model = result.model.clone();
+    run.usage = result.usage.clone().unwrap_or_default();
+    if from_cache {
+        run.status = RunStatus::Cached;
+    }
+    record_history(cli, &handler, &run);
+    log_run(cli, &handler, &run, started.elapsed());
+    if let Some(timing) = logging::take_phase_timing() {
+        tracing::info!("Timing: {}", timing);
+    }
+
+    if cli.output_format == OutputFormat::Json {
+        report::print(&Report {
+            prompt: prompt.to_string(),
+            provider: result.provider.clone(),
+            model: result.model.clone(),
+            images: reported,
+            usage: run.usage.clone(),
+            duration_ms: started.elapsed().as_millis(),
+            cost_usd: image_cost.map(|cost| {
+                if from_cache {
+                    0.0
+                } else {
+                    cost * result.images.len() as f64
+                }
+            }),
+            cached: from_cache,
+        })?;
+    }
+
+    Ok(saved)
+}
+
+/// One history entry with its image paths, as listed by `imago history`
+fn print_run_summary(run: &Run) {
+    let when = run
+        .timestamp
+        .get(..16)
+        .unwrap_or(&run.timestamp)
+        .replace('T', " ");
+    let status = match run.status {
+        RunStatus::Failed => run.status.to_string().red(),
+        _ => run.status.to_string().normal(),
+    };
+    let tags = if run.tags.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]  ", run.tags.join(", "))
+    };
+    println!(
+        "{}  {}  {}  {}  {}{}",
+        format!("#{}", run.id).bold(),
+        when,
+        run.model.cyan(),
+        status,
+        tags.yellow(),
+        run.prompt
+    );
+    for image in &run.images {
+        println!("    {}", image.path.display());
+    }
+}
+
+/// `imago history show`: every recorded detail, then a preview of each image
+fn show_run(cli: &Cli, run: &Run) -> Result<()> {
+    println!("{}", format!("Run #{}", run.id).bold());
+    println!("Time:      {}", run.timestamp);
+    println!("Prompt:    {}", run.prompt);
+    println!("Provider:  {}", run.provider);
+    println!("Model:     {}", run.model);
+    let settings = [
+        ("Size:      ", run.size.clone()),
+        ("Quality:   ", run.quality.clone()),
+        ("Seed:      ", run.seed.map(|seed| seed.to_string())),
+        ("CFG scale: ", run.cfg_scale.map(|scale| scale.to_string())),
+        ("Negative:  ", run.negative_prompt.clone()),
+        ("Tokens:    ", run.usage.total_tokens.map(|n| n.to_string())),
+        (
+            "Duration:  ",
+            run.duration_ms
+                .map(|ms| format!("{:.1}s", ms as f64 / 1000.0)),
+        ),
+        ("Error:     ", run.error.clone()),
+    ];
+    println!("Count:     {}", run.count);
+    for (label, value) in settings {
+        if let Some(value) = value {
+            println!("{}{}", label, value);
+        }
+    }
+    println!("Status:    {}", run.status);
+    if !run.tags.is_empty() {
+        println!("Tags:      {}", run.tags.join(", "));
     }
 
+    let handler = cli.image_handler();
+    for image in &run.images {
+        println!();
+        match fs::read(&image.path) {
+            Ok(data) => {
+                handler.print_success(&image.path);
+                if !cli.no_preview && !cli.launcher_mode {
+                    let caption = ImageHandler::caption(&run.prompt, &image.path);
+                    if let Err(e) = handler.display_with_caption(&data, Some(&caption)) {
+                        handler.print_warning(&format!("Could not display preview: {}", e));
+                    }
+                }
+            }
+            Err(e) => handler.print_warning(&format!("{}: {}", image.path.display(), e)),
+        }
+    }
     Ok(())
 }
+
+/// Add a run to the history database unless --no-history; failures only warn
+fn record_history(cli: &Cli, handler: &ImageHandler, run: &Run) {
+    if cli.no_history {
+        return;
+    }
+    if let Err(e) = History::open().and_then(|mut history| history.record(run)) {
+        handler.print_warning(&format!("Could not record history: {}", e));
+    }
+}
+
+/// Append the outcome to --log-file; a log that can't be written only warns
+fn log_run(cli: &Cli, handler: &ImageHandler, run: &Run, duration: Duration) {
+    let Some(path) = &cli.log_file else {
+        return;
+    };
+    if let Err(e) = run_log::append(path, run, duration) {
+        handler.print_warning(&format!("Could not write log file: {}", e));
+    }
+}
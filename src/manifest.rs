@@ -0,0 +1,159 @@
+//! Reproducibility manifests written with `--manifest`
+//!
+//! A manifest records everything needed to regenerate an image (prompt as
+//! sent, provider, model, seed and options) plus hashes of the saved file and
+//! of its decoded pixels. The file hash proves the asset is unchanged; the
+//! pixel hash ignores embedded metadata, so `imago reproduce` can compare a
+//! fresh generation. Manifests can be signed with a detached GPG signature.
+
+use crate::error::{ImagoError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_SUFFIX: &str = "manifest.json";
+
+/// How an image was produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub manifest_version: u32,
+    pub imago_version: String,
+    pub created: String,
+    /// Prompt exactly as sent, style preset included
+    pub prompt: String,
+    pub provider: String,
+    /// Model that served the request (for Azure, the deployment)
+    pub model: String,
+    pub seed: Option<u64>,
+    pub options: ManifestOptions,
+    pub output: ManifestOutput,
+}
+
+/// Generation options that affect the output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestOptions {
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub negative_prompt: Option<String>,
+    pub compressed: bool,
+}
+
+/// The saved image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestOutput {
+    /// File name, relative to the manifest
+    pub file: String,
+    /// SHA-256 of the file as saved
+    pub sha256: String,
+    /// SHA-256 of the decoded RGBA pixels and dimensions
+    pub pixels_sha256: Option<String>,
+}
+
+impl ManifestOutput {
+    /// Hash a saved image
+    pub fn for_image(path: &Path, data: &[u8]) -> Self {
+        Self {
+            file: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            sha256: sha256_hex(data),
+            pixels_sha256: pixels_sha256(data),
+        }
+    }
+}
+
+/// Manifest location for an image: `photo.png` → `photo.manifest.json`
+pub fn path_for(image: &Path) -> PathBuf {
+    image.with_extension(MANIFEST_SUFFIX)
+}
+
+/// Detached signature location for a manifest
+pub fn signature_path(manifest: &Path) -> PathBuf {
+    let mut name = manifest.as_os_str().to_owned();
+    name.push(".asc");
+    PathBuf::from(name)
+}
+
+/// Write the manifest next to its image, returning its path
+pub fn write(image: &Path, manifest: &Manifest) -> Result<PathBuf> {
+    let path = path_for(image);
+    let mut json = serde_json::to_vec_pretty(manifest)?;
+    json.push(b'\n');
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+pub fn read(path: &Path) -> Result<Manifest> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        ImagoError::ManifestError(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    let manifest: Manifest = serde_json::from_str(&text)
+        .map_err(|e| ImagoError::ManifestError(format!("Invalid {}: {}", path.display(), e)))?;
+    if manifest.manifest_version > MANIFEST_VERSION {
+        return Err(ImagoError::ManifestError(format!(
+            "{} uses manifest version {}; this imago reads up to {}",
+            path.display(),
+            manifest.manifest_version,
+            MANIFEST_VERSION
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Create an ASCII-armored detached signature with `gpg`
+pub fn sign(manifest: &Path, key: Option<&str>) -> Result<PathBuf> {
+    let signature = signature_path(manifest);
+    let mut command = Command::new("gpg");
+    command.args(["--batch", "--yes", "--armor", "--detach-sign"]);
+    if let Some(key) = key {
+        command.arg("--local-user").arg(key);
+    }
+    command.arg("--output").arg(&signature).arg(manifest);
+
+    let output = command
+        .output()
+        .map_err(|e| ImagoError::ManifestError(format!("Failed to run gpg: {}", e)))?;
+    if !output.status.success() {
+        return Err(ImagoError::ManifestError(format!(
+            "gpg could not sign {}: {}",
+            manifest.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(signature)
+}
+
+/// Check the manifest's detached signature; `None` when it is unsigned
+pub fn verify(manifest: &Path) -> Result<Option<bool>> {
+    let signature = signature_path(manifest);
+    if !signature.is_file() {
+        return Ok(None);
+    }
+    let status = Command::new("gpg")
+        .args(["--batch", "--verify"])
+        .arg(&signature)
+        .arg(manifest)
+        .output()
+        .map_err(|e| ImagoError::ManifestError(format!("Failed to run gpg: {}", e)))?
+        .status;
+    Ok(Some(status.success()))
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Hash of the decoded image, unaffected by metadata chunks
+pub fn pixels_sha256(data: &[u8]) -> Option<String> {
+    let img = image::load_from_memory(data).ok()?.to_rgba8();
+    let mut hasher = Sha256::new();
+    hasher.update(img.width().to_be_bytes());
+    hasher.update(img.height().to_be_bytes());
+    hasher.update(img.as_raw());
+    Some(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const KEY_PREFIX: &str = "imago.";
+
+/// Generation parameters embedded into saved images
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetadata {
+    pub prompt: String,
+    pub provider: String,
+    pub model: String,
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    pub seed: Option<u64>,
+    pub negative_prompt: Option<String>,
+    pub cfg_scale: Option<f32>,
+    pub created: String,
+}
+
+impl ImageMetadata {
+    /// Flatten into `(key, value)` pairs as stored in the image
+    fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![
+            ("prompt", self.prompt.clone()),
+            ("provider", self.provider.clone()),
+            ("model", self.model.clone()),
+            ("created", self.created.clone()),
+        ];
+        if let Some(size) = &self.size {
+            pairs.push(("size", size.clone()));
+        }
+        if let Some(quality) = &self.quality {
+            pairs.push(("quality", quality.clone()));
+        }
+        if let Some(seed) = self.seed {
+            pairs.push(("seed", seed.to_string()));
+        }
+        if let Some(negative_prompt) = &self.negative_prompt {
+            pairs.push(("negative_prompt", negative_prompt.clone()));
+        }
+        if let Some(cfg_scale) = self.cfg_scale {
+            pairs.push(("cfg_scale", cfg_scale.to_string()));
+        }
+        pairs
+    }
+
+    /// Rebuild from stored pairs, returning `None` when no imago keys are present
+    fn from_pairs(mut pairs: BTreeMap<String, String>) -> Option<Self> {
+        let prompt = pairs.remove("prompt")?;
+        Some(Self {
+            prompt,
+            provider: pairs.remove("provider").unwrap_or_default(),
+            model: pairs.remove("model").unwrap_or_default(),
+            size: pairs.remove("size"),
+            quality: pairs.remove("quality"),
+            seed: pairs.remove("seed").and_then(|s| s.parse().ok()),
+            negative_prompt: pairs.remove("negative_prompt"),
+            cfg_scale: pairs.remove("cfg_scale").and_then(|s| s.parse().ok()),
+            created: pairs.remove("created").unwrap_or_default(),
+        })
+    }
+}
+
+/// Embed metadata into PNG bytes as UTF-8 `iTXt` chunks
+///
+/// Non-PNG data is returned unchanged.
+pub fn embed(image_data: &[u8], metadata: &ImageMetadata) -> Vec<u8> {
+    // IHDR is always the first chunk: signature + length + type + 13 bytes + crc
+    let ihdr_end = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+    if !image_data.starts_with(&PNG_SIGNATURE) || image_data.len() < ihdr_end {
+        return image_data.to_vec();
+    }
+
+    let mut out = Vec::with_capacity(image_data.len() + 512);
+    out.extend_from_slice(&image_data[..ihdr_end]);
+
+    let software = format!("imago {}", env!("CARGO_PKG_VERSION"));
+    write_itxt_chunk(&mut out, "Software", &software);
+    for (key, value) in metadata.to_pairs() {
+        write_itxt_chunk(&mut out, &format!("{}{}", KEY_PREFIX, key), &value);
+    }
+
+    out.extend_from_slice(&image_data[ihdr_end..]);
+    out
+}
+
+/// Embed a TIFF-encoded EXIF block (see [`crate::exif::FakeExif::to_tiff`])
+///
+/// PNG gets an `eXIf` chunk after IHDR, JPEG an APP1 segment after SOI; other
+/// formats are returned unchanged.
+pub fn embed_exif(image_data: &[u8], tiff: &[u8]) -> Vec<u8> {
+    let ihdr_end = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+    if image_data.starts_with(&PNG_SIGNATURE) && image_data.len() >= ihdr_end {
+        let mut out = Vec::with_capacity(image_data.len() + tiff.len() + 12);
+        out.extend_from_slice(&image_data[..ihdr_end]);
+        write_chunk(&mut out, b"eXIf", tiff);
+        out.extend_from_slice(&image_data[ihdr_end..]);
+        return out;
+    }
+
+    const EXIF_HEADER: &[u8] = b"Exif\0\0";
+    let segment_len = 2 + EXIF_HEADER.len() + tiff.len();
+    if image_data.starts_with(&[0xFF, 0xD8]) && segment_len <= u16::MAX as usize {
+        let mut out = Vec::with_capacity(image_data.len() + segment_len + 2);
+        out.extend_from_slice(&image_data[..2]);
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&(segment_len as u16).to_be_bytes());
+        out.extend_from_slice(EXIF_HEADER);
+        out.extend_from_slice(tiff);
+        out.extend_from_slice(&image_data[2..]);
+        return out;
+    }
+
+    image_data.to_vec()
+}
+
+/// Read imago metadata back from PNG bytes
+pub fn read(image_data: &[u8]) -> Option<ImageMetadata> {
+    if !image_data.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut pairs = BTreeMap::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= image_data.len() {
+        let len = u32::from_be_bytes(image_data[pos..pos + 4].try_into().ok()?) as usize;
+        let kind = &image_data[pos + 4..pos + 8];
+        let data_start = pos + 8;
+        let data_end = data_start.checked_add(len)?;
+        if data_end + 4 > image_data.len() {
+            break;
+        }
+        let data = &image_data[data_start..data_end];
+
+        let entry = match kind {
+            b"iTXt" => parse_itxt(data),
+            b"tEXt" => parse_text(data),
+            b"IEND" => break,
+            _ => None,
+        };
+        if let Some((key, value)) = entry {
+            if let Some(key) = key.strip_prefix(KEY_PREFIX) {
+                pairs.insert(key.to_string(), value);
+            }
+        }
+
+        pos = data_end + 4;
+    }
+
+    ImageMetadata::from_pairs(pairs)
+}
+
+fn write_itxt_chunk(out: &mut Vec<u8>, keyword: &str, text: &str) {
+    // keyword, null, compression flag, compression method, empty language tag,
+    // empty translated keyword, UTF-8 text
+    let mut data = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    write_chunk(out, b"iTXt", &data);
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn parse_itxt(data: &[u8]) -> Option<(String, String)> {
+    let keyword_end = data.iter().position(|&b| b == 0)?;
+    let keyword = String::from_utf8_lossy(&data[..keyword_end]).into_owned();
+    let compressed = *data.get(keyword_end + 1)? != 0;
+    if compressed {
+        return None;
+    }
+
+    // Skip compression method, then language tag and translated keyword
+    let mut rest = data.get(keyword_end + 3..)?;
+    for _ in 0..2 {
+        let end = rest.iter().position(|&b| b == 0)?;
+        rest = &rest[end + 1..];
+    }
+
+    Some((keyword, String::from_utf8_lossy(rest).into_owned()))
+}
+
+fn parse_text(data: &[u8]) -> Option<(String, String)> {
+    let keyword_end = data.iter().position(|&b| b == 0)?;
+    // tEXt is Latin-1, which maps byte-for-byte onto the first 256 code points
+    let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+    Some((
+        latin1(&data[..keyword_end]),
+        latin1(&data[keyword_end + 1..]),
+    ))
+}
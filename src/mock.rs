@@ -0,0 +1,269 @@
+//! `--provider mock`: placeholder images rendered locally
+//!
+//! Each image is a diagonal gradient with the prompt written across it in a
+//! built-in 5×7 pixel font. Nothing is sent over the network and no key is
+//! needed, so the preview, save and reporting pipeline can be demoed and
+//! integration-tested offline. Colors are derived from the prompt, seed and
+//! image number, so the same request always renders the same pixels.
+
+use crate::error::{ImagoError, Result};
+use crate::provider::{parse_dimensions, GeneratedImage, GenerationOptions, GenerationResult};
+use image::{ImageFormat, Rgb, RgbImage};
+use std::io::Cursor;
+
+const DEFAULT_SIZE: u32 = 1024;
+/// Largest side rendered, whatever `--size` asks for
+const MAX_SIZE: u32 = 4096;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Offline client that draws placeholders instead of calling an API
+pub struct MockClient {
+    model: String,
+}
+
+impl MockClient {
+    pub fn new(model: String) -> Self {
+        Self { model }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Render `options.count` placeholder images for `prompt`
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let (width, height) = dimensions(options.size.as_deref());
+        let mut result = GenerationResult::new("mock", &self.model);
+        for i in 1..=options.count {
+            let text = if options.count > 1 {
+                format!("{} ({}/{})", prompt, i, options.count)
+            } else {
+                prompt.to_string()
+            };
+            let key = format!("{}\0{}\0{}", prompt, options.seed.unwrap_or(0), i);
+            let seed = fnv1a(key.as_bytes());
+            result.images.push(GeneratedImage {
+                data: render(width, height, &text, seed)?,
+                text: None,
+                seed: options.seed,
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// Pixel size for `--size`: `WIDTHxHEIGHT`, an aspect ratio such as `16:9`
+/// on a 1024-pixel long side, or a 1024 square
+fn dimensions(size: Option<&str>) -> (u32, u32) {
+    let ratio = |size: &str| {
+        let (w, h) = size.split_once(':')?;
+        let (w, h) = (w.trim().parse::<f64>().ok()?, h.trim().parse::<f64>().ok()?);
+        (w > 0.0 && h > 0.0).then(|| {
+            let scale = DEFAULT_SIZE as f64 / w.max(h);
+            ((w * scale).round() as u32, (h * scale).round() as u32)
+        })
+    };
+    let (width, height) = size
+        .and_then(|size| parse_dimensions(size).or_else(|| ratio(size)))
+        .unwrap_or((DEFAULT_SIZE, DEFAULT_SIZE));
+    (width.clamp(1, MAX_SIZE), height.clamp(1, MAX_SIZE))
+}
+
+/// Draw the gradient and the wrapped, upper-cased text, encoded as PNG
+fn render(width: u32, height: u32, text: &str, seed: u64) -> Result<Vec<u8>> {
+    // Mid-range channels keep white text readable on either end
+    let color = |bits: u64| {
+        Rgb([
+            40 + (bits & 0xff) as u8 % 140,
+            40 + ((bits >> 8) & 0xff) as u8 % 140,
+            40 + ((bits >> 16) & 0xff) as u8 % 140,
+        ])
+    };
+    let (from, to) = (color(seed), color(seed >> 24));
+    let span = (width + height).max(2) - 2;
+    let mut image = RgbImage::from_fn(width, height, |x, y| {
+        let t = (x + y) as f32 / span.max(1) as f32;
+        Rgb(std::array::from_fn(|c| {
+            (from.0[c] as f32 + (to.0[c] as f32 - from.0[c] as f32) * t) as u8
+        }))
+    });
+
+    let scale = (width.min(height) / 160).max(1);
+    let margin = scale * 8;
+    let columns = ((width.saturating_sub(2 * margin)) / ((GLYPH_WIDTH + 1) * scale)).max(1);
+    let rows = ((height.saturating_sub(2 * margin)) / ((GLYPH_HEIGHT + 2) * scale)).max(1);
+    let lines = wrap(&text.to_uppercase(), columns as usize, rows as usize);
+
+    let block_height = lines.len() as u32 * (GLYPH_HEIGHT + 2) * scale;
+    let top = height.saturating_sub(block_height) / 2;
+    for (row, line) in lines.iter().enumerate() {
+        let line_width = line.chars().count() as u32 * (GLYPH_WIDTH + 1) * scale;
+        let left = width.saturating_sub(line_width) / 2;
+        let y = top + row as u32 * (GLYPH_HEIGHT + 2) * scale;
+        for (column, c) in line.chars().enumerate() {
+            let x = left + column as u32 * (GLYPH_WIDTH + 1) * scale;
+            // Shadow first, so the text stays legible on light gradients
+            draw_glyph(&mut image, x + scale, y + scale, scale, c, Rgb([0, 0, 0]));
+            draw_glyph(&mut image, x, y, scale, c, Rgb([255, 255, 255]));
+        }
+    }
+
+    let mut buf = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .map_err(|e| ImagoError::ImageError(format!("Failed to encode placeholder: {}", e)))?;
+    Ok(buf)
+}
+
+/// Break `text` into lines of at most `columns` characters, ending with `...`
+/// when it doesn't fit in `rows` lines
+fn wrap(text: &str, columns: usize, rows: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while !word.is_empty() {
+            let used = line.chars().count();
+            let space = usize::from(used > 0);
+            if used + space + word.len() <= columns {
+                if space == 1 {
+                    line.push(' ');
+                }
+                line.extend(word.drain(..));
+            } else if used > 0 {
+                lines.push(std::mem::take(&mut line));
+            } else {
+                // A word longer than a whole line is split
+                line.extend(word.drain(..columns));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    if lines.len() > rows {
+        lines.truncate(rows);
+        let last = &mut lines[rows - 1];
+        let keep = columns.saturating_sub(3).min(last.chars().count());
+        *last = last.chars().take(keep).collect::<String>() + "...";
+    }
+    lines
+}
+
+fn draw_glyph(image: &mut RgbImage, x: u32, y: u32, scale: u32, c: char, color: Rgb<u8>) {
+    for (row, bits) in glyph(c).iter().enumerate() {
+        for column in 0..GLYPH_WIDTH {
+            if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let (px, py) = (x + column * scale + dx, y + row as u32 * scale + dy);
+                    if px < image.width() && py < image.height() {
+                        image.put_pixel(px, py, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 5×7 glyph, most significant of the low five bits on the left;
+/// characters without one are drawn as `?`
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        ' ' => [0x00; 7],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '\'' => [0x0c, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '&' => [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d],
+        '#' => [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Stable 64-bit hash, so colors don't change between builds
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn renders_requested_size_deterministically() {
+        let client = MockClient::new("placeholder".to_string());
+        let options = GenerationOptions {
+            size: Some("320x200".to_string()),
+            count: 2,
+            ..GenerationOptions::default()
+        };
+        let first = client.generate_images("a red fox", &options).await.unwrap();
+        let again = client.generate_images("a red fox", &options).await.unwrap();
+
+        assert_eq!(first.images.len(), 2);
+        assert_eq!(first.images[0].data, again.images[0].data);
+        assert_ne!(first.images[0].data, first.images[1].data);
+        let image = image::load_from_memory(&first.images[0].data).unwrap();
+        assert_eq!((image.width(), image.height()), (320, 200));
+    }
+
+    #[test]
+    fn wraps_and_truncates_text() {
+        assert_eq!(wrap("A RED FOX", 5, 3), vec!["A RED", "FOX"]);
+        assert_eq!(wrap("ABCDEFGHIJ", 4, 3), vec!["ABCD", "EFGH", "IJ"]);
+        assert_eq!(wrap("ONE TWO THREE", 5, 2), vec!["ONE", "TW..."]);
+    }
+}
@@ -0,0 +1,210 @@
+//! `imago models`: the models a key can reach, from the provider's listing
+//!
+//! Gemini's ListModels reports token limits and supported methods; OpenAI's
+//! listing only has IDs. Image models are picked out by name, since neither
+//! API reports output modalities. Listings are cached for shell completion
+//! (`imago --complete-model`).
+
+use crate::error::{ImagoError, Result};
+use crate::provider::Provider;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+const GEMINI_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
+const PAGE_SIZE: &str = "1000";
+/// How long a cached listing serves completions before it is refreshed
+const COMPLETION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Completion must never keep the shell waiting long
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One model from a provider listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// ID to pass to `-m`
+    pub name: String,
+    pub input_token_limit: Option<u64>,
+    pub output_token_limit: Option<u64>,
+    /// API methods the model supports, e.g. `generateContent` or `predict`
+    pub methods: Vec<String>,
+}
+
+impl ModelInfo {
+    /// Whether the model produces images
+    pub fn is_image_model(&self) -> bool {
+        self.name.contains("image")
+            || self.name.starts_with("imagen")
+            || self.name.starts_with("dall-e")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String,
+    input_token_limit: Option<u64>,
+    output_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiModelList {
+    data: Vec<OpenAiModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiModel {
+    id: String,
+}
+
+/// Every model the key can see, sorted by name
+pub async fn list(client: &Client, provider: &Provider, api_key: &str) -> Result<Vec<ModelInfo>> {
+    let mut models = match provider {
+        Provider::Gemini => list_gemini(client, api_key).await?,
+        Provider::OpenAi => {
+            let request = client.get(OPENAI_MODELS_URL).bearer_auth(api_key);
+            let list: OpenAiModelList = fetch(request).await?;
+            list.data
+                .into_iter()
+                .map(|model| ModelInfo {
+                    name: model.id,
+                    input_token_limit: None,
+                    output_token_limit: None,
+                    methods: Vec::new(),
+                })
+                .collect()
+        }
+        other => {
+            return Err(ImagoError::InvalidArgument(format!(
+                "{} has no model listing (supported: gemini, openai)",
+                other
+            )))
+        }
+    };
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+async fn list_gemini(client: &Client, api_key: &str) -> Result<Vec<ModelInfo>> {
+    let mut models = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut request = client
+            .get(GEMINI_MODELS_URL)
+            .query(&[("pageSize", PAGE_SIZE)])
+            .header("x-goog-api-key", api_key);
+        if let Some(token) = &page_token {
+            request = request.query(&[("pageToken", token)]);
+        }
+        let page: GeminiModelList = fetch(request).await?;
+        models.extend(page.models.into_iter().map(|model| {
+            ModelInfo {
+                name: model
+                    .name
+                    .strip_prefix("models/")
+                    .unwrap_or(&model.name)
+                    .to_string(),
+                input_token_limit: model.input_token_limit,
+                output_token_limit: model.output_token_limit,
+                methods: model.supported_generation_methods,
+            }
+        }));
+
+        page_token = page.next_page_token.filter(|token| !token.is_empty());
+        if page_token.is_none() {
+            return Ok(models);
+        }
+    }
+}
+
+/// Image model names for `-m` completion, from the cache when it is fresh
+///
+/// Refreshes a stale cache when a key is available and falls back to the old
+/// listing (or just the default model) on any failure, so it never errors.
+pub async fn completions(provider: &Provider, api_key: Option<&str>) -> Vec<String> {
+    let cached = cache_path(provider).and_then(|path| {
+        let age = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        let models: Vec<ModelInfo> = serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+        Some((models, age < COMPLETION_TTL))
+    });
+
+    let models = match (cached, api_key) {
+        (Some((models, true)), _) => models,
+        (cached, Some(api_key)) => {
+            let client = Client::builder().timeout(COMPLETION_TIMEOUT).build();
+            let fresh = match client {
+                Ok(client) => list(&client, provider, api_key).await.ok(),
+                Err(_) => None,
+            };
+            match (fresh, cached) {
+                (Some(models), _) => {
+                    save_cache(provider, &models);
+                    models
+                }
+                (None, cached) => cached.map(|(models, _)| models).unwrap_or_default(),
+            }
+        }
+        (cached, None) => cached.map(|(models, _)| models).unwrap_or_default(),
+    };
+
+    let mut names: Vec<String> = models
+        .into_iter()
+        .filter(ModelInfo::is_image_model)
+        .map(|model| model.name)
+        .collect();
+    let default = provider.default_model().to_string();
+    if !names.contains(&default) {
+        names.insert(0, default);
+    }
+    names
+}
+
+/// Remember a listing for completion; failures only cost a refresh later
+pub fn save_cache(provider: &Provider, models: &[ModelInfo]) {
+    let Some(path) = cache_path(provider) else {
+        return;
+    };
+    if let (Some(dir), Ok(json)) = (path.parent(), serde_json::to_vec(models)) {
+        let _ = fs::create_dir_all(dir).and_then(|_| fs::write(&path, json));
+    }
+}
+
+fn cache_path(provider: &Provider) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join("imago")
+            .join(format!("models-{}.json", provider.name()))
+    })
+}
+
+async fn fetch<T: for<'de> Deserialize<'de>>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await?;
+    let status = response.status();
+    let request_id = crate::provider::request_id(response.headers());
+    let text = crate::http::read_text(response).await?;
+    if !status.is_success() {
+        return Err(ImagoError::ApiError {
+            status: status.as_u16(),
+            message: text.chars().take(300).collect(),
+            retry_after: None,
+            request_id,
+        });
+    }
+    serde_json::from_str(&text).map_err(|e| ImagoError::ResponseFormatError {
+        message: format!("Unexpected model listing: {}", e),
+    })
+}
@@ -0,0 +1,188 @@
+//! Output file names from `--name-template`
+//!
+//! A template is plain text with `{placeholder}` fields, e.g.
+//! `{date}_{model}_{prompt_slug}_{n}`. Values are made safe for file names;
+//! `/` in the template itself creates subdirectories. `.png` is appended
+//! unless the template ends in an image extension.
+
+use crate::error::{ImagoError, Result};
+use chrono::Local;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+/// `a_red_dragon_over_mountains.png`, identifiable at a glance
+pub const DEFAULT_TEMPLATE: &str = "{prompt_slug}";
+/// Longest `{prompt_slug}`, in characters
+const SLUG_MAX: usize = 60;
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Every known placeholder, documented in the README
+const PLACEHOLDERS: [&str; 9] = [
+    "date",
+    "time",
+    "timestamp",
+    "provider",
+    "model",
+    "prompt_slug",
+    "n",
+    "seed",
+    "rand",
+];
+
+/// What a file name can be built from
+pub struct NameFields<'a> {
+    pub prompt: &'a str,
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub seed: Option<u64>,
+}
+
+/// Check a template for unknown placeholders and unbalanced braces
+pub fn validate(template: &str) -> Result<()> {
+    let invalid =
+        |message: String| ImagoError::InvalidArgument(format!("--name-template: {}", message));
+    if template.trim().is_empty() {
+        return Err(invalid("template is empty".to_string()));
+    }
+    for part in split(template) {
+        match part {
+            Part::Field(name) if !PLACEHOLDERS.contains(&name) => {
+                let known: Vec<String> = PLACEHOLDERS
+                    .iter()
+                    .map(|name| format!("{{{}}}", name))
+                    .collect();
+                return Err(invalid(format!(
+                    "unknown placeholder {{{}}} (known: {})",
+                    name,
+                    known.join(", ")
+                )));
+            }
+            Part::Unclosed => return Err(invalid("unclosed '{'".to_string())),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Whether every image of a multi-image run gets a distinct name on its own
+pub fn is_unique_per_image(template: &str) -> bool {
+    split(template).any(|part| matches!(part, Part::Field("n" | "rand")))
+}
+
+/// File name (possibly with subdirectories) for image `n` of a run
+pub fn render(template: &str, fields: &NameFields, n: usize) -> String {
+    let now = Local::now();
+    let mut name = String::new();
+    for part in split(template) {
+        match part {
+            Part::Text(text) => name.push_str(text),
+            Part::Field(field) => name.push_str(&match field {
+                "date" => now.format("%Y%m%d").to_string(),
+                "time" => now.format("%H%M%S").to_string(),
+                "timestamp" => now.format("%Y%m%d%H%M").to_string(),
+                "provider" => sanitize(fields.provider),
+                "model" => sanitize(fields.model),
+                "prompt_slug" => slug(fields.prompt),
+                "n" => n.to_string(),
+                "seed" => fields.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+                "rand" => random_suffix(),
+                _ => String::new(),
+            }),
+            Part::Unclosed => {}
+        }
+    }
+
+    let has_extension = name
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    if !has_extension {
+        name.push_str(".png");
+    }
+    name
+}
+
+/// `A red dragon, over mountains!` becomes `a_red_dragon_over_mountains`
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        let extra = usize::from(!slug.is_empty()) + word.chars().count();
+        if slug.chars().count() + extra > SLUG_MAX {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('_');
+        }
+        slug.push_str(&word);
+    }
+    if slug.is_empty() {
+        "image".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Eight lowercase letters and digits
+pub fn random_suffix() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        .take(8)
+        .map(char::from)
+        .collect()
+}
+
+/// Replace characters that are unsafe in file names, such as `/` in model IDs
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Field(&'a str),
+    Unclosed,
+}
+
+/// Template text and `{field}`s in order
+fn split(template: &str) -> impl Iterator<Item = Part<'_>> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        match rest.find('{') {
+            Some(0) => match rest.find('}') {
+                Some(end) => {
+                    let field = &rest[1..end];
+                    rest = &rest[end + 1..];
+                    Some(Part::Field(field))
+                }
+                None => {
+                    rest = "";
+                    Some(Part::Unclosed)
+                }
+            },
+            Some(start) => {
+                let text = &rest[..start];
+                rest = &rest[start..];
+                Some(Part::Text(text))
+            }
+            None => {
+                let text = rest;
+                rest = "";
+                Some(Part::Text(text))
+            }
+        }
+    })
+}
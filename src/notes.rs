@@ -0,0 +1,116 @@
+use crate::error::{ImagoError, Result};
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Subset of Obsidian's `.obsidian/app.json` that imago cares about
+#[derive(Debug, Default, Deserialize)]
+struct AppConfig {
+    #[serde(rename = "attachmentFolderPath")]
+    attachment_folder_path: Option<String>,
+}
+
+/// A note inside an Obsidian (or compatible) vault
+pub struct VaultNote {
+    vault: PathBuf,
+    note: PathBuf,
+}
+
+impl VaultNote {
+    /// Resolve a vault-relative note path, adding `.md` when missing
+    pub fn new(vault: &Path, note: &Path) -> Result<Self> {
+        if !vault.is_dir() {
+            return Err(ImagoError::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Vault not found: {}", vault.display()),
+            )));
+        }
+        if note.is_absolute()
+            || note
+                .components()
+                .any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+        {
+            return Err(ImagoError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Note path must be inside the vault: {}", note.display()),
+            )));
+        }
+
+        let note = if note.extension().is_some_and(|ext| ext == "md") {
+            note.to_path_buf()
+        } else {
+            let mut name = note.as_os_str().to_os_string();
+            name.push(".md");
+            PathBuf::from(name)
+        };
+
+        Ok(Self {
+            vault: vault.to_path_buf(),
+            note,
+        })
+    }
+
+    /// Attachment folder configured in the vault, defaulting to the vault root
+    ///
+    /// Mirrors Obsidian's `attachmentFolderPath` semantics: `/` is the vault
+    /// root, `./` and `./sub` are relative to the note's folder, anything else
+    /// is a vault-relative folder.
+    pub fn attachment_dir(&self) -> PathBuf {
+        let config: AppConfig = fs::read_to_string(self.vault.join(".obsidian/app.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+
+        let note_dir = self
+            .note
+            .parent()
+            .map(|p| self.vault.join(p))
+            .unwrap_or_else(|| self.vault.clone());
+
+        match config.attachment_folder_path.as_deref().map(str::trim) {
+            None | Some("") | Some("/") => self.vault.clone(),
+            Some(".") | Some("./") => note_dir,
+            Some(path) if path.starts_with("./") => note_dir.join(&path[2..]),
+            Some(path) => self.vault.join(path.trim_start_matches('/')),
+        }
+    }
+
+    /// Append an embed link for `image` to the note, creating the note if needed
+    pub fn append_embed(&self, image: &Path) -> Result<PathBuf> {
+        let note_path = self.vault.join(&self.note);
+        if let Some(parent) = note_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let link = self.vault_relative(image);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&note_path)?;
+        writeln!(file, "\n![[{}]]", link)?;
+
+        Ok(note_path)
+    }
+
+    /// Vault-relative path with forward slashes, as used by wikilinks
+    fn vault_relative(&self, path: &Path) -> String {
+        let vault = self
+            .vault
+            .canonicalize()
+            .unwrap_or_else(|_| self.vault.clone());
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        match path.strip_prefix(&vault) {
+            Ok(relative) => relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::error::{ImagoError, Result};
+use crate::http;
+use crate::provider::{
+    parse_dimensions, request_id, retry_after, GeneratedImage, GenerationOptions, GenerationResult,
+    Usage,
+};
+use base64::prelude::*;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+const API_URL: &str = "https://api.openai.com/v1/images/generations";
+const DEFAULT_SIZE: &str = "1024x1024";
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+/// JPEG quality requested under `--low-bandwidth`
+const COMPRESSED_QUALITY: u8 = 75;
+
+/// Which flavour of the Images API to talk to
+enum Endpoint {
+    /// api.openai.com with bearer auth
+    OpenAi,
+    /// An Azure OpenAI resource; the model is the deployment name
+    Azure,
+}
+
+/// OpenAI Images API client (also used for Azure OpenAI deployments)
+pub struct OpenAiClient {
+    client: Client,
+    api_key: String,
+    model: String,
+    endpoint: Endpoint,
+}
+
+/// Request payload for image generation
+#[derive(Debug, Serialize)]
+struct ImageGenerationRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    prompt: &'a str,
+    n: u32,
+    size: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_format: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_compression: Option<u8>,
+}
+
+/// Response from image generation
+#[derive(Debug, Deserialize)]
+struct ImageGenerationResponse {
+    data: Vec<ImageData>,
+    usage: Option<ResponseUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    total_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageData {
+    b64_json: Option<String>,
+    revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: String,
+    code: Option<String>,
+}
+
+impl OpenAiClient {
+    /// Create a new OpenAI client
+    pub fn new(api_key: String, model: String, client: Client) -> Self {
+        Self::with_endpoint(api_key, model, Endpoint::OpenAi, client)
+    }
+
+    /// Create a client for an Azure OpenAI image deployment
+    ///
+    /// The resource endpoint and API version are read from
+    /// `AZURE_OPENAI_ENDPOINT` and `AZURE_OPENAI_API_VERSION` when generating.
+    pub fn azure(api_key: String, deployment: String, client: Client) -> Self {
+        Self::with_endpoint(api_key, deployment, Endpoint::Azure, client)
+    }
+
+    fn with_endpoint(api_key: String, model: String, endpoint: Endpoint, client: Client) -> Self {
+        Self {
+            client,
+            api_key,
+            model,
+            endpoint,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Provider name reported in results
+    fn provider(&self) -> &'static str {
+        match self.endpoint {
+            Endpoint::OpenAi => "openai",
+            Endpoint::Azure => "azure",
+        }
+    }
+
+    /// Generate images from a text prompt
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let size = options.size.as_deref().unwrap_or(DEFAULT_SIZE);
+        if parse_dimensions(size).is_none() {
+            return Err(ImagoError::InvalidArgument(format!(
+                "OpenAI expects --size as WIDTHxHEIGHT (e.g. {}), got '{}'",
+                DEFAULT_SIZE, size
+            )));
+        }
+
+        // gpt-image models always return base64; DALL·E defaults to URLs unless
+        // asked. Azure deployment names are arbitrary, so only skip it for
+        // deployments that look like gpt-image.
+        let response_format = match self.endpoint {
+            Endpoint::OpenAi => self.model.starts_with("dall-e"),
+            Endpoint::Azure => !self.model.contains("gpt-image"),
+        }
+        .then_some("b64_json");
+
+        // Only gpt-image models accept an output format; DALL·E is always PNG
+        let compress = options.compressed && self.model.contains("gpt-image");
+
+        let request = ImageGenerationRequest {
+            model: match self.endpoint {
+                Endpoint::OpenAi => Some(self.model.as_str()),
+                Endpoint::Azure => None,
+            },
+            prompt,
+            n: options.count,
+            size,
+            quality: options.quality.as_deref(),
+            response_format,
+            output_format: compress.then_some("jpeg"),
+            output_compression: compress.then_some(COMPRESSED_QUALITY),
+        };
+
+        let http_request = match self.endpoint {
+            Endpoint::OpenAi => self.client.post(API_URL).bearer_auth(&self.api_key),
+            Endpoint::Azure => {
+                let resource = std::env::var("AZURE_OPENAI_ENDPOINT")
+                    .map_err(|_| ImagoError::MissingApiKey("AZURE_OPENAI_ENDPOINT"))?;
+                let api_version = std::env::var("AZURE_OPENAI_API_VERSION")
+                    .unwrap_or_else(|_| DEFAULT_AZURE_API_VERSION.to_string());
+                let url = format!(
+                    "{}/openai/deployments/{}/images/generations?api-version={}",
+                    resource.trim_end_matches('/'),
+                    self.model,
+                    api_version
+                );
+                self.client.post(&url).header("api-key", &self.api_key)
+            }
+        };
+
+        let body = serde_json::to_value(&request)?;
+        let http_request = http_request.json(&body);
+        let reply = http::send(self.provider(), http_request, Some(&body), &self.api_key).await?;
+        let status = reply.status;
+        let retry_after = retry_after(&reply.headers);
+        let request_id = request_id(&reply.headers);
+        let response_text = reply.text();
+
+        if !status.is_success() {
+            let message = match serde_json::from_str::<ErrorResponse>(&response_text) {
+                Ok(err) => match err.error.code {
+                    Some(code) => format!("{} ({})", err.error.message, code),
+                    None => err.error.message,
+                },
+                Err(_) => response_text,
+            };
+            return Err(ImagoError::ApiError {
+                status: status.as_u16(),
+                message,
+                retry_after,
+                request_id,
+            });
+        }
+
+        tracing::info_span!("decode")
+            .in_scope(|| normalize(self.provider(), &self.model, &response_text))
+    }
+}
+
+/// Map a raw Images API response onto a [`GenerationResult`]
+fn normalize(provider: &str, model: &str, response_text: &str) -> Result<GenerationResult> {
+    let parsed: ImageGenerationResponse =
+        serde_json::from_str(response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })?;
+
+    let mut result = GenerationResult::new(provider, model);
+    result.usage = parsed.usage.map(|usage| Usage {
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        total_tokens: usage.total_tokens,
+    });
+
+    for item in parsed.data {
+        let encoded = item.b64_json.ok_or(ImagoError::NoImageData)?;
+        result.images.push(GeneratedImage {
+            data: BASE64_STANDARD.decode(&encoded)?,
+            text: item.revised_prompt,
+            seed: None,
+        });
+    }
+
+    if result.images.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_openai_response() {
+        let result = normalize("openai", "gpt-image-1", &fixture("openai/response.json")).unwrap();
+        assert_golden("openai/response.golden.json", &result);
+    }
+
+    #[test]
+    fn normalizes_azure_response() {
+        let result = normalize("azure", "my-dalle3", &fixture("openai/azure.json")).unwrap();
+        assert_golden("openai/azure.golden.json", &result);
+    }
+}
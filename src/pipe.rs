@@ -0,0 +1,66 @@
+//! `--pipe-to`: hand each saved image to a post-processing command
+//!
+//! The command runs through the shell with the image bytes on stdin, and
+//! `{path}` in it is replaced by the quoted path of the saved file, so both
+//! `pngquant - > small.png` and `optipng {path}` work.
+
+use crate::error::{ImagoError, Result};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Run `command` with the image on stdin; its output goes to stderr instead
+/// of stdout when `quiet_stdout` is set (`-o -`)
+pub async fn run(command: &str, path: &Path, data: &[u8], quiet_stdout: bool) -> Result<()> {
+    let command_line = command.replace("{path}", &quote(&path.to_string_lossy()));
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let stdout = if quiet_stdout {
+        Stdio::from(std::io::stderr())
+    } else {
+        Stdio::inherit()
+    };
+    let mut child = shell
+        .arg(&command_line)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| ImagoError::PipeError(format!("could not run '{}': {}", command, e)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Commands that only use {path} may exit without reading stdin
+        match stdin.write_all(data).await {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(ImagoError::PipeError(format!(
+            "'{}' exited with {} (the image is saved at {})",
+            command,
+            status,
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Quote a path for the platform shell
+fn quote(path: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
@@ -0,0 +1,193 @@
+//! External provider plugins
+//!
+//! `imago --provider foo` runs `imago-provider-foo` from `PATH`, writes one
+//! JSON request to its stdin and reads one JSON response from its stdout.
+//! Anything the plugin writes to stderr is passed through to the user.
+//!
+//! Request:
+//!
+//! ```json
+//! {
+//!   "protocol": 1,
+//!   "prompt": "a red fox",
+//!   "model": "default",
+//!   "api_key": null,
+//!   "options": { "size": "1024x1024", "count": 1, "quality": null,
+//!                "seed": null, "cfg_scale": null, "negative_prompt": null }
+//! }
+//! ```
+//!
+//! Response (`error` set instead of `images` on failure; `kind` may be
+//! `"safety"` to report a content block):
+//!
+//! ```json
+//! {
+//!   "images": [{ "data": "<base64>", "mime_type": "image/png", "seed": 42, "text": null }],
+//!   "error": { "message": "...", "kind": "safety" }
+//! }
+//! ```
+
+use crate::error::{ImagoError, Result};
+use crate::provider::{GeneratedImage, GenerationOptions, GenerationResult};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+const EXECUTABLE_PREFIX: &str = "imago-provider-";
+
+/// Client that delegates generation to an external executable
+pub struct PluginClient {
+    name: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginRequest<'a> {
+    protocol: u32,
+    prompt: &'a str,
+    model: &'a str,
+    api_key: Option<&'a str>,
+    options: &'a GenerationOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginResponse {
+    #[serde(default)]
+    images: Vec<PluginImage>,
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginImage {
+    data: String,
+    seed: Option<u64>,
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginError {
+    message: String,
+    kind: Option<String>,
+}
+
+impl PluginClient {
+    /// Create a client for the `imago-provider-<name>` executable
+    pub fn new(name: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            name,
+            api_key,
+            model,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Executable looked up on `PATH`
+    pub fn executable(&self) -> String {
+        format!("{}{}", EXECUTABLE_PREFIX, self.name)
+    }
+
+    /// Generate images by running the plugin
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let request = PluginRequest {
+            protocol: PROTOCOL_VERSION,
+            prompt,
+            model: &self.model,
+            api_key: self.api_key.as_deref(),
+            options,
+        };
+        let payload = serde_json::to_vec(&request)?;
+
+        let executable = self.executable();
+        let mut child = Command::new(&executable)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => ImagoError::ApiResponseError(format!(
+                    "Unknown provider '{}': no '{}' executable found on PATH",
+                    self.name, executable
+                )),
+                _ => {
+                    ImagoError::ApiResponseError(format!("Failed to launch {}: {}", executable, e))
+                }
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&payload).await?;
+            // Dropping stdin closes the pipe so the plugin sees EOF
+        }
+
+        let output = child.wait_with_output().await?;
+        let response: PluginResponse = serde_json::from_slice(&output.stdout).map_err(|e| {
+            let message = if output.status.success() {
+                format!("Invalid response from {}: {}", executable, e)
+            } else {
+                format!("{} exited with {}", executable, output.status)
+            };
+            ImagoError::ResponseFormatError { message }
+        })?;
+
+        tracing::info_span!("decode").in_scope(|| normalize(&self.name, &self.model, response))
+    }
+}
+
+/// Map a plugin response onto a [`GenerationResult`]
+fn normalize(name: &str, model: &str, response: PluginResponse) -> Result<GenerationResult> {
+    if let Some(error) = response.error {
+        return Err(match error.kind.as_deref() {
+            Some("safety") => ImagoError::SafetyFilter(error.message),
+            _ => ImagoError::ApiResponseError(error.message),
+        });
+    }
+
+    let mut result = GenerationResult::new(name, model);
+    for image in response.images {
+        result.images.push(GeneratedImage {
+            data: BASE64_STANDARD.decode(&image.data)?,
+            text: image.text,
+            seed: image.seed,
+        });
+    }
+
+    if result.images.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_plugin_response() {
+        let response: PluginResponse =
+            serde_json::from_str(&fixture("plugin/response.json")).unwrap();
+        let result = normalize("comfy", "default", response).unwrap();
+        assert_golden("plugin/response.golden.json", &result);
+    }
+
+    #[test]
+    fn safety_error_kind_maps_to_safety_filter() {
+        let response: PluginResponse =
+            serde_json::from_str(r#"{"error":{"message":"nope","kind":"safety"}}"#).unwrap();
+        let err = normalize("comfy", "default", response).unwrap_err();
+        assert!(matches!(err, ImagoError::SafetyFilter(_)));
+    }
+}
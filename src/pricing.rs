@@ -0,0 +1,100 @@
+//! Estimated generation cost
+//!
+//! Built-in list prices (USD) cover the default models; `[pricing."<model>"]`
+//! tables in the config override or extend them when prices change. Token
+//! prices are used when the provider reports token usage, otherwise the
+//! per-image price.
+
+use crate::provider::Usage;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Price of one model; any field may be missing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Price {
+    pub per_image: Option<f64>,
+    /// USD per million input tokens
+    pub input_per_million: Option<f64>,
+    /// USD per million output tokens
+    pub output_per_million: Option<f64>,
+}
+
+impl Price {
+    const fn image(per_image: f64) -> Self {
+        Self {
+            per_image: Some(per_image),
+            input_per_million: None,
+            output_per_million: None,
+        }
+    }
+
+    const fn tokens(per_image: f64, input: f64, output: f64) -> Self {
+        Self {
+            per_image: Some(per_image),
+            input_per_million: Some(input),
+            output_per_million: Some(output),
+        }
+    }
+
+    /// Fields set here win over `base`
+    fn or(self, base: Price) -> Price {
+        Price {
+            per_image: self.per_image.or(base.per_image),
+            input_per_million: self.input_per_million.or(base.input_per_million),
+            output_per_million: self.output_per_million.or(base.output_per_million),
+        }
+    }
+}
+
+/// List prices at the time of writing; override them in the config
+const BUILTIN: [(&str, Price); 11] = [
+    ("gemini-2.5-flash-image", Price::tokens(0.039, 0.30, 30.0)),
+    (
+        "gemini-3-pro-image-preview",
+        Price::tokens(0.134, 2.0, 120.0),
+    ),
+    ("imagen-4.0-generate-001", Price::image(0.04)),
+    ("imagen-4.0-fast-generate-001", Price::image(0.02)),
+    ("imagen-4.0-ultra-generate-001", Price::image(0.06)),
+    ("gpt-image-1", Price::tokens(0.042, 5.0, 40.0)),
+    ("dall-e-3", Price::image(0.04)),
+    ("dall-e-2", Price::image(0.02)),
+    ("black-forest-labs/flux-schnell", Price::image(0.003)),
+    ("fal-ai/flux/schnell", Price::image(0.003)),
+    ("amazon.titan-image-generator-v2:0", Price::image(0.01)),
+];
+
+/// Price for `model`, config overrides layered over the built-in table
+pub fn lookup(overrides: &BTreeMap<String, Price>, model: &str) -> Option<Price> {
+    let builtin = BUILTIN
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map(|(_, price)| *price);
+    match (overrides.get(model), builtin) {
+        (Some(custom), Some(builtin)) => Some(custom.or(builtin)),
+        (custom, builtin) => custom.copied().or(builtin),
+    }
+}
+
+/// Estimated cost in USD of a request that produced `images` images
+pub fn estimate(price: &Price, images: u32, usage: Option<&Usage>) -> Option<f64> {
+    let by_tokens = usage.and_then(|usage| {
+        let output = usage.output_tokens? as f64 * price.output_per_million? / 1_000_000.0;
+        let input = match (usage.input_tokens, price.input_per_million) {
+            (Some(tokens), Some(rate)) => tokens as f64 * rate / 1_000_000.0,
+            _ => 0.0,
+        };
+        Some(input + output)
+    });
+    by_tokens.or_else(|| price.per_image.map(|per_image| per_image * images as f64))
+}
+
+/// `$0.039`, with more digits for fractions of a cent
+pub fn format_usd(amount: f64) -> String {
+    if amount != 0.0 && amount.abs() < 0.01 {
+        format!("${:.4}", amount)
+    } else {
+        format!("${:.3}", amount)
+    }
+}
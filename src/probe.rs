@@ -0,0 +1,192 @@
+//! Cheap authenticated requests that exercise a key without generating an image
+//!
+//! Each provider's lightest endpoint (model lookup or account info) is used,
+//! so probes cost nothing but still go through authentication and the
+//! per-key rate limiter. The same endpoints back `imago auth check`.
+
+use crate::error::{ImagoError, Result};
+use crate::models::{self, ModelInfo};
+use crate::provider::Provider;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+const GEMINI_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
+const STABILITY_ACCOUNT_URL: &str = "https://api.stability.ai/v1/user/account";
+const REPLICATE_ACCOUNT_URL: &str = "https://api.replicate.com/v1/account";
+const STABILITY_ENGINES_URL: &str = "https://api.stability.ai/v1/engines/list";
+
+/// Outcome of one probe
+#[derive(Debug, Clone)]
+pub struct ProbeResponse {
+    pub status: u16,
+    pub latency: Duration,
+    /// Requests-per-minute limit advertised in response headers, if any
+    pub rate_limit: Option<u32>,
+    pub body: String,
+}
+
+impl ProbeResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.status == 429
+    }
+}
+
+/// Whether `provider` has an endpoint suitable for probing
+pub fn supported(provider: &Provider) -> bool {
+    matches!(
+        provider,
+        Provider::Gemini | Provider::OpenAi | Provider::Stability | Provider::Replicate
+    )
+}
+
+/// What a key gives access to, from `imago auth check`
+#[derive(Debug, Clone, Default)]
+pub struct KeyInfo {
+    /// Image models the key can use; empty when the provider has no listing
+    pub models: Vec<String>,
+    /// Account, organization or project the key belongs to, when reported
+    pub account: Option<String>,
+}
+
+/// Validate `api_key` and list what it can reach
+///
+/// Fails with the provider's error when the key is rejected.
+pub async fn check(client: &Client, provider: &Provider, api_key: &str) -> Result<KeyInfo> {
+    match provider {
+        Provider::Gemini => {
+            let models = models::list(client, provider, api_key)
+                .await?
+                .into_iter()
+                .filter(ModelInfo::is_image_model)
+                .map(|model| model.name)
+                .collect();
+            // API keys are not tied to a visible project name
+            Ok(KeyInfo {
+                models,
+                account: None,
+            })
+        }
+        Provider::OpenAi => {
+            let (headers, body) = fetch(client.get(OPENAI_MODELS_URL).bearer_auth(api_key)).await?;
+            let models = names(&body["data"], "id")
+                .into_iter()
+                .filter(|id| id.contains("image") || id.starts_with("dall-e"))
+                .collect();
+            let header = |name: &str| {
+                headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let account = match (header("openai-organization"), header("openai-project")) {
+                (Some(org), Some(project)) => Some(format!("{} / {}", org, project)),
+                (org, project) => org.or(project),
+            };
+            Ok(KeyInfo { models, account })
+        }
+        Provider::Stability => {
+            let (_, account) =
+                fetch(client.get(STABILITY_ACCOUNT_URL).bearer_auth(api_key)).await?;
+            let (_, engines) =
+                fetch(client.get(STABILITY_ENGINES_URL).bearer_auth(api_key)).await?;
+            let organizations = names(&account["organizations"], "name");
+            let account = account["email"].as_str().map(|email| {
+                if organizations.is_empty() {
+                    email.to_string()
+                } else {
+                    format!("{} ({})", email, organizations.join(", "))
+                }
+            });
+            Ok(KeyInfo {
+                models: names(&engines, "id"),
+                account,
+            })
+        }
+        Provider::Replicate => {
+            let (_, body) = fetch(client.get(REPLICATE_ACCOUNT_URL).bearer_auth(api_key)).await?;
+            // Any public model is reachable, so there is nothing useful to list
+            Ok(KeyInfo {
+                models: Vec::new(),
+                account: body["username"].as_str().map(str::to_string),
+            })
+        }
+        other => Err(unsupported(other)),
+    }
+}
+
+/// Send `request`, turning a non-success status into an API error
+async fn fetch(request: RequestBuilder) -> Result<(reqwest::header::HeaderMap, Value)> {
+    let response = request.send().await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let text = crate::http::read_text(response).await?;
+    if !status.is_success() {
+        return Err(ImagoError::ApiError {
+            status: status.as_u16(),
+            message: text.chars().take(300).collect(),
+            retry_after: None,
+            request_id: crate::provider::request_id(&headers),
+        });
+    }
+    Ok((headers, serde_json::from_str(&text)?))
+}
+
+/// String field `key` of every object in a JSON array
+fn names(list: &Value, key: &str) -> Vec<String> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item[key].as_str().map(str::to_string))
+        .collect()
+}
+
+fn unsupported(provider: &Provider) -> ImagoError {
+    ImagoError::InvalidArgument(format!(
+        "{} has no lightweight endpoint to probe (supported: gemini, openai, stability, replicate)",
+        provider
+    ))
+}
+
+/// Send one probe for `model` with `api_key`
+pub async fn send(
+    client: &Client,
+    provider: &Provider,
+    api_key: &str,
+    model: &str,
+) -> Result<ProbeResponse> {
+    let request = match provider {
+        Provider::Gemini => client
+            .get(format!("{}/{}", GEMINI_MODELS_URL, model))
+            .header("x-goog-api-key", api_key),
+        Provider::OpenAi => client
+            .get(format!("{}/{}", OPENAI_MODELS_URL, model))
+            .bearer_auth(api_key),
+        Provider::Stability => client.get(STABILITY_ACCOUNT_URL).bearer_auth(api_key),
+        Provider::Replicate => client.get(REPLICATE_ACCOUNT_URL).bearer_auth(api_key),
+        other => return Err(unsupported(other)),
+    };
+
+    let started = Instant::now();
+    let response = request.send().await?;
+    let latency = started.elapsed();
+    let status = response.status().as_u16();
+    let rate_limit = response
+        .headers()
+        .get("x-ratelimit-limit-requests")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let body = crate::http::read_text(response).await?;
+
+    Ok(ProbeResponse {
+        status,
+        latency,
+        rate_limit,
+        body,
+    })
+}
@@ -0,0 +1,64 @@
+//! Typed progress events for status displays
+//!
+//! Clients and the image handler report what they are doing through a
+//! [`Reporter`]. A reporter built from a channel sender forwards each event;
+//! the default one drops them, so nothing is emitted unless asked for.
+//! Sending never blocks and a dropped receiver is ignored.
+
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Something a generation has started doing
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Progress {
+    /// About to ask `model` for an image
+    ModelAttempted { model: String },
+    /// `model` isn't available, so `next` is tried instead
+    FallbackTriggered { model: String, next: String },
+    /// A request went out to `provider`
+    RequestSent { provider: String },
+    /// Text the model streamed ahead of the image, such as its thoughts
+    PartialText { text: String },
+    /// Response body received so far; `total` when the server announced it
+    BytesReceived { bytes: u64, total: Option<u64> },
+    /// Parsing the response and decoding images
+    Decoding,
+    /// Writing an image to `path`
+    Saving { path: PathBuf },
+}
+
+/// Where progress events go
+#[derive(Debug, Clone, Default)]
+pub struct Reporter(Option<UnboundedSender<Progress>>);
+
+impl Reporter {
+    /// Forward events to `sender`
+    pub fn new(sender: UnboundedSender<Progress>) -> Self {
+        Self(Some(sender))
+    }
+
+    pub fn emit(&self, event: Progress) {
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_events_only_when_built_from_a_sender() {
+        Reporter::default().emit(Progress::Decoding);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let reporter = Reporter::new(sender);
+        reporter.emit(Progress::Decoding);
+        assert_eq!(receiver.try_recv(), Ok(Progress::Decoding));
+
+        drop(receiver);
+        reporter.emit(Progress::Decoding);
+    }
+}
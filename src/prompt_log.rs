@@ -0,0 +1,78 @@
+use crate::error::{ImagoError, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// One audit record, written as a single JSON line
+#[derive(Debug, Serialize)]
+pub struct PromptLogEntry<'a> {
+    pub timestamp: String,
+    pub user: Option<String>,
+    pub cwd: Option<String>,
+    pub provider: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub count: u32,
+    pub size: Option<&'a str>,
+}
+
+impl<'a> PromptLogEntry<'a> {
+    /// Build an entry stamped with the current time, user and working directory
+    pub fn new(provider: &'a str, model: &'a str, prompt: &'a str) -> Self {
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|p| p.display().to_string()),
+            provider,
+            model,
+            prompt,
+            count: 1,
+            size: None,
+        }
+    }
+}
+
+/// Append an entry to the prompt log
+///
+/// The file is opened in append mode for every write and never held open, so
+/// logrotate's default move-and-recreate rotation works without signals.
+/// Any failure is returned so the caller can refuse to send an unlogged request.
+pub fn append(path: &Path, entry: &PromptLogEntry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    append_line(path, &line, "prompt log")
+}
+
+/// Append one newline-terminated `line` to a log file readable only by the
+/// user; `what` names the log in errors
+pub fn append_line(path: &Path, line: &[u8], what: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).map_err(|e| {
+        ImagoError::IoError(std::io::Error::new(
+            e.kind(),
+            format!("Failed to open {} {}: {}", what, path.display(), e),
+        ))
+    })?;
+    // A single write keeps concurrent appends from interleaving within a line
+    file.write_all(line)?;
+    Ok(())
+}
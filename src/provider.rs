@@ -0,0 +1,623 @@
+use crate::bedrock::BedrockClient;
+use crate::error::{ImagoError, Result};
+use crate::fal::FalClient;
+use crate::gemini::GeminiClient;
+use crate::mock::MockClient;
+use crate::openai::OpenAiClient;
+use crate::plugin::PluginClient;
+use crate::progress::Progress;
+use crate::replicate::ReplicateClient;
+use crate::stability::StabilityClient;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Supported image generation backends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provider {
+    /// Google Gemini Image Generation API
+    Gemini,
+    /// OpenAI Images API (gpt-image / DALL·E)
+    OpenAi,
+    /// Stability AI REST API (Stable Diffusion)
+    Stability,
+    /// Replicate hosted models, addressed by `owner/name[:version]`
+    Replicate,
+    /// fal.ai queue API (Flux models)
+    Fal,
+    /// AWS Bedrock (Titan Image, Nova Canvas, Stability on Bedrock)
+    Bedrock,
+    /// Azure OpenAI image deployments, addressed by `--deployment`
+    Azure,
+    /// Placeholder images rendered locally, for demos and offline tests
+    Mock,
+    /// External `imago-provider-<name>` executable found on `PATH`
+    Plugin(String),
+}
+
+/// Names of the providers built into imago
+pub const BUILTIN_PROVIDERS: [&str; 8] = [
+    "gemini",
+    "openai",
+    "stability",
+    "replicate",
+    "fal",
+    "bedrock",
+    "azure",
+    "mock",
+];
+
+impl Provider {
+    /// Provider name as accepted by `--provider`
+    pub fn name(&self) -> &str {
+        match self {
+            Provider::Gemini => "gemini",
+            Provider::OpenAi => "openai",
+            Provider::Stability => "stability",
+            Provider::Replicate => "replicate",
+            Provider::Fal => "fal",
+            Provider::Bedrock => "bedrock",
+            Provider::Azure => "azure",
+            Provider::Mock => "mock",
+            Provider::Plugin(name) => name,
+        }
+    }
+
+    /// Model used when `--model` is not given
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            Provider::Gemini => "gemini-2.5-flash-image",
+            Provider::OpenAi => "gpt-image-1",
+            Provider::Stability => "stable-diffusion-xl-1024-v1-0",
+            Provider::Replicate => "black-forest-labs/flux-schnell",
+            Provider::Fal => "fal-ai/flux/schnell",
+            Provider::Bedrock => "amazon.titan-image-generator-v2:0",
+            Provider::Azure => "dall-e-3",
+            Provider::Mock => "placeholder",
+            Provider::Plugin(_) => "default",
+        }
+    }
+
+    /// Environment variable holding the provider's API key
+    ///
+    /// Plugins manage their own credentials, so a key is optional for them;
+    /// the mock provider needs none.
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            Provider::Gemini => Some("GEMINI_API_KEY"),
+            Provider::OpenAi => Some("OPENAI_API_KEY"),
+            Provider::Stability => Some("STABILITY_API_KEY"),
+            Provider::Replicate => Some("REPLICATE_API_TOKEN"),
+            Provider::Fal => Some("FAL_KEY"),
+            Provider::Bedrock => Some("AWS_ACCESS_KEY_ID"),
+            Provider::Azure => Some("AZURE_OPENAI_API_KEY"),
+            Provider::Mock | Provider::Plugin(_) => None,
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Ok(match name.as_str() {
+            "gemini" => Provider::Gemini,
+            "openai" => Provider::OpenAi,
+            "stability" => Provider::Stability,
+            "replicate" => Provider::Replicate,
+            "fal" => Provider::Fal,
+            "bedrock" => Provider::Bedrock,
+            "azure" => Provider::Azure,
+            "mock" => Provider::Mock,
+            _ if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+            {
+                Provider::Plugin(name)
+            }
+            _ => {
+                return Err(format!(
+                    "invalid provider '{}': use one of {} or a plugin name",
+                    s,
+                    BUILTIN_PROVIDERS.join(", ")
+                ))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+pub const DEFAULT_COOLDOWN_SECS: u64 = 30;
+
+/// Generation options shared by every provider
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationOptions {
+    /// Requested size, either `WIDTHxHEIGHT` or an aspect ratio such as `16:9`
+    pub size: Option<String>,
+    /// Number of images to generate
+    pub count: u32,
+    /// Provider-specific quality hint (e.g. `low`, `high`, `hd`)
+    pub quality: Option<String>,
+    /// Seed for reproducible generations
+    pub seed: Option<u64>,
+    /// How strongly the image should follow the prompt
+    pub cfg_scale: Option<f32>,
+    /// Things the image should not contain
+    pub negative_prompt: Option<String>,
+    /// Prefer smaller, compressed output formats where the provider offers them
+    pub compressed: bool,
+    /// Most requests in flight at once, for providers that need one request per image
+    pub jobs: u32,
+    /// Requests per minute to stay under, spacing per-image requests evenly
+    pub rpm: Option<u32>,
+    /// Consecutive failures of one kind that pause per-image requests (0 never)
+    pub max_failures: u32,
+    /// Pause after `max_failures`, in seconds, before trying once more
+    pub cooldown_secs: u64,
+}
+
+impl Default for GenerationOptions {
+    fn default() -> Self {
+        Self {
+            size: None,
+            count: 1,
+            quality: None,
+            seed: None,
+            cfg_scale: None,
+            negative_prompt: None,
+            compressed: false,
+            jobs: 1,
+            rpm: None,
+            max_failures: DEFAULT_MAX_FAILURES,
+            cooldown_secs: DEFAULT_COOLDOWN_SECS,
+        }
+    }
+}
+
+impl GenerationOptions {
+    /// Options for the `index`th of several one-image requests: the seed is
+    /// offset per request, so `-n 4 --seed 42` gives four different images
+    /// that each reproduce from their own recorded seed
+    pub fn variant(&self, index: u32) -> GenerationOptions {
+        GenerationOptions {
+            seed: self.seed.map(|seed| seed.wrapping_add(u64::from(index))),
+            ..self.clone()
+        }
+    }
+}
+
+/// A single image returned by a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedImage {
+    #[serde(with = "base64_data")]
+    pub data: Vec<u8>,
+    pub text: Option<String>,
+    pub seed: Option<u64>,
+}
+
+/// Safety classification reported by a provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: Option<String>,
+    pub blocked: bool,
+}
+
+/// Token usage reported by a provider
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+}
+
+impl Usage {
+    /// Add another request's usage, keeping counts that only one side reported
+    pub fn add(&mut self, other: &Usage) {
+        fn sum(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            }
+        }
+        self.input_tokens = sum(self.input_tokens, other.input_tokens);
+        self.output_tokens = sum(self.output_tokens, other.output_tokens);
+        self.total_tokens = sum(self.total_tokens, other.total_tokens);
+    }
+}
+
+/// Normalized outcome of a generation, independent of the backend
+///
+/// Every provider maps its response into this shape so saving, metadata,
+/// caching and reporting never need to know which API produced the images.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationResult {
+    pub provider: String,
+    /// Model that actually served the request (may differ after fallback)
+    pub model: String,
+    pub images: Vec<GeneratedImage>,
+    pub text: Option<String>,
+    pub finish_reason: Option<String>,
+    pub safety: Vec<SafetyRating>,
+    pub usage: Option<Usage>,
+    /// Requests of a one-image-per-request batch that failed while others
+    /// came through; the images that did arrive are still saved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+}
+
+impl GenerationResult {
+    /// Empty result for `provider`/`model`, filled in by the backend
+    pub fn new(provider: &str, model: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            images: Vec::new(),
+            text: None,
+            finish_reason: None,
+            safety: Vec::new(),
+            usage: None,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Fold the result of a follow-up request (e.g. one image per call) into this one
+    pub fn merge(&mut self, other: GenerationResult) {
+        self.model = other.model;
+        self.images.extend(other.images);
+        self.failures.extend(other.failures);
+        self.text = other.text.or(self.text.take());
+        self.finish_reason = other.finish_reason.or(self.finish_reason.take());
+        for rating in other.safety {
+            if !self.safety.contains(&rating) {
+                self.safety.push(rating);
+            }
+        }
+        if let Some(other_usage) = other.usage {
+            match &mut self.usage {
+                Some(usage) => usage.add(&other_usage),
+                None => self.usage = Some(other_usage),
+            }
+        }
+    }
+}
+
+/// Serialize image bytes as base64 so results can be cached and snapshotted as JSON
+mod base64_data {
+    use base64::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Provider-agnostic image generation client
+pub enum ImageClient {
+    Gemini(GeminiClient),
+    OpenAi(OpenAiClient),
+    Stability(StabilityClient),
+    Replicate(ReplicateClient),
+    Fal(FalClient),
+    Bedrock(BedrockClient),
+    Mock(MockClient),
+    Plugin(PluginClient),
+}
+
+impl ImageClient {
+    /// Create a client for the given provider, sending requests through `http`
+    pub fn new(provider: Provider, api_key: String, model: String, http: Client) -> Self {
+        match provider {
+            Provider::Plugin(name) => {
+                let api_key = (!api_key.is_empty()).then_some(api_key);
+                ImageClient::Plugin(PluginClient::new(name, api_key, model))
+            }
+            Provider::Gemini => ImageClient::Gemini(GeminiClient::new(api_key, model, http)),
+            Provider::OpenAi => ImageClient::OpenAi(OpenAiClient::new(api_key, model, http)),
+            Provider::Azure => ImageClient::OpenAi(OpenAiClient::azure(api_key, model, http)),
+            Provider::Stability => {
+                ImageClient::Stability(StabilityClient::new(api_key, model, http))
+            }
+            Provider::Replicate => {
+                ImageClient::Replicate(ReplicateClient::new(api_key, model, http))
+            }
+            Provider::Fal => ImageClient::Fal(FalClient::new(api_key, model, http)),
+            Provider::Bedrock => ImageClient::Bedrock(BedrockClient::new(api_key, model, http)),
+            Provider::Mock => ImageClient::Mock(MockClient::new(model)),
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        match self {
+            ImageClient::Gemini(client) => client.model(),
+            ImageClient::OpenAi(client) => client.model(),
+            ImageClient::Stability(client) => client.model(),
+            ImageClient::Replicate(client) => client.model(),
+            ImageClient::Fal(client) => client.model(),
+            ImageClient::Bedrock(client) => client.model(),
+            ImageClient::Mock(client) => client.model(),
+            ImageClient::Plugin(client) => client.model(),
+        }
+    }
+
+    /// Report progress events to `sender` while generating; only Gemini
+    /// reports them so far
+    pub fn with_progress(self, sender: UnboundedSender<Progress>) -> Self {
+        match self {
+            ImageClient::Gemini(client) => ImageClient::Gemini(client.with_progress(sender)),
+            other => other,
+        }
+    }
+
+    /// Generate one or more images from a text prompt
+    pub async fn generate(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        self.generate_all(prompt, options)
+            .await
+            .map_err(|e| match e {
+                ImagoError::NetworkError(e) if e.is_timeout() => ImagoError::Timeout,
+                e => e,
+            })
+    }
+
+    async fn generate_all(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        match self {
+            ImageClient::Gemini(client) => {
+                // Gemini returns a single image per request; run up to `jobs`
+                // at a time, paced to `rpm`, keeping results in request order.
+                // Requests skipped by a tripped breaker come back as `None`.
+                let pacer = &Pacer::new(options.rpm);
+                let breaker = &Breaker::new(
+                    options.max_failures,
+                    Duration::from_secs(options.cooldown_secs),
+                );
+                let results: Vec<Option<Result<GenerationResult>>> = stream::iter(0..options.count)
+                    .map(|i| async move {
+                        if !breaker.ready().await {
+                            return None;
+                        }
+                        pacer.wait().await;
+                        let result = client.generate_image(prompt, &options.variant(i)).await;
+                        breaker.record(result.as_ref().err()).await;
+                        Some(result)
+                    })
+                    .buffered(options.jobs.max(1) as usize)
+                    .collect()
+                    .await;
+
+                if breaker.stopped().await {
+                    let failed = results.iter().filter(|r| matches!(r, Some(Err(_)))).count();
+                    if let Some(last) = results
+                        .into_iter()
+                        .rev()
+                        .find_map(|result| result.and_then(Result::err))
+                    {
+                        return Err(ImagoError::CircuitOpen {
+                            failed: failed as u32,
+                            requested: options.count,
+                            source: Box::new(last),
+                        });
+                    }
+                    return Err(ImagoError::NoImageData);
+                }
+                // Keep every image that arrived, since each was paid for, and
+                // fail only when none did
+                let mut merged: Option<GenerationResult> = None;
+                let mut errors = Vec::new();
+                for result in results.into_iter().flatten() {
+                    match (result, &mut merged) {
+                        (Ok(next), Some(merged)) => merged.merge(next),
+                        (Ok(next), None) => merged = Some(next),
+                        (Err(e), _) => errors.push(e),
+                    }
+                }
+                let Some(mut result) = merged else {
+                    return Err(errors.into_iter().next().unwrap_or(
+                        ImagoError::ResponseFormatError {
+                            message: "No images requested".to_string(),
+                        },
+                    ));
+                };
+                result
+                    .failures
+                    .extend(errors.iter().map(ImagoError::to_string));
+                Ok(result)
+            }
+            ImageClient::OpenAi(client) => client.generate_images(prompt, options).await,
+            ImageClient::Stability(client) => client.generate_images(prompt, options).await,
+            ImageClient::Replicate(client) => client.generate_images(prompt, options).await,
+            ImageClient::Fal(client) => client.generate_images(prompt, options).await,
+            ImageClient::Bedrock(client) => client.generate_images(prompt, options).await,
+            ImageClient::Mock(client) => client.generate_images(prompt, options).await,
+            ImageClient::Plugin(client) => client.generate_images(prompt, options).await,
+        }
+    }
+}
+
+/// Spaces requests evenly to stay under a requests-per-minute limit
+struct Pacer {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl Pacer {
+    fn new(rpm: Option<u32>) -> Self {
+        Self {
+            interval: rpm
+                .filter(|&rpm| rpm > 0)
+                .map(|rpm| Duration::from_secs(60) / rpm),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot
+    async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Stops a batch of per-image requests that keeps failing the same way
+///
+/// After `threshold` consecutive failures of one kind, new requests wait out
+/// the cool-down; if the next ones fail the same way again, the rest of the
+/// batch is skipped.
+struct Breaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    /// Kind of the latest failure and how many in a row
+    kind: Option<&'static str>,
+    consecutive: u32,
+    trips: u32,
+    open_until: Option<Instant>,
+}
+
+impl Breaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Wait out a cool-down; `false` once the batch has been given up on
+    async fn ready(&self) -> bool {
+        let open_until = {
+            let state = self.state.lock().await;
+            if state.trips > 1 {
+                return false;
+            }
+            state.open_until
+        };
+        if let Some(until) = open_until {
+            tokio::time::sleep_until(until).await;
+        }
+        !self.stopped().await
+    }
+
+    async fn record(&self, error: Option<&ImagoError>) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().await;
+        let Some(error) = error else {
+            state.kind = None;
+            state.consecutive = 0;
+            return;
+        };
+        if state.kind == Some(error.kind()) {
+            state.consecutive += 1;
+        } else {
+            state.kind = Some(error.kind());
+            state.consecutive = 1;
+        }
+        if state.consecutive >= self.threshold {
+            state.trips += 1;
+            state.consecutive = 0;
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Whether the breaker tripped again after a cool-down
+    async fn stopped(&self) -> bool {
+        self.state.lock().await.trips > 1
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` size string
+pub fn parse_dimensions(size: &str) -> Option<(u32, u32)> {
+    let (w, h) = size.split_once('x')?;
+    let w = w.trim().parse::<u32>().ok()?;
+    let h = h.trim().parse::<u32>().ok()?;
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+/// Wait requested by a `Retry-After` header, in seconds or as an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Headers providers use to identify a call to their support teams
+const REQUEST_ID_HEADERS: [&str; 5] = [
+    "x-request-id",
+    "request-id",
+    "x-goog-request-id",
+    "x-amzn-requestid",
+    "x-fal-request-id",
+];
+
+/// ID the provider assigned to this call, for support tickets
+pub fn request_id(headers: &HeaderMap) -> Option<String> {
+    REQUEST_ID_HEADERS
+        .iter()
+        .find_map(|name| headers.get(*name)?.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Fetch an image produced by providers that return URLs instead of inline data
+pub async fn download(client: &Client, provider: &str, url: &str) -> Result<Vec<u8>> {
+    let reply = crate::http::send(provider, client.get(url), None, "").await?;
+    if !reply.status.is_success() {
+        return Err(ImagoError::ApiError {
+            status: reply.status.as_u16(),
+            message: format!("Failed to download image from {}", url),
+            retry_after: None,
+            request_id: request_id(&reply.headers),
+        });
+    }
+    Ok(reply.body)
+}
@@ -0,0 +1,293 @@
+use crate::error::{ImagoError, Result};
+use crate::metadata::{self, ImageMetadata};
+use clap::ValueEnum;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const THUMB_DIR: &str = "thumbs";
+const THUMB_SIZE: u32 = 320;
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Output format for a published gallery page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GalleryFormat {
+    Html,
+    Md,
+}
+
+impl GalleryFormat {
+    fn page_name(&self) -> &'static str {
+        match self {
+            GalleryFormat::Html => "index.html",
+            GalleryFormat::Md => "README.md",
+        }
+    }
+}
+
+/// One image in the gallery
+struct GalleryEntry {
+    file_name: String,
+    thumb_name: String,
+    metadata: Option<ImageMetadata>,
+}
+
+/// A written gallery page and the files that could not be included
+pub struct Published {
+    pub page: PathBuf,
+    pub skipped: Vec<(PathBuf, ImagoError)>,
+}
+
+/// Build a static gallery page for every image in `dir`
+///
+/// Thumbnails are written to `<dir>/thumbs/` and the page to `index.html` or
+/// `README.md` inside `dir`, so the whole directory can be shared as-is.
+/// Files that cannot be read or decoded are left out and reported back.
+pub fn publish(dir: &Path, format: GalleryFormat, title: Option<&str>) -> Result<Published> {
+    if !dir.is_dir() {
+        return Err(ImagoError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Not a directory: {}", dir.display()),
+        )));
+    }
+
+    let thumb_dir = dir.join(THUMB_DIR);
+    fs::create_dir_all(&thumb_dir)?;
+
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    for path in list_images(dir)? {
+        match add_entry(&path, &thumb_dir) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => skipped.push((path, e)),
+        }
+    }
+
+    let dir_name = dir
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "imago".to_string());
+    let title = title.map(str::to_string).unwrap_or(dir_name);
+
+    let page = match format {
+        GalleryFormat::Html => render_html(&title, &entries),
+        GalleryFormat::Md => render_markdown(&title, &entries),
+    };
+
+    let page_path = dir.join(format.page_name());
+    fs::write(&page_path, page)?;
+    Ok(Published {
+        page: page_path,
+        skipped,
+    })
+}
+
+/// Write the thumbnail for one image; the thumbnail keeps the full file name
+/// (`a.jpg.png`) so `a.png` and `a.jpg` don't share one
+fn add_entry(path: &Path, thumb_dir: &Path) -> Result<GalleryEntry> {
+    let data = fs::read(path)?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let thumb_name = format!("{}.png", file_name);
+
+    let img = image::load_from_memory(&data)
+        .map_err(|e| ImagoError::ImageError(format!("Failed to load {}: {}", file_name, e)))?;
+    img.thumbnail(THUMB_SIZE, THUMB_SIZE)
+        .save(thumb_dir.join(&thumb_name))
+        .map_err(|e| ImagoError::ImageError(format!("Failed to write thumbnail: {}", e)))?;
+
+    Ok(GalleryEntry {
+        file_name,
+        thumb_name,
+        metadata: metadata::read(&data),
+    })
+}
+
+/// Image files directly inside `dir`, sorted by name, leaving out the
+/// `latest.*` link to one of the others
+fn list_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| !is_latest_link(path))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .map(|ext| {
+                        IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+                    })
+                    .unwrap_or(false)
+        })
+        .collect();
+    images.sort();
+    Ok(images)
+}
+
+fn is_latest_link(path: &Path) -> bool {
+    path.file_stem().is_some_and(|stem| stem == "latest")
+        && fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// Parameter rows shown under each image
+fn parameters(metadata: &ImageMetadata) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("Provider", metadata.provider.clone()),
+        ("Model", metadata.model.clone()),
+    ];
+    if let Some(size) = &metadata.size {
+        params.push(("Size", size.clone()));
+    }
+    if let Some(quality) = &metadata.quality {
+        params.push(("Quality", quality.clone()));
+    }
+    if let Some(seed) = metadata.seed {
+        params.push(("Seed", seed.to_string()));
+    }
+    if let Some(cfg_scale) = metadata.cfg_scale {
+        params.push(("CFG scale", cfg_scale.to_string()));
+    }
+    if let Some(negative_prompt) = &metadata.negative_prompt {
+        params.push(("Negative prompt", negative_prompt.clone()));
+    }
+    if !metadata.created.is_empty() {
+        params.push(("Created", metadata.created.clone()));
+    }
+    params
+}
+
+fn render_markdown(title: &str, entries: &[GalleryEntry]) -> String {
+    let mut out = format!("# {}\n\n", title);
+    let _ = writeln!(out, "_{} images generated with imago_\n", entries.len());
+
+    for entry in entries {
+        let heading = entry
+            .metadata
+            .as_ref()
+            .map(|m| m.prompt.as_str())
+            .unwrap_or(&entry.file_name);
+        let _ = writeln!(out, "### {}\n", heading.replace('\n', " "));
+        let _ = writeln!(
+            out,
+            "[![{}]({}/{})]({})\n",
+            entry.file_name.replace(['[', ']'], ""),
+            THUMB_DIR,
+            encode_url(&entry.thumb_name),
+            encode_url(&entry.file_name)
+        );
+        if let Some(metadata) = &entry.metadata {
+            for (key, value) in parameters(metadata) {
+                let _ = writeln!(out, "- **{}**: `{}`", key, value);
+            }
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn render_html(title: &str, entries: &[GalleryEntry]) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; background: #111; color: #eee; }}
+.grid {{ display: grid; grid-template-columns: repeat(auto-fill, minmax({thumb}px, 1fr)); gap: 1.5rem; }}
+figure {{ margin: 0; background: #1c1c1c; border-radius: 8px; padding: 0.75rem; }}
+img {{ width: 100%; border-radius: 4px; }}
+figcaption {{ margin-top: 0.5rem; font-size: 0.9rem; }}
+dl {{ display: grid; grid-template-columns: auto 1fr; gap: 0.1rem 0.75rem; font-size: 0.8rem; color: #aaa; }}
+dt {{ font-weight: 600; }}
+dd {{ margin: 0; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{count} images generated with imago</p>
+<div class="grid">
+"#,
+        title = escape_html(title),
+        thumb = THUMB_SIZE,
+        count = entries.len()
+    );
+
+    for entry in entries {
+        let file = escape_html(&entry.file_name);
+        let _ = writeln!(out, "<figure>");
+        let _ = writeln!(
+            out,
+            r#"<a href="{href}"><img src="{dir}/{thumb}" alt="{alt}" loading="lazy"></a>"#,
+            href = encode_url(&entry.file_name),
+            dir = THUMB_DIR,
+            thumb = encode_url(&entry.thumb_name),
+            alt = escape_html(
+                entry
+                    .metadata
+                    .as_ref()
+                    .map(|m| m.prompt.as_str())
+                    .unwrap_or(&entry.file_name)
+            ),
+        );
+        match &entry.metadata {
+            Some(metadata) => {
+                let _ = writeln!(
+                    out,
+                    "<figcaption>{}</figcaption>",
+                    escape_html(&metadata.prompt)
+                );
+                let _ = writeln!(out, "<dl>");
+                for (key, value) in parameters(metadata) {
+                    let _ = writeln!(out, "<dt>{}</dt><dd>{}</dd>", key, escape_html(&value));
+                }
+                let _ = writeln!(out, "</dl>");
+            }
+            None => {
+                let _ = writeln!(out, "<figcaption>{}</figcaption>", file);
+            }
+        }
+        let _ = writeln!(out, "</figure>");
+    }
+
+    out.push_str("</div>\n</body>\n</html>\n");
+    out
+}
+
+/// Percent-encode a file name for use as a relative link, so spaces, `#`
+/// and `?` don't end the path early
+fn encode_url(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
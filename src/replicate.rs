@@ -0,0 +1,234 @@
+use crate::error::{ImagoError, Result};
+use crate::http;
+use crate::provider::{
+    download, parse_dimensions, request_id, retry_after, GeneratedImage, GenerationOptions,
+    GenerationResult,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::{Duration, Instant};
+
+const API_BASE_URL: &str = "https://api.replicate.com/v1";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_WAIT: Duration = Duration::from_secs(600);
+/// WebP quality requested under `--low-bandwidth`
+const COMPRESSED_QUALITY: u8 = 75;
+
+/// Replicate predictions API client
+///
+/// The model is a slug such as `black-forest-labs/flux-schnell`, optionally
+/// pinned to a version with `owner/name:version`.
+pub struct ReplicateClient {
+    client: Client,
+    api_key: String,
+    model: String,
+}
+
+/// Prediction state as returned by create and poll calls
+#[derive(Debug, Deserialize)]
+struct Prediction {
+    status: String,
+    output: Option<Value>,
+    error: Option<Value>,
+    urls: PredictionUrls,
+}
+
+#[derive(Debug, Deserialize)]
+struct PredictionUrls {
+    get: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    detail: String,
+}
+
+impl ReplicateClient {
+    /// Create a new Replicate client
+    pub fn new(api_key: String, model: String, client: Client) -> Self {
+        Self {
+            client,
+            api_key,
+            model,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Generate images from a text prompt
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let input = Self::build_input(prompt, options);
+
+        // Versioned slugs go through the generic endpoint, plain slugs use the
+        // model's latest deployment
+        let (url, body) = match self.model.split_once(':') {
+            Some((_, version)) => (
+                format!("{}/predictions", API_BASE_URL),
+                json!({ "version": version, "input": input }),
+            ),
+            None => (
+                format!("{}/models/{}/predictions", API_BASE_URL, self.model),
+                json!({ "input": input }),
+            ),
+        };
+
+        let request = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .json(&body);
+        let mut prediction = self.send(request, Some(&body)).await?;
+
+        let started = Instant::now();
+        while !matches!(
+            prediction.status.as_str(),
+            "succeeded" | "failed" | "canceled"
+        ) {
+            if started.elapsed() > MAX_WAIT {
+                return Err(ImagoError::Timeout);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            let request = self
+                .client
+                .get(&prediction.urls.get)
+                .bearer_auth(&self.api_key);
+            prediction = self.send(request, None).await?;
+        }
+
+        let (mut result, urls) =
+            tracing::info_span!("decode").in_scope(|| normalize(&self.model, prediction))?;
+        for url in urls {
+            result.images.push(GeneratedImage {
+                data: download(&self.client, "replicate", &url).await?,
+                text: None,
+                seed: options.seed,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Map shared options onto the input names common to Replicate image models
+    fn build_input(prompt: &str, options: &GenerationOptions) -> Value {
+        let mut input = Map::new();
+        input.insert("prompt".to_string(), json!(prompt));
+        input.insert("num_outputs".to_string(), json!(options.count));
+        if options.compressed {
+            input.insert("output_format".to_string(), json!("webp"));
+            input.insert("output_quality".to_string(), json!(COMPRESSED_QUALITY));
+        } else {
+            input.insert("output_format".to_string(), json!("png"));
+        }
+
+        if let Some(size) = options.size.as_deref() {
+            match parse_dimensions(size) {
+                Some((width, height)) => {
+                    input.insert("width".to_string(), json!(width));
+                    input.insert("height".to_string(), json!(height));
+                }
+                None => {
+                    input.insert("aspect_ratio".to_string(), json!(size));
+                }
+            }
+        }
+        if let Some(seed) = options.seed {
+            input.insert("seed".to_string(), json!(seed));
+        }
+        if let Some(cfg_scale) = options.cfg_scale {
+            input.insert("guidance_scale".to_string(), json!(cfg_scale));
+        }
+        if let Some(negative) = options.negative_prompt.as_deref() {
+            input.insert("negative_prompt".to_string(), json!(negative));
+        }
+
+        Value::Object(input)
+    }
+
+    /// Send a prediction request and parse the response; `body` is the JSON
+    /// set on `request`, if any
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        body: Option<&Value>,
+    ) -> Result<Prediction> {
+        let reply = http::send("replicate", request, body, &self.api_key).await?;
+        let status = reply.status;
+        let retry_after = retry_after(&reply.headers);
+        let request_id = request_id(&reply.headers);
+        let response_text = reply.text();
+
+        if !status.is_success() {
+            let message = serde_json::from_str::<ErrorResponse>(&response_text)
+                .map(|e| e.detail)
+                .unwrap_or(response_text);
+            return Err(ImagoError::ApiError {
+                status: status.as_u16(),
+                message,
+                retry_after,
+                request_id,
+            });
+        }
+
+        serde_json::from_str(&response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })
+    }
+}
+
+/// Map a finished prediction onto a [`GenerationResult`] plus the output URLs
+/// still to be downloaded into it
+fn normalize(model: &str, prediction: Prediction) -> Result<(GenerationResult, Vec<String>)> {
+    if prediction.status != "succeeded" {
+        let reason = prediction
+            .error
+            .map(|e| match e {
+                Value::String(s) => s,
+                other => other.to_string(),
+            })
+            .unwrap_or_else(|| format!("prediction {}", prediction.status));
+        if reason.to_lowercase().contains("nsfw") {
+            return Err(ImagoError::SafetyFilter(reason));
+        }
+        return Err(ImagoError::ApiResponseError(reason));
+    }
+
+    let urls: Vec<String> = match prediction.output {
+        Some(Value::String(url)) => vec![url],
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    if urls.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    let mut result = GenerationResult::new("replicate", model);
+    result.finish_reason = Some(prediction.status);
+    Ok((result, urls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_succeeded_prediction() {
+        let prediction: Prediction =
+            serde_json::from_str(&fixture("replicate/prediction.json")).unwrap();
+        let normalized = normalize("black-forest-labs/flux-schnell", prediction).unwrap();
+        assert_golden("replicate/prediction.golden.json", &normalized);
+    }
+}
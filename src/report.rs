@@ -0,0 +1,71 @@
+//! `--output-format json`: one JSON object describing the finished run
+//!
+//! Human-readable messages move to stderr, so stdout carries only the report
+//! and scripts can read it with `jq` or any JSON parser.
+
+use crate::error::Result;
+use crate::provider::Usage;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+/// How the result of a generation is printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored banners and previews
+    #[default]
+    Text,
+    /// A single JSON object on stdout
+    Json,
+}
+
+/// Everything a script needs to know about a finished generation
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub prompt: String,
+    pub provider: String,
+    /// Model that served the request, which can differ from the one asked for
+    pub model: String,
+    pub images: Vec<ReportImage>,
+    pub usage: Usage,
+    pub duration_ms: u128,
+    /// Estimated cost in USD, when the model's price is known
+    pub cost_usd: Option<f64>,
+    pub cached: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportImage {
+    pub path: PathBuf,
+    /// Copies saved to additional `-o` destinations
+    pub copies: Vec<PathBuf>,
+    pub bytes: usize,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub seed: Option<u64>,
+}
+
+impl ReportImage {
+    /// Describe a saved image, reading its dimensions from the header
+    pub fn new(path: PathBuf, copies: Vec<PathBuf>, data: &[u8], seed: Option<u64>) -> Self {
+        let dimensions = image::ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        Self {
+            path,
+            copies,
+            bytes: data.len(),
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            seed,
+        }
+    }
+}
+
+/// Print the report on stdout
+pub fn print(report: &Report) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
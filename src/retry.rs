@@ -0,0 +1,102 @@
+//! Retrying transient provider failures
+//!
+//! Server errors, dropped connections and timeouts are retried with
+//! exponential backoff. Each wait is jittered so that parallel runs hitting
+//! the same outage don't retry in lockstep. Rate limits are retried only
+//! when the provider says how long to wait (`Retry-After` or Gemini's
+//! `retryDelay`), and only for waits up to [`MAX_RETRY_AFTER`].
+
+use crate::error::{ImagoError, Result};
+use rand::Rng;
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::time::Duration;
+
+pub const DEFAULT_RETRIES: u32 = 2;
+pub const DEFAULT_DELAY_SECS: f64 = 1.0;
+/// Longest single wait between attempts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// Longest rate-limit wait worth sitting through; beyond it the error is
+/// reported (or the next API key tried) instead
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// How often and how patiently to retry
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub delay: Duration,
+    /// Wait out rate limits that say when to come back
+    pub rate_limits: bool,
+    /// Count down the wait on stderr (verbose mode)
+    pub countdown: bool,
+}
+
+impl RetryPolicy {
+    /// Run `attempt` until it succeeds, fails for good, or retries run out;
+    /// `on_retry` is told about each failure and the wait before the next try
+    pub async fn run<T, F, Fut>(
+        &self,
+        mut attempt: F,
+        mut on_retry: impl FnMut(&ImagoError, Duration, u32),
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            let error = match attempt().await {
+                Err(e) if retry < self.retries => e,
+                result => return result,
+            };
+            retry += 1;
+            let Some(wait) = self.wait(&error, retry) else {
+                return Err(error);
+            };
+            on_retry(&error, wait, retry);
+            if self.countdown && std::io::stderr().is_terminal() {
+                countdown(wait).await;
+            } else {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    /// Wait before retrying after `error`, or `None` if it shouldn't be retried
+    fn wait(&self, error: &ImagoError, retry: u32) -> Option<Duration> {
+        match error {
+            ImagoError::ApiError {
+                status: 429,
+                retry_after,
+                ..
+            } => retry_after.filter(|wait| self.rate_limits && *wait <= MAX_RETRY_AFTER),
+            e if e.is_retryable() => Some(self.backoff(retry)),
+            _ => None,
+        }
+    }
+
+    /// Wait before retry number `retry` (from 1): the base delay doubled each
+    /// time, capped, then scaled by a random factor between 0.5 and 1.5
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(MAX_DELAY);
+        exponential.mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+    }
+}
+
+/// Sleep for `wait`, showing the seconds left on one stderr line
+async fn countdown(wait: Duration) {
+    let mut left = wait;
+    while !left.is_zero() {
+        eprint!("\rRetrying in {}s ", left.as_secs_f64().ceil());
+        let _ = std::io::stderr().flush();
+        let step = left.min(Duration::from_secs(1));
+        tokio::time::sleep(step).await;
+        left -= step;
+    }
+    eprint!("\r\x1b[K");
+}
@@ -0,0 +1,41 @@
+//! `--log-file`: one line per generation, for batch and unattended runs
+//!
+//! Unlike the history database, the log is plain text that can be tailed,
+//! grepped and rotated alongside other service logs. Lines look like
+//!
+//! ```text
+//! 2026-10-16T09:30:12+09:00 succeeded gemini/gemini-2.5-flash-image 12.4s prompt="a red fox" files=fox.png
+//! 2026-10-16T09:31:02+09:00 failed gemini/gemini-2.5-flash-image 0.8s prompt="..." error="API error (status 429): ..."
+//! ```
+
+use crate::error::Result;
+use crate::history::Run;
+use crate::prompt_log;
+use std::path::Path;
+use std::time::Duration;
+
+/// Append the outcome of `run`, which took `duration`
+pub fn append(path: &Path, run: &Run, duration: Duration) -> Result<()> {
+    let mut line = format!(
+        "{} {} {}/{} {:.1}s prompt={:?}",
+        run.timestamp,
+        run.status,
+        run.provider,
+        run.model,
+        duration.as_secs_f64(),
+        run.prompt
+    );
+    if !run.images.is_empty() {
+        let files: Vec<String> = run
+            .images
+            .iter()
+            .map(|image| image.path.display().to_string())
+            .collect();
+        line.push_str(&format!(" files={}", files.join(",")));
+    }
+    if let Some(error) = &run.error {
+        line.push_str(&format!(" error={:?}", error));
+    }
+    line.push('\n');
+    prompt_log::append_line(path, line.as_bytes(), "log file")
+}
@@ -0,0 +1,228 @@
+//! `--record` / `--replay`: provider traffic saved to a session file and
+//! played back later
+//!
+//! Recording adds every exchange that goes through [`crate::http::send`] to a JSON
+//! file, rewritten after each one so an interrupted run keeps what it got.
+//! Replaying answers each request from that file instead of the network: the
+//! first unused exchange with the same provider, method and URL is returned,
+//! so runs with `--jobs` replay even when requests finish in another order.
+//!
+//! API keys are redacted when recording, and a replay runs with
+//! [`REPLAY_KEY`] as its key so URLs that embed the key still match.
+
+use crate::dump::{self, REDACTED};
+use crate::error::{ImagoError, Result};
+use crate::http::Reply;
+use base64::prelude::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// API key used while replaying; it redacts to itself
+pub const REPLAY_KEY: &str = REDACTED;
+
+/// One request and the response it got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exchange {
+    provider: String,
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request: Option<Value>,
+    status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    /// Body as text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    /// Body that isn't UTF-8 (downloaded images), base64 encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response_base64: Option<String>,
+    #[serde(skip)]
+    used: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Session {
+    exchanges: Vec<Exchange>,
+}
+
+enum Mode {
+    Record(PathBuf),
+    Replay,
+}
+
+static STATE: OnceLock<(Mode, Mutex<Session>)> = OnceLock::new();
+
+/// Start recording into `path`, replacing whatever it held
+pub fn record(path: &Path) -> Result<()> {
+    // Written up front so a bad path fails before any request is sent
+    let session = Session::default();
+    write(path, &session)?;
+    let _ = STATE.set((Mode::Record(path.to_path_buf()), Mutex::new(session)));
+    Ok(())
+}
+
+/// Start answering requests from the session saved at `path`
+pub fn replay(path: &Path) -> Result<()> {
+    let invalid = |e: String| ImagoError::SessionError(format!("{}: {}", path.display(), e));
+    let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    let session: Session = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    let _ = STATE.set((Mode::Replay, Mutex::new(session)));
+    Ok(())
+}
+
+/// Check if requests are being answered from a session file
+pub fn replaying() -> bool {
+    matches!(STATE.get(), Some((Mode::Replay, _)))
+}
+
+/// The recorded response to a request, when replaying
+///
+/// `url` must already be redacted, as it was when recorded.
+pub fn answer(provider: &str, method: &str, url: &str) -> Result<Option<Reply>> {
+    let Some((Mode::Replay, session)) = STATE.get() else {
+        return Ok(None);
+    };
+    let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+    let exchange = session
+        .exchanges
+        .iter_mut()
+        .find(|e| !e.used && e.provider == provider && e.method == method && e.url == url)
+        .ok_or_else(|| {
+            ImagoError::SessionError(format!(
+                "no recorded {} response left for {} {}",
+                provider, method, url
+            ))
+        })?;
+    exchange.used = true;
+    exchange.reply().map(Some)
+}
+
+/// Add an exchange to the session file, when recording
+pub fn capture(
+    provider: &str,
+    method: &str,
+    url: &str,
+    request: Option<&Value>,
+    reply: &Reply,
+    secret: &str,
+) -> Result<()> {
+    let Some((Mode::Record(path), session)) = STATE.get() else {
+        return Ok(());
+    };
+    let exchange = Exchange::new(provider, method, url, request, reply, secret);
+    let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+    session.exchanges.push(exchange);
+    write(path, &session)
+}
+
+impl Exchange {
+    fn new(
+        provider: &str,
+        method: &str,
+        url: &str,
+        request: Option<&Value>,
+        reply: &Reply,
+        secret: &str,
+    ) -> Self {
+        let redact = |text: &str| dump::redact(text, secret);
+        let request = request.map(|body| {
+            serde_json::from_str(&redact(&body.to_string())).unwrap_or_else(|_| body.clone())
+        });
+        let headers = reply
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), redact(value.to_str().ok()?))))
+            .collect();
+        let (response, response_base64) = match std::str::from_utf8(&reply.body) {
+            Ok(text) => (Some(redact(text)), None),
+            Err(_) => (None, Some(BASE64_STANDARD.encode(&reply.body))),
+        };
+
+        Self {
+            provider: provider.to_string(),
+            method: method.to_string(),
+            url: redact(url),
+            request,
+            status: reply.status.as_u16(),
+            headers,
+            response,
+            response_base64,
+            used: false,
+        }
+    }
+
+    fn reply(&self) -> Result<Reply> {
+        let invalid = |what: &str| {
+            ImagoError::SessionError(format!("{} {}: invalid {}", self.method, self.url, what))
+        };
+        let status = StatusCode::from_u16(self.status).map_err(|_| invalid("status"))?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+        let body = match (&self.response, &self.response_base64) {
+            (_, Some(encoded)) => BASE64_STANDARD
+                .decode(encoded)
+                .map_err(|_| invalid("response_base64"))?,
+            (Some(text), None) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        };
+        Ok(Reply {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+fn write(path: &Path, session: &Session) -> Result<()> {
+    let json = serde_json::to_string_pretty(session)?;
+    fs::write(path, json)
+        .map_err(|e| ImagoError::SessionError(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_round_trips_with_key_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("req-1"));
+        let reply = Reply {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            headers,
+            body: vec![0x89, b'P', b'N', b'G', 0xff],
+        };
+        let exchange = Exchange::new(
+            "gemini",
+            "POST",
+            "https://example.com/m:generateContent?key=sk-123",
+            None,
+            &reply,
+            "sk-123",
+        );
+        assert_eq!(
+            exchange.url,
+            "https://example.com/m:generateContent?key=REDACTED"
+        );
+
+        let replayed = exchange.reply().unwrap();
+        assert_eq!(replayed.status, reply.status);
+        assert_eq!(replayed.body, reply.body);
+        assert_eq!(replayed.text(), reply.text());
+        assert_eq!(replayed.headers["x-request-id"], "req-1");
+    }
+}
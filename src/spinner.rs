@@ -0,0 +1,158 @@
+//! Spinner shown on stderr while waiting for the provider
+//!
+//! It shows the model and the elapsed time, and follows the client's
+//! [`Progress`] events: fallbacks, text streamed ahead of the image, bytes
+//! received. Anything printed while it spins has to go through
+//! [`Spinner::suspend`] so the line isn't drawn over.
+
+use crate::cache::format_bytes;
+use crate::progress::Progress;
+use crate::provider::ImageClient;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+const TICK: Duration = Duration::from_millis(100);
+/// Longest streamed text shown next to the spinner
+const MAX_TEXT: usize = 60;
+
+pub struct Spinner {
+    bar: Option<ProgressBar>,
+    sender: Option<UnboundedSender<Progress>>,
+}
+
+impl Spinner {
+    /// A spinner for `model`, hidden until [`start`](Self::start); one that
+    /// does nothing when not `enabled`
+    ///
+    /// With a `typical` duration from history it also says how long the
+    /// model usually takes and roughly how much longer to wait.
+    pub fn new(model: &str, typical: Option<Duration>, enabled: bool) -> Self {
+        if !enabled {
+            return Self {
+                bar: None,
+                sender: None,
+            };
+        }
+
+        let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::hidden());
+        let style = ProgressStyle::with_template(
+            "{spinner:.blue} Generating with {prefix} · {elapsed}{typical} {msg}",
+        )
+        .unwrap_or_else(|_| ProgressStyle::default_spinner())
+        .with_key(
+            "typical",
+            move |state: &ProgressState, out: &mut dyn fmt::Write| {
+                if let Some(typical) = typical {
+                    let _ = write!(out, " · {}", eta(typical, state.elapsed()));
+                }
+            },
+        );
+        bar.set_style(style);
+        bar.set_prefix(model.to_string());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let events = bar.clone();
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                match event {
+                    Progress::ModelAttempted { model } => events.set_prefix(model),
+                    Progress::FallbackTriggered { model, .. } => {
+                        events.set_message(format!("· {} unavailable", model))
+                    }
+                    Progress::PartialText { text } => {
+                        events.set_message(format!("· {}", first_line(&text)))
+                    }
+                    Progress::BytesReceived { bytes, .. } => {
+                        events.set_message(format!("· received {}", format_bytes(bytes)))
+                    }
+                    Progress::Decoding => events.set_message("· decoding"),
+                    _ => {}
+                }
+            }
+        });
+
+        Self {
+            bar: Some(bar),
+            sender: Some(sender),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.bar.is_some()
+    }
+
+    /// `client`, reporting its progress to this spinner
+    pub fn follow(&self, client: ImageClient) -> ImageClient {
+        match &self.sender {
+            Some(sender) => client.with_progress(sender.clone()),
+            None => client,
+        }
+    }
+
+    /// Start drawing and counting from zero
+    pub fn start(&self) {
+        if let Some(bar) = &self.bar {
+            bar.set_draw_target(ProgressDrawTarget::stderr());
+            bar.reset_elapsed();
+            bar.enable_steady_tick(TICK);
+        }
+    }
+
+    /// Run `print` with the spinner line cleared
+    pub fn suspend(&self, print: impl FnOnce()) {
+        match &self.bar {
+            Some(bar) => bar.suspend(print),
+            None => print(),
+        }
+    }
+
+    /// Stop and clear the line, so whatever comes next starts clean
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// `usually ~18s`, with the time left while there is some
+fn eta(typical: Duration, elapsed: Duration) -> String {
+    let usually = format!("usually ~{}s", typical.as_secs().max(1));
+    match typical.checked_sub(elapsed) {
+        Some(left) if left.as_secs() > 0 => format!("{}, ~{}s left", usually, left.as_secs()),
+        _ => usually,
+    }
+}
+
+/// First line of `text`, shortened to [`MAX_TEXT`] characters
+fn first_line(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or_default();
+    if line.chars().count() > MAX_TEXT {
+        let short: String = line.chars().take(MAX_TEXT - 1).collect();
+        format!("{}…", short)
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_counts_down_then_keeps_the_typical_time() {
+        let typical = Duration::from_secs(18);
+        assert_eq!(
+            eta(typical, Duration::from_secs(5)),
+            "usually ~18s, ~13s left"
+        );
+        assert_eq!(eta(typical, Duration::from_secs(30)), "usually ~18s");
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::{ImagoError, Result};
+use crate::http;
+use crate::provider::{
+    parse_dimensions, request_id, retry_after, GeneratedImage, GenerationOptions, GenerationResult,
+};
+use base64::prelude::*;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+const API_BASE_URL: &str = "https://api.stability.ai/v1/generation";
+const DEFAULT_DIMENSIONS: (u32, u32) = (1024, 1024);
+
+/// Stability AI REST API client
+pub struct StabilityClient {
+    client: Client,
+    api_key: String,
+    model: String,
+}
+
+/// Request payload for text-to-image
+#[derive(Debug, Serialize)]
+struct TextToImageRequest<'a> {
+    text_prompts: Vec<TextPrompt<'a>>,
+    width: u32,
+    height: u32,
+    samples: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cfg_scale: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TextPrompt<'a> {
+    text: &'a str,
+    weight: f32,
+}
+
+/// Response from text-to-image
+#[derive(Debug, Deserialize)]
+struct TextToImageResponse {
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artifact {
+    base64: Option<String>,
+    seed: Option<u64>,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    name: Option<String>,
+    message: String,
+}
+
+impl StabilityClient {
+    /// Create a new Stability client
+    pub fn new(api_key: String, model: String, client: Client) -> Self {
+        Self {
+            client,
+            api_key,
+            model,
+        }
+    }
+
+    /// Model this client was configured with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Generate images from a text prompt
+    pub async fn generate_images(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<GenerationResult> {
+        let (width, height) = match options.size.as_deref() {
+            Some(size) => parse_dimensions(size).ok_or_else(|| {
+                ImagoError::InvalidArgument(format!(
+                    "Stability expects --size as WIDTHxHEIGHT (e.g. 1024x1024), got '{}'",
+                    size
+                ))
+            })?,
+            None => DEFAULT_DIMENSIONS,
+        };
+
+        // Negative prompts are expressed as a negatively weighted text prompt
+        let mut text_prompts = vec![TextPrompt {
+            text: prompt,
+            weight: 1.0,
+        }];
+        if let Some(negative) = options.negative_prompt.as_deref() {
+            text_prompts.push(TextPrompt {
+                text: negative,
+                weight: -1.0,
+            });
+        }
+
+        let request = TextToImageRequest {
+            text_prompts,
+            width,
+            height,
+            samples: options.count,
+            cfg_scale: options.cfg_scale,
+            seed: options.seed,
+        };
+
+        let url = format!("{}/{}/text-to-image", API_BASE_URL, self.model);
+        let body = serde_json::to_value(&request)?;
+        let http_request = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .json(&body);
+        let reply = http::send("stability", http_request, Some(&body), &self.api_key).await?;
+        let status = reply.status;
+        let retry_after = retry_after(&reply.headers);
+        let request_id = request_id(&reply.headers);
+        let response_text = reply.text();
+
+        if !status.is_success() {
+            let message = match serde_json::from_str::<ErrorResponse>(&response_text) {
+                Ok(err) => match err.name {
+                    Some(name) => format!("{} ({})", err.message, name),
+                    None => err.message,
+                },
+                Err(_) => response_text,
+            };
+            return Err(ImagoError::ApiError {
+                status: status.as_u16(),
+                message,
+                retry_after,
+                request_id,
+            });
+        }
+
+        tracing::info_span!("decode").in_scope(|| normalize(&self.model, &response_text))
+    }
+}
+
+/// Map a raw text-to-image response onto a [`GenerationResult`]
+fn normalize(model: &str, response_text: &str) -> Result<GenerationResult> {
+    let parsed: TextToImageResponse =
+        serde_json::from_str(response_text).map_err(|e| ImagoError::ResponseFormatError {
+            message: format!("Failed to parse API response: {}", e),
+        })?;
+
+    let mut result = GenerationResult::new("stability", model);
+    for artifact in parsed.artifacts {
+        if artifact.finish_reason.as_deref() == Some("CONTENT_FILTERED") {
+            return Err(ImagoError::SafetyFilter(
+                "Image content blocked by safety filters".to_string(),
+            ));
+        }
+        let encoded = artifact.base64.ok_or(ImagoError::NoImageData)?;
+        result.images.push(GeneratedImage {
+            data: BASE64_STANDARD.decode(&encoded)?,
+            text: None,
+            seed: artifact.seed,
+        });
+        result.finish_reason = artifact.finish_reason;
+    }
+
+    if result.images.is_empty() {
+        return Err(ImagoError::NoImageData);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::{assert_golden, fixture};
+
+    #[test]
+    fn normalizes_artifacts() {
+        let result = normalize(
+            "stable-diffusion-xl-1024-v1-0",
+            &fixture("stability/response.json"),
+        )
+        .unwrap();
+        assert_golden("stability/response.golden.json", &result);
+    }
+
+    #[test]
+    fn filtered_artifact_is_a_safety_error() {
+        let body = r#"{"artifacts":[{"base64":null,"seed":1,"finishReason":"CONTENT_FILTERED"}]}"#;
+        let err = normalize("stable-diffusion-xl-1024-v1-0", body).unwrap_err();
+        assert!(matches!(err, ImagoError::SafetyFilter(_)));
+    }
+}
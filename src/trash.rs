@@ -0,0 +1,146 @@
+//! Soft-delete trash for generated images
+//!
+//! Deleted files are moved into `<data dir>/imago/trash/<id>/` together with
+//! an `entry.json` recording where they came from, and can be restored until
+//! the retention window passes.
+
+use crate::error::{ImagoError, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ENTRY_FILE: &str = "entry.json";
+/// How long trashed files are kept before being purged
+pub const RETENTION_DAYS: i64 = 30;
+
+/// A trashed file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: String,
+    pub original_path: PathBuf,
+    pub deleted: String,
+}
+
+impl TrashEntry {
+    fn dir(&self) -> Result<PathBuf> {
+        Ok(trash_dir()?.join(&self.id))
+    }
+
+    /// Location of the file inside the trash
+    fn stored_path(&self) -> Result<PathBuf> {
+        let name = self
+            .original_path
+            .file_name()
+            .ok_or_else(|| ImagoError::TrashError("Trashed entry has no file name".to_string()))?;
+        Ok(self.dir()?.join(name))
+    }
+
+    fn is_expired(&self) -> bool {
+        DateTime::parse_from_rfc3339(&self.deleted).is_ok_and(|deleted| {
+            Local::now().signed_duration_since(deleted).num_days() >= RETENTION_DAYS
+        })
+    }
+}
+
+/// Directory holding one subdirectory per trashed file
+pub fn trash_dir() -> Result<PathBuf> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("imago").join("trash"))
+        .ok_or_else(|| ImagoError::TrashError("No data directory available".to_string()))
+}
+
+/// Move a file into the trash
+pub fn put(path: &Path) -> Result<TrashEntry> {
+    if !path.is_file() {
+        return Err(ImagoError::TrashError(format!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+
+    let deleted = Local::now();
+    let entry = TrashEntry {
+        id: format!(
+            "{}-{:04x}",
+            deleted.format("%Y%m%d%H%M%S"),
+            rand::random::<u16>()
+        ),
+        original_path: fs::canonicalize(path)?,
+        deleted: deleted.to_rfc3339(),
+    };
+
+    let dir = entry.dir()?;
+    fs::create_dir_all(&dir)?;
+    move_file(path, &entry.stored_path()?)?;
+    fs::write(dir.join(ENTRY_FILE), serde_json::to_vec_pretty(&entry)?)?;
+
+    purge_expired()?;
+    Ok(entry)
+}
+
+/// Trashed files, oldest first
+pub fn list() -> Result<Vec<TrashEntry>> {
+    let entries = match fs::read_dir(trash_dir()?) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut trashed: Vec<TrashEntry> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join(ENTRY_FILE)).ok())
+        .filter_map(|text| serde_json::from_str(&text).ok())
+        .collect();
+    trashed.sort_by(|a, b| a.deleted.cmp(&b.deleted));
+    Ok(trashed)
+}
+
+/// Put a trashed file back where it was deleted from
+pub fn restore(id: &str) -> Result<PathBuf> {
+    let entry = list()?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| ImagoError::TrashError(format!("No trashed file with id '{}'", id)))?;
+
+    let target = &entry.original_path;
+    if target.exists() {
+        return Err(ImagoError::TrashError(format!(
+            "{} already exists; move it away before restoring",
+            target.display()
+        )));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    move_file(&entry.stored_path()?, target)?;
+    fs::remove_dir_all(entry.dir()?)?;
+    Ok(entry.original_path)
+}
+
+/// Permanently delete everything in the trash, returning how many files were removed
+pub fn empty() -> Result<usize> {
+    let trashed = list()?;
+    for entry in &trashed {
+        fs::remove_dir_all(entry.dir()?)?;
+    }
+    Ok(trashed.len())
+}
+
+/// Permanently delete entries older than the retention window
+pub fn purge_expired() -> Result<()> {
+    for entry in list()?.into_iter().filter(TrashEntry::is_expired) {
+        fs::remove_dir_all(entry.dir()?)?;
+    }
+    Ok(())
+}
+
+/// Rename, falling back to copy-and-remove across filesystems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)?;
+    Ok(())
+}
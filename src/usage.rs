@@ -0,0 +1,172 @@
+//! Local usage ledger and `imago usage`
+//!
+//! Every generation that reaches a provider appends one JSON line to
+//! `<data dir>/imago/usage.jsonl` with the image count and the token usage the
+//! provider reported. Cache hits cost nothing and are not recorded.
+
+use crate::error::{ImagoError, Result};
+use crate::pricing::{self, Price};
+use crate::provider::{GenerationResult, Usage};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+const LEDGER_FILE: &str = "usage.jsonl";
+
+/// One provider call, written as a single JSON line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub provider: String,
+    pub model: String,
+    pub images: u32,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+}
+
+impl UsageRecord {
+    /// Record for a result just returned by the provider
+    pub fn from_result(result: &GenerationResult) -> Self {
+        let usage = result.usage.clone().unwrap_or_default();
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            provider: result.provider.clone(),
+            model: result.model.clone(),
+            images: result.images.len() as u32,
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+
+    /// Local date of the record, `YYYY-MM-DD`
+    pub fn day(&self) -> &str {
+        self.timestamp.get(..10).unwrap_or(&self.timestamp)
+    }
+
+    /// Estimated cost with today's prices, if the model has one
+    pub fn cost(&self, overrides: &BTreeMap<String, Price>) -> Option<f64> {
+        let usage = Usage {
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+            total_tokens: self.total_tokens,
+        };
+        let price = pricing::lookup(overrides, &self.model)?;
+        pricing::estimate(&price, self.images, Some(&usage))
+    }
+}
+
+/// Totals for one day and model
+#[derive(Debug, Clone, Default)]
+pub struct UsageSummary {
+    pub runs: u32,
+    pub images: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl UsageSummary {
+    /// Fold another summary into this one
+    pub fn merge(&mut self, other: &UsageSummary) {
+        self.runs += other.runs;
+        self.images += other.images;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+
+    fn add(&mut self, record: &UsageRecord) {
+        self.runs += 1;
+        self.images += record.images;
+        self.input_tokens += record.input_tokens.unwrap_or(0);
+        self.output_tokens += record.output_tokens.unwrap_or(0);
+        self.total_tokens += record
+            .total_tokens
+            .or_else(|| Some(record.input_tokens? + record.output_tokens?))
+            .unwrap_or(0);
+    }
+}
+
+pub fn ledger_path() -> Result<PathBuf> {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("imago").join(LEDGER_FILE))
+        .ok_or_else(|| ImagoError::ConfigError("No data directory available".to_string()))
+}
+
+/// Append a record to the ledger
+pub fn record(entry: &UsageRecord) -> Result<()> {
+    let path = ledger_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // A single write keeps concurrent appends from interleaving within a line
+    options.open(&path)?.write_all(&line)?;
+    Ok(())
+}
+
+/// Every record in the ledger; unreadable lines are skipped
+pub fn read_all() -> Result<Vec<UsageRecord>> {
+    let path = ledger_path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Estimated spend of records on or after `since` (`YYYY-MM-DD`); models
+/// without a price count as free
+pub fn spend_since(
+    records: &[UsageRecord],
+    since: &str,
+    overrides: &BTreeMap<String, Price>,
+) -> f64 {
+    records
+        .iter()
+        .filter(|record| record.day() >= since)
+        .filter_map(|record| record.cost(overrides))
+        .sum()
+}
+
+/// First day of the current month, the start of the budget period
+pub fn month_start() -> String {
+    Local::now().format("%Y-%m-01").to_string()
+}
+
+/// Totals per (day, model), oldest day first, for records on or after `since`
+pub fn summarize(
+    records: &[UsageRecord],
+    since: Option<&str>,
+) -> BTreeMap<(String, String), UsageSummary> {
+    let mut summary: BTreeMap<(String, String), UsageSummary> = BTreeMap::new();
+    for record in records {
+        if since.is_some_and(|since| record.day() < since) {
+            continue;
+        }
+        summary
+            .entry((record.day().to_string(), record.model.clone()))
+            .or_default()
+            .add(record);
+    }
+    summary
+}